@@ -0,0 +1,73 @@
+//! Both engines on loop heavy scripts. The tree-walker these replaced cloned the AST as it
+//! ran, to compare against it check out the commit before the arena AST and run the same
+//! programs on its `Engine`, which only has that one engine
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use scorpionest::engine::engine::{Backend, Engine};
+
+/// Loop heavy scripts, each one is run by both engines
+const PROGRAMS: &[(&str, &str)] = &[
+    (
+        "global_loop",
+        "var i = 0;
+var total = 0i64;
+while i < 100000 { { total += i + 1; i += 1; } }",
+    ),
+    (
+        "nested_loops",
+        "fn count(val n: int), int {
+    var total = 0;
+    var i = 0;
+    var j = 0;
+    while i < n {
+        {
+            j = 0;
+            while j < n {
+                {
+                    total += i * j;
+                    j += 1;
+                }
+            }
+            i += 1;
+        }
+    }
+    total
+}
+count(200);",
+    ),
+    (
+        "fib",
+        "fn fib(val n: int), int {
+    var result = n;
+    if n > 1 {
+        {
+            let a = fib(n - 1);
+            let b = fib(n - 2);
+            result = a + b;
+        }
+    }
+    result
+}
+fib(18);",
+    ),
+];
+
+fn loops(c: &mut Criterion) {
+    for (name, source) in PROGRAMS {
+        let mut group = c.benchmark_group(*name);
+        for backend in [Backend::Tree, Backend::Vm] {
+            let program = Engine::new().compile(source).unwrap();
+            group.bench_function(BenchmarkId::from_parameter(format!("{backend:?}")), |b| {
+                b.iter(|| {
+                    let mut engine = Engine::new();
+                    engine.set_backend(backend).capture_output();
+                    engine.run(&program).unwrap();
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, loops);
+criterion_main!(benches);
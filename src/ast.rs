@@ -1,3 +1,4 @@
+pub mod arena;
 pub mod expr;
 pub mod misc;
 pub mod pattern;
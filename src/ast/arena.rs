@@ -0,0 +1,100 @@
+use std::ops::{Index, IndexMut};
+
+use crate::utils::spanned::{Span, Spanned};
+
+use super::{expr::Expr, stmt::Statement};
+
+/// Identifies an expression within the arena of the program it was parsed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExprId(u32);
+
+/// Identifies a statement within the arena of the program it was parsed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StmtId(u32);
+
+impl ExprId {
+    /// Ids count up from zero, so side tables can be plain vectors
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl StmtId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Owns every node of a program, nodes refer to their children by id
+#[derive(Debug, Clone, Default)]
+pub struct Arena {
+    exprs: Vec<Spanned<Expr>>,
+    stmts: Vec<Spanned<Statement>>,
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alloc_expr(&mut self, expr: Expr, span: Span) -> ExprId {
+        self.exprs.push(Spanned(expr, span));
+        ExprId(self.exprs.len() as u32 - 1)
+    }
+
+    pub fn alloc_stmt(&mut self, stmt: Statement, span: Span) -> StmtId {
+        self.stmts.push(Spanned(stmt, span));
+        StmtId(self.stmts.len() as u32 - 1)
+    }
+
+    /// Widens the span of an expression, parentheses around it are part of it
+    pub fn respan(&mut self, id: ExprId, span: Span) {
+        self.exprs[id.index()].1 = span;
+    }
+
+    /// How many expressions were allocated, every `ExprId` is below this
+    pub fn expr_count(&self) -> usize {
+        self.exprs.len()
+    }
+
+    /// How many statements were allocated, every `StmtId` is below this
+    pub fn stmt_count(&self) -> usize {
+        self.stmts.len()
+    }
+
+    pub fn expr_ids(&self) -> impl Iterator<Item = ExprId> {
+        (0..self.exprs.len() as u32).map(ExprId)
+    }
+
+    pub fn stmt_ids(&self) -> impl Iterator<Item = StmtId> {
+        (0..self.stmts.len() as u32).map(StmtId)
+    }
+}
+
+impl Index<ExprId> for Arena {
+    type Output = Spanned<Expr>;
+
+    fn index(&self, id: ExprId) -> &Self::Output {
+        &self.exprs[id.index()]
+    }
+}
+
+impl Index<StmtId> for Arena {
+    type Output = Spanned<Statement>;
+
+    fn index(&self, id: StmtId) -> &Self::Output {
+        &self.stmts[id.index()]
+    }
+}
+
+impl IndexMut<ExprId> for Arena {
+    fn index_mut(&mut self, id: ExprId) -> &mut Self::Output {
+        &mut self.exprs[id.index()]
+    }
+}
+
+impl IndexMut<StmtId> for Arena {
+    fn index_mut(&mut self, id: StmtId) -> &mut Self::Output {
+        &mut self.stmts[id.index()]
+    }
+}
@@ -1,18 +1,23 @@
-use crate::utils::{object::Object, spanned::Spanned};
+use lasso::Spur;
 
-use super::misc::{BinaryOp, UnaryOp};
+use crate::utils::{object::Object, spanned::Spanned, valtype::Type};
+
+use super::{
+    arena::{ExprId, StmtId},
+    misc::{BinaryOp, FuncParameter, UnaryOp},
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Binary {
-    pub left: Box<Spanned<Expr>>,
+    pub left: ExprId,
     pub operator: Spanned<BinaryOp>,
-    pub right: Box<Spanned<Expr>>,
+    pub right: ExprId,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Unary {
     pub operator: Spanned<UnaryOp>,
-    pub right: Box<Spanned<Expr>>,
+    pub right: ExprId,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -20,6 +25,16 @@ pub struct Literal {
     pub value: Spanned<Object>,
 }
 
+/// An anonymous function, it copies the locals it uses from the functions around it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lambda {
+    pub parameters: Option<Vec<FuncParameter>>,
+    pub return_type: Option<Spanned<Type>>,
+    /// `fn(..) -> value` has a single expression statement as its body
+    pub statments: Vec<StmtId>,
+    pub arrow: bool,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Binary(Binary),
@@ -29,12 +44,22 @@ pub enum Expr {
         name: Spanned<u64>,
     },
     TenaryIfStmt {
-        condition: Box<Spanned<Expr>>,
-        value: Box<Spanned<Expr>>,
-        else_value: Box<Spanned<Expr>>,
+        condition: ExprId,
+        value: ExprId,
+        else_value: ExprId,
     },
     FunctionCall {
-        func_name: Box<Spanned<u64>>,
-        arguments: Option<Vec<Expr>>,
+        callee: ExprId,
+        arguments: Option<Vec<ExprId>>,
+        /// `receiver?.callee(..)`, the receiver is the first argument and the call is
+        /// skipped for `null` when the receiver is null
+        safe: bool,
+    },
+    Lambda(Lambda),
+    /// `"a {b:>4} c"`, `template` is the string with a `{}` or `{:spec}` where each
+    /// argument goes
+    Interpolation {
+        template: Spur,
+        arguments: Vec<ExprId>,
     },
 }
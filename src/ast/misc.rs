@@ -1,4 +1,4 @@
-use crate::utils::{spanned::Spanned, valtype::Type};
+use crate::utils::{object::Object, spanned::Spanned, valtype::Type};
 
 #[derive(Debug, Clone, Copy)]
 pub enum DeclarationType {
@@ -6,7 +6,7 @@ pub enum DeclarationType {
     Immutable,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ParamType {
     Reference,
     Value,
@@ -14,7 +14,7 @@ pub enum ParamType {
     Output,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ParamRestrictor {
     Mutable,
     Constant,
@@ -34,15 +34,19 @@ pub enum BinaryOp {
     NotEqual,
     And,
     Or,
+    /// `a ?? b`, `b` is only evaluated when `a` is null
+    Coalesce,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum AssignOp {
     Add,
     Sub,
     Mul,
     Div,
     Set,
+    /// `?=`, assigns only when the variable is null
+    SetIfNull,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -51,10 +55,77 @@ pub enum UnaryOp {
     Not,
 }
 
-#[derive(Debug, Clone)]
+/// `@name` or `@name(arguments)` before a declaration, the arguments are literals
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub name: Spanned<String>,
+    pub arguments: Vec<Spanned<Object>>,
+}
+
+/// What an `extern` function's parameters and result are in C
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CType {
+    /// `int`
+    Int,
+    /// `long`
+    Long,
+    /// `double`
+    Float,
+    /// An `int` that's 0 or 1
+    Bool,
+    /// `char *`, ending at the first 0 byte
+    CStr,
+    /// `void *`, an address scripts pass around as a `long`
+    Ptr,
+}
+
+impl CType {
+    pub const NAMES: &'static [&'static str] = &["int", "long", "float", "bool", "cstr", "ptr"];
+
+    pub fn from_name(name: &str) -> Option<CType> {
+        Some(match name {
+            "int" => CType::Int,
+            "long" => CType::Long,
+            "float" => CType::Float,
+            "bool" => CType::Bool,
+            "cstr" => CType::CStr,
+            "ptr" => CType::Ptr,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CType::Int => "int",
+            CType::Long => "long",
+            CType::Float => "float",
+            CType::Bool => "bool",
+            CType::CStr => "cstr",
+            CType::Ptr => "ptr",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct FuncParameter {
     pub param_type: Box<Spanned<ParamType>>,
     pub param_value_name: Box<Spanned<u64>>,
     pub param_restrictor: Option<Spanned<ParamRestrictor>>,
     pub param_value_type: Box<Spanned<Type>>,
 }
+
+impl FuncParameter {
+    /// `ref` and `out` parameters alias a variable of the caller instead of copying a value
+    pub fn is_alias(&self) -> bool {
+        matches!(self.param_type.0, ParamType::Reference | ParamType::Output)
+    }
+
+    /// Whether the body can assign to the parameter, `out` parameters have to be assigned
+    pub fn is_mutable(&self) -> bool {
+        self.param_type.0 == ParamType::Output
+            || matches!(
+                self.param_restrictor.map(|r| r.0),
+                Some(ParamRestrictor::Mutable)
+            )
+    }
+}
@@ -1,57 +1,172 @@
-use std::collections::HashMap;
+use std::{path::PathBuf, rc::Rc};
 
-use crate::utils::{spanned::Spanned, valtype::Type};
+use crate::utils::{
+    spanned::{concat_span, FileId, Spanned},
+    valtype::Type,
+};
 
-use super::{expr::Expr, misc::*, pattern::Pattern};
+use super::{
+    arena::{Arena, ExprId, StmtId},
+    misc::*,
+    pattern::Pattern,
+};
 
 #[derive(Debug, Clone)]
 pub struct Assign {
     pub name: Spanned<u64>,
     pub operator: Spanned<AssignOp>,
-    pub value: Box<Spanned<Expr>>,
+    pub value: ExprId,
 }
 
 #[derive(Debug, Clone)]
 pub struct MatchStmt {
-    pub predicate: Box<Spanned<Expr>>,
-    pub then_branches: Spanned<Vec<(Pattern, Statement)>>,
+    pub predicate: ExprId,
+    pub then_branches: Spanned<Vec<(Pattern, StmtId)>>,
+}
+
+/// `extern "C" in "libm.so.6" fn cos(x: float) -> float;`, a function from a shared library.
+/// Only fixed parameter lists of scalars are supported, each passed in a register
+#[derive(Debug, Clone)]
+pub struct ExternDeclaration {
+    pub docs: Option<Spanned<String>>,
+    pub attributes: Vec<Attribute>,
+    pub public: bool,
+    /// The shared object to load, `None` for the C library the interpreter itself uses
+    pub library: Option<Spanned<String>>,
+    pub name: Spanned<u64>,
+    /// What the library calls the function, modules rename `name` but not this
+    pub symbol: String,
+    pub parameters: Vec<(Spanned<u64>, Spanned<CType>)>,
+    /// `None` for functions returning `void`
+    pub return_type: Option<Spanned<CType>>,
+}
+
+impl ExternDeclaration {
+    /// How many non-float parameters an extern function can take
+    pub const INT_PARAMETERS: usize = 6;
+    /// How many float parameters an extern function can take
+    pub const FLOAT_PARAMETERS: usize = 8;
 }
 
 #[derive(Debug, Clone)]
 pub enum Statement {
     Error,
     Empty,
-    Test(Expr),
+    Test(ExprId),
+    /// `test "name" { ... }`, skipped when a script runs and run by the test runner instead
+    TestDeclaration {
+        name: Spanned<String>,
+        /// `@ignore` tests only run when ignored tests are asked for
+        attributes: Vec<Attribute>,
+        statments: Vec<StmtId>,
+    },
     Assign(Assign),
     Block {
-        statments: Vec<Spanned<Statement>>,
+        statments: Vec<StmtId>,
     },
     Expression {
-        expr: Box<Spanned<Expr>>,
+        expr: ExprId,
     },
     Declaration {
+        docs: Option<Spanned<String>>,
+        /// Marked `pub`, modules only export what's public
+        public: bool,
         declaration_type: DeclarationType,
         name: Spanned<u64>,
         manual_type: Option<Spanned<Type>>,
-        value: Box<Spanned<Expr>>,
+        value: ExprId,
     },
     FuncDeclaration {
+        docs: Option<Spanned<String>>,
+        inner_docs: Option<Spanned<String>>,
+        attributes: Vec<Attribute>,
+        public: bool,
         name: Spanned<u64>,
         parameters: Option<Vec<FuncParameter>>,
         return_type: Option<Spanned<Type>>,
-        statments: Vec<Spanned<Statement>>,
+        statments: Vec<StmtId>,
     },
     IfStmt {
-        condition: Box<Spanned<Expr>>,
-        then_branch: Box<Spanned<Statement>>,
-        else_branch: Option<Box<Spanned<Statement>>>,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
     },
     MatchStmt(MatchStmt),
     WhileStmt {
-        condition: Box<Spanned<Expr>>,
-        then_branch: Box<Spanned<Statement>>,
+        condition: ExprId,
+        then_branch: StmtId,
     },
     Defer {
-        defered_statment: Box<Spanned<Statement>>,
+        defered_statment: StmtId,
+    },
+    /// `with name = open(..) { ... }`, the exit hook of the function making the value
+    /// runs however the body ends
+    With {
+        name: Spanned<u64>,
+        value: ExprId,
+        statments: Vec<StmtId>,
+    },
+    Extern(ExternDeclaration),
+    /// `use a::b;` or `use a::b::{c, d};`, only allowed at the top of a file
+    Use {
+        path: Vec<Spanned<u64>>,
+        items: Option<Vec<Spanned<u64>>>,
     },
 }
+
+impl Statement {
+    /// The `///` and `//!` comments documenting this statement, if it can hold any
+    pub fn docs(&self) -> Option<Spanned<String>> {
+        match self {
+            Statement::Declaration { docs, .. } => docs.clone(),
+            Statement::Extern(declaration) => declaration.docs.clone(),
+            Statement::FuncDeclaration {
+                docs, inner_docs, ..
+            } => match (docs, inner_docs) {
+                (Some(outer), Some(inner)) => Some(Spanned(
+                    format!("{}\n{}", outer.0, inner.0),
+                    concat_span(outer.1, inner.1),
+                )),
+                (outer, inner) => outer.clone().or(inner.clone()),
+            },
+            _ => None,
+        }
+    }
+
+    /// The `@` attributes on this statement, if it can hold any
+    pub fn attributes(&self) -> &[Attribute] {
+        match self {
+            Statement::FuncDeclaration { attributes, .. }
+            | Statement::TestDeclaration { attributes, .. }
+            | Statement::Extern(ExternDeclaration { attributes, .. }) => attributes,
+            _ => &[],
+        }
+    }
+
+    /// The attribute called `name`, which is written without the `@`
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes()
+            .iter()
+            .find(|attribute| attribute.name.0 == name)
+    }
+}
+
+/// A whole source file, `docs` holds the `//!` comments at its top,
+/// `arena` owns the statements and everything below them
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub docs: Option<Spanned<String>>,
+    pub statements: Vec<StmtId>,
+    pub arena: Rc<Arena>,
+    pub file: FileId,
+    /// Every module the program imports, directly or not, each before the ones importing it
+    pub modules: Vec<Module>,
+}
+
+/// A file loaded by a `use`, its globals are renamed so they can't clash with other files
+#[derive(Debug, Clone)]
+pub struct Module {
+    /// Canonical, a module runs once per engine however many programs import it
+    pub path: PathBuf,
+    pub program: Program,
+}
@@ -0,0 +1,8 @@
+pub mod assignment;
+pub mod attributes;
+pub mod checker;
+pub mod mutability;
+pub mod names;
+pub mod nulls;
+#[cfg(test)]
+mod test;
@@ -0,0 +1,405 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        arena::{Arena, ExprId, StmtId},
+        expr::Expr,
+        misc::{AssignOp, FuncParameter, ParamType},
+        pattern::Pattern,
+        stmt::{Program, Statement},
+    },
+    utils::spanned::Span,
+};
+
+use super::{
+    checker::{nested, Diagnostic},
+    names::{Declaration, Owner, Resolution},
+};
+
+/// Definite assignment, `out` parameters have to be assigned on every path before the
+/// function returns and nothing can be read on a path that might not have assigned it
+pub fn check(input: &str, program: &Program, resolution: &Resolution) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        input,
+        arena: &program.arena,
+        resolution,
+        functions: vec![],
+        diagnostics: vec![],
+    };
+    checker.body(None, &[], &program.statements);
+    checker.diagnostics
+}
+
+/// A path through the code on which a variable isn't assigned
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Missing {
+    span: Span,
+    note: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Unassigned,
+    Assigned,
+    /// Assigned on some of the paths leading here but not on `Missing`
+    Maybe(Missing),
+}
+
+/// What's been assigned at a point in the code, declarations that aren't in here are
+/// unassigned
+#[derive(Debug, Clone, Default)]
+struct State(HashMap<Declaration, Status>);
+
+impl State {
+    fn get(&self, declaration: Declaration) -> Status {
+        self.0
+            .get(&declaration)
+            .copied()
+            .unwrap_or(Status::Unassigned)
+    }
+
+    fn assign(&mut self, declaration: Declaration) {
+        self.0.insert(declaration, Status::Assigned);
+    }
+
+    /// Where two paths meet, `path` and `other_path` describe each of them
+    fn join(&self, path: Missing, other: &State, other_path: Missing) -> State {
+        let names: HashSet<Declaration> = self.0.keys().chain(other.0.keys()).copied().collect();
+        let joined = names.into_iter().map(|name| {
+            let status = match (self.get(name), other.get(name)) {
+                (Status::Maybe(missing), _) | (_, Status::Maybe(missing)) => Status::Maybe(missing),
+                (Status::Unassigned, Status::Assigned) => Status::Maybe(path),
+                (Status::Assigned, Status::Unassigned) => Status::Maybe(other_path),
+                (status, _) => status,
+            };
+            (name, status)
+        });
+        State(joined.collect())
+    }
+}
+
+/// The function, lambda or script being checked
+struct Function {
+    /// The `out` parameters and their names
+    outputs: Vec<(Declaration, Span)>,
+    /// The first declaration of each name in the body, by every declaration of it. They
+    /// share a variable when the body runs
+    shared: HashMap<StmtId, StmtId>,
+    /// Deferred statements, along with the `defer` when only some paths reach it
+    defers: Vec<(StmtId, Option<Span>)>,
+    /// How many branches or loop bodies deep the statement being checked is
+    branches: usize,
+}
+
+struct Checker<'a> {
+    input: &'a str,
+    arena: &'a Arena,
+    resolution: &'a Resolution,
+    /// Innermost last
+    functions: Vec<Function>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn text(&self, span: Span) -> &str {
+        self.input.get(span.into_range()).unwrap_or_default()
+    }
+
+    fn function(&mut self) -> &mut Function {
+        self.functions
+            .last_mut()
+            .expect("there's always a function")
+    }
+
+    /// Checks a body on its own, it doesn't see what the code around it assigned
+    fn body(&mut self, owner: Option<Owner>, parameters: &[FuncParameter], statments: &[StmtId]) {
+        let mut first = HashMap::new();
+        let mut shared = HashMap::new();
+        declarations(self.arena, statments, &mut |name, stmt| {
+            shared.insert(stmt, *first.entry(name).or_insert(stmt));
+        });
+        self.functions.push(Function {
+            outputs: owner
+                .into_iter()
+                .flat_map(|owner| {
+                    parameters
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, param)| param.param_type.0 == ParamType::Output)
+                        .map(move |(index, param)| {
+                            (
+                                Declaration::Parameter { owner, index },
+                                param.param_value_name.1,
+                            )
+                        })
+                })
+                .collect(),
+            shared,
+            defers: vec![],
+            branches: 0,
+        });
+        let mut state = State::default();
+        self.statements(statments, &mut state);
+        // Deferred statements run once the body is done, the last one first. The ones only
+        // some paths queue might not run at all
+        while let Some((stmt, conditional)) = self.function().defers.pop() {
+            let Some(span) = conditional else {
+                self.statement(stmt, &mut state);
+                continue;
+            };
+            let mut ran = state.clone();
+            self.statement(stmt, &mut ran);
+            let skipped = Missing {
+                span,
+                note: "this `defer` might not be reached",
+            };
+            state = ran.join(skipped, &state, skipped);
+        }
+        let function = self.functions.pop().expect("the function was just pushed");
+        for (output, span) in function.outputs {
+            let name = self.text(span);
+            let diagnostic = match state.get(output) {
+                Status::Assigned => continue,
+                Status::Unassigned => {
+                    Diagnostic::error(format!("`out` parameter `{name}` is never assigned"), span)
+                }
+                Status::Maybe(missing) => Diagnostic::error(
+                    format!("`out` parameter `{name}` isn't assigned on every path"),
+                    span,
+                )
+                .with_label(missing.span, missing.note),
+            };
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    /// Whether `declaration` is one of the `out` parameters of the innermost function
+    fn output(&self, declaration: Declaration) -> bool {
+        let function = self.functions.last().expect("there's always a function");
+        function
+            .outputs
+            .iter()
+            .any(|(output, _)| *output == declaration)
+    }
+
+    /// The variable `declaration` declares in the innermost function
+    fn variable(&self, declaration: Declaration) -> Declaration {
+        let function = self.functions.last().expect("there's always a function");
+        match declaration {
+            Declaration::Variable(stmt) => {
+                Declaration::Variable(function.shared.get(&stmt).copied().unwrap_or(stmt))
+            }
+            declaration => declaration,
+        }
+    }
+
+    fn read(&mut self, declaration: Option<Declaration>, span: Span, state: &State) {
+        let Some(declaration) = declaration.map(|d| self.variable(d)) else {
+            return;
+        };
+        let text = self.text(span);
+        let diagnostic = match state.get(declaration) {
+            Status::Assigned => return,
+            Status::Unassigned if self.output(declaration) => {
+                Diagnostic::error(format!("`{text}` is read before it's assigned"), span)
+            }
+            // Declarations of the functions around this one, and ones this body can't
+            // have skipped
+            Status::Unassigned => return,
+            Status::Maybe(missing) => {
+                Diagnostic::error(format!("`{text}` might not be assigned here"), span)
+                    .with_label(missing.span, missing.note)
+            }
+        };
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn statements(&mut self, statements: &[StmtId], state: &mut State) {
+        for stmt in statements {
+            self.statement(*stmt, state);
+        }
+    }
+
+    /// A statement only some paths run
+    fn branch(&mut self, id: StmtId, state: &mut State) {
+        self.function().branches += 1;
+        self.statement(id, state);
+        self.function().branches -= 1;
+    }
+
+    fn statement(&mut self, id: StmtId, state: &mut State) {
+        let arena = self.arena;
+        match &arena[id].0 {
+            Statement::Error | Statement::Empty | Statement::Use { .. } | Statement::Extern(_) => {}
+            Statement::Test(expr) | Statement::Expression { expr } => self.expr(*expr, state),
+            // Tests run on their own after the script's top level
+            Statement::TestDeclaration { statments, .. } => {
+                self.statements(statments, &mut state.clone())
+            }
+            Statement::Assign(assign) => {
+                self.expr(assign.value, state);
+                let declaration = self.resolution.stmt(id);
+                if assign.operator.0 != AssignOp::Set {
+                    self.read(declaration, assign.name.1, state);
+                }
+                if let Some(declaration) = declaration.filter(|d| self.output(*d)) {
+                    state.assign(declaration);
+                }
+            }
+            Statement::Block { statments } => self.statements(statments, state),
+            Statement::Declaration { value, .. } => {
+                self.expr(*value, state);
+                state.assign(self.variable(Declaration::Variable(id)));
+            }
+            Statement::FuncDeclaration {
+                parameters,
+                statments,
+                ..
+            } => self.body(
+                Some(Owner::Function(id)),
+                parameters.as_deref().unwrap_or_default(),
+                statments,
+            ),
+            Statement::IfStmt {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(*condition, state);
+                let mut then_state = state.clone();
+                self.branch(*then_branch, &mut then_state);
+                let then_path = Missing {
+                    span: arena[*then_branch].1,
+                    note: "this branch doesn't assign it",
+                };
+                *state = match else_branch {
+                    Some(else_branch) => {
+                        let mut else_state = state.clone();
+                        self.branch(*else_branch, &mut else_state);
+                        let else_path = Missing {
+                            span: arena[*else_branch].1,
+                            note: "this branch doesn't assign it",
+                        };
+                        then_state.join(then_path, &else_state, else_path)
+                    }
+                    None => {
+                        let skipped = Missing {
+                            span: arena[*condition].1,
+                            note: "it isn't assigned when this is false",
+                        };
+                        then_state.join(then_path, state, skipped)
+                    }
+                };
+            }
+            Statement::MatchStmt(match_stmt) => {
+                self.expr(match_stmt.predicate, state);
+                let mut joined: Option<(State, Missing)> = None;
+                for (_, arm) in &match_stmt.then_branches.0 {
+                    let mut arm_state = state.clone();
+                    self.branch(*arm, &mut arm_state);
+                    let path = Missing {
+                        span: arena[*arm].1,
+                        note: "this arm doesn't assign it",
+                    };
+                    joined = Some(match joined {
+                        Some((joined, first)) => (joined.join(first, &arm_state, path), first),
+                        None => (arm_state, path),
+                    });
+                }
+                let exhaustive = match_stmt
+                    .then_branches
+                    .0
+                    .iter()
+                    .any(|(pattern, _)| matches!(pattern, Pattern::WildCard));
+                *state = match joined {
+                    Some((joined, _)) if exhaustive => joined,
+                    Some((joined, first)) => {
+                        let unmatched = Missing {
+                            span: arena[match_stmt.predicate].1,
+                            note: "it isn't assigned when no arm matches",
+                        };
+                        joined.join(first, state, unmatched)
+                    }
+                    None => state.clone(),
+                };
+            }
+            Statement::WhileStmt {
+                condition,
+                then_branch,
+            } => {
+                self.expr(*condition, state);
+                let mut body_state = state.clone();
+                self.branch(*then_branch, &mut body_state);
+                let skipped = Missing {
+                    span: arena[*condition].1,
+                    note: "it isn't assigned if the loop doesn't run",
+                };
+                *state = body_state.join(skipped, state, skipped);
+            }
+            Statement::With {
+                value, statments, ..
+            } => {
+                self.expr(*value, state);
+                state.assign(self.variable(Declaration::Variable(id)));
+                self.statements(statments, state);
+            }
+            Statement::Defer { defered_statment } => {
+                let span = arena[id].1;
+                let function = self.function();
+                let conditional = (function.branches > 0).then_some(span);
+                function.defers.push((*defered_statment, conditional));
+            }
+        }
+    }
+
+    fn expr(&mut self, id: ExprId, state: &State) {
+        let arena = self.arena;
+        match &arena[id].0 {
+            Expr::Literal(_) => (),
+            Expr::Variable { name } => self.read(self.resolution.expr(id), name.1, state),
+            Expr::Unary(unary) => self.expr(unary.right, state),
+            Expr::Binary(binary) => {
+                self.expr(binary.left, state);
+                self.expr(binary.right, state);
+            }
+            Expr::TenaryIfStmt {
+                condition,
+                value,
+                else_value,
+            } => {
+                for e in [condition, value, else_value] {
+                    self.expr(*e, state);
+                }
+            }
+            Expr::FunctionCall {
+                callee, arguments, ..
+            } => {
+                self.expr(*callee, state);
+                for arg in arguments.iter().flatten() {
+                    self.expr(*arg, state);
+                }
+            }
+            Expr::Interpolation { arguments, .. } => {
+                for arg in arguments {
+                    self.expr(*arg, state);
+                }
+            }
+            Expr::Lambda(lambda) => self.body(
+                Some(Owner::Lambda(id)),
+                lambda.parameters.as_deref().unwrap_or_default(),
+                &lambda.statments,
+            ),
+        }
+    }
+}
+
+/// Calls `declare` with the name and statement of every declaration in a body, leaving out
+/// the bodies of functions declared in it
+fn declarations(arena: &Arena, statements: &[StmtId], declare: &mut impl FnMut(u64, StmtId)) {
+    for stmt in statements {
+        match &arena[*stmt].0 {
+            Statement::Declaration { name, .. } => declare(name.0, *stmt),
+            stmt => declarations(arena, &nested(stmt), declare),
+        }
+    }
+}
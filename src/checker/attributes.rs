@@ -0,0 +1,265 @@
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use crate::{
+    ast::{
+        arena::ExprId,
+        expr::Expr,
+        stmt::{Program, Statement},
+    },
+    utils::{interner::INTERNER, object::Object, spanned::Spanned},
+};
+
+use super::{
+    checker::Diagnostic,
+    names::{suggestion, Declaration, Environment, Resolution},
+};
+
+/// What an attribute can be put on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Function,
+    Extern,
+    Test,
+}
+
+impl Target {
+    pub fn of(stmt: &Statement) -> Option<Target> {
+        match stmt {
+            Statement::FuncDeclaration { .. } => Some(Target::Function),
+            Statement::Extern(_) => Some(Target::Extern),
+            Statement::TestDeclaration { .. } => Some(Target::Test),
+            _ => None,
+        }
+    }
+
+    fn plural(self) -> &'static str {
+        match self {
+            Target::Function => "functions",
+            Target::Extern => "extern functions",
+            Target::Test => "tests",
+        }
+    }
+}
+
+/// An attribute the checker knows, anything else is reported as unknown
+#[derive(Debug, Clone)]
+pub struct AttributeSpec {
+    pub name: String,
+    pub targets: Vec<Target>,
+    /// How many arguments it takes
+    pub arguments: RangeInclusive<usize>,
+}
+
+impl AttributeSpec {
+    pub fn new(name: &str, targets: &[Target], arguments: RangeInclusive<usize>) -> Self {
+        AttributeSpec {
+            name: name.to_string(),
+            targets: targets.to_vec(),
+            arguments,
+        }
+    }
+}
+
+/// The attributes programs can use, embedders can register their own to query later
+#[derive(Debug, Clone)]
+pub struct Registry {
+    attributes: Vec<AttributeSpec>,
+}
+
+impl Registry {
+    /// The attributes the language gives meaning to
+    ///
+    /// - `@inline`, a hint the backends are free to ignore
+    /// - `@deprecated` or `@deprecated("message")`, uses of the function are warned about
+    /// - `@test`, the test runner calls the function as a test
+    /// - `@ignore`, the test runner skips the test unless asked not to
+    /// - `@memoize`, calls with arguments the function has seen return the same result
+    /// - `@exit("name")` and `@enter("name")`, `with` passes what the function returns to
+    ///   them when its body starts and however it ends
+    pub fn new() -> Self {
+        use Target::*;
+        Registry {
+            attributes: vec![
+                AttributeSpec::new("inline", &[Function], 0..=0),
+                AttributeSpec::new("deprecated", &[Function, Extern], 0..=1),
+                AttributeSpec::new("test", &[Function], 0..=0),
+                AttributeSpec::new("ignore", &[Function, Test], 0..=0),
+                AttributeSpec::new("memoize", &[Function], 0..=0),
+                AttributeSpec::new("enter", &[Function, Extern], 1..=1),
+                AttributeSpec::new("exit", &[Function, Extern], 1..=1),
+            ],
+        }
+    }
+
+    /// Adds an attribute, replacing one with the same name
+    pub fn register(&mut self, spec: AttributeSpec) -> &mut Self {
+        self.attributes.retain(|known| known.name != spec.name);
+        self.attributes.push(spec);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AttributeSpec> {
+        self.attributes.iter().find(|spec| spec.name == name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.attributes.iter().map(|spec| spec.name.as_str())
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Attributes that aren't registered, are repeated or don't fit what they're on, uses of
+/// `@deprecated` functions and `with` values that don't come from an `@exit` function
+pub fn check(
+    input: &str,
+    program: &Program,
+    environment: &Environment,
+    resolution: &Resolution,
+) -> Vec<Diagnostic> {
+    let arena = &program.arena;
+    let registry = environment.attributes();
+    let mut diagnostics = vec![];
+    for id in arena.stmt_ids() {
+        let stmt = &arena[id].0;
+        let mut seen = HashMap::new();
+        for attribute in stmt.attributes() {
+            let Spanned(name, span) = &attribute.name;
+            if let Some(first) = seen.insert(name.as_str(), *span) {
+                diagnostics.push(
+                    Diagnostic::warning(format!("`@{name}` is repeated"), *span)
+                        .with_label(first, "first used here"),
+                );
+                continue;
+            }
+            let Some(spec) = registry.get(name) else {
+                let message = match suggestion(name, registry.names()) {
+                    Some(suggested) => {
+                        format!("Unknown attribute `@{name}`, did you mean `@{suggested}`?")
+                    }
+                    None => format!("Unknown attribute `@{name}`"),
+                };
+                diagnostics.push(Diagnostic::warning(message, *span));
+                continue;
+            };
+            let target = Target::of(stmt).expect("only declarations have attributes");
+            if !spec.targets.contains(&target) {
+                diagnostics.push(Diagnostic::error(
+                    format!("`@{name}` can't be used on {}", target.plural()),
+                    *span,
+                ));
+                continue;
+            }
+            let count = attribute.arguments.len();
+            if !spec.arguments.contains(&count) {
+                let expected = match spec.arguments.start() == spec.arguments.end() {
+                    true => spec.arguments.start().to_string(),
+                    false => format!("{} to {}", spec.arguments.start(), spec.arguments.end()),
+                };
+                diagnostics.push(Diagnostic::error(
+                    format!("`@{name}` takes {expected} argument(s), found {count}"),
+                    *span,
+                ));
+                continue;
+            }
+            if let Some(message) = misuse(stmt, name, &attribute.arguments) {
+                diagnostics.push(Diagnostic::error(message, *span));
+            }
+        }
+    }
+    for id in arena.expr_ids() {
+        let Expr::Variable { name } = &arena[id].0 else {
+            continue;
+        };
+        let Some(Declaration::Function(declaration)) = resolution.expr(id) else {
+            continue;
+        };
+        let Some(deprecated) = arena[declaration].0.attribute("deprecated") else {
+            continue;
+        };
+        let text = &input[name.1.into_range()];
+        let message = match deprecated.arguments.first() {
+            Some(Spanned(Object::String(reason), _)) => {
+                format!("`{text}` is deprecated, {}", INTERNER.resolve(reason))
+            }
+            _ => format!("`{text}` is deprecated"),
+        };
+        diagnostics.push(
+            Diagnostic::warning(message, name.1).with_label(deprecated.name.1, "deprecated here"),
+        );
+    }
+    for id in arena.stmt_ids() {
+        if let Statement::With { value, .. } = &arena[id].0 {
+            diagnostics.extend(resource(program, resolution, *value));
+        }
+    }
+    diagnostics
+}
+
+/// Reports `value` unless it calls a function `with` knows the hooks of. Declared functions
+/// need an `@exit`, what's imported or native can only be told apart when it runs
+fn resource(program: &Program, resolution: &Resolution, value: ExprId) -> Option<Diagnostic> {
+    let arena = &program.arena;
+    let callee = match &arena[value].0 {
+        Expr::FunctionCall {
+            callee,
+            safe: false,
+            ..
+        } if matches!(arena[*callee].0, Expr::Variable { .. }) => Some(*callee),
+        _ => None,
+    };
+    let declaration = callee.and_then(|callee| resolution.expr(callee));
+    let error = Diagnostic::error(
+        "Only what a function with an `@exit` returns can be used with `with`",
+        arena[value].1,
+    );
+    match declaration {
+        Some(Declaration::Function(function)) => match &arena[function].0 {
+            stmt if stmt.attribute("exit").is_some() => None,
+            Statement::FuncDeclaration { name, .. } => {
+                Some(error.with_label(name.1, "it has no `@exit`"))
+            }
+            Statement::Extern(declaration) => {
+                Some(error.with_label(declaration.name.1, "it has no `@exit`"))
+            }
+            _ => Some(error),
+        },
+        Some(Declaration::Native | Declaration::Global | Declaration::Imported { .. }) => None,
+        // Not found at all is reported already
+        None if callee.is_some() => None,
+        _ => Some(error),
+    }
+}
+
+/// What's wrong with a built in attribute that fits its target, if anything
+fn misuse(stmt: &Statement, name: &str, arguments: &[Spanned<Object>]) -> Option<String> {
+    let parameters = match stmt {
+        Statement::FuncDeclaration { parameters, .. } => parameters.as_deref().unwrap_or_default(),
+        _ => &[],
+    };
+    match name {
+        "deprecated"
+            if !matches!(
+                arguments.first(),
+                None | Some(Spanned(Object::String(_), _))
+            ) =>
+        {
+            Some("`@deprecated` takes a string saying what to use instead".into())
+        }
+        "enter" | "exit" if !matches!(arguments.first(), Some(Spanned(Object::String(_), _))) => {
+            Some(format!("`@{name}` takes the name of the function to call"))
+        }
+        "enter" if stmt.attribute("exit").is_none() => {
+            Some("`@enter` needs an `@exit` to go with it".into())
+        }
+        "test" if !parameters.is_empty() => Some("`@test` functions can't take parameters".into()),
+        "memoize" if parameters.iter().any(|param| param.is_alias()) => {
+            Some("`@memoize` functions can't take `ref` or `out` parameters".into())
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,117 @@
+use ariadne::{Color, Label, ReportKind};
+
+use crate::{
+    ast::{
+        arena::StmtId,
+        stmt::{Program, Statement},
+    },
+    utils::{
+        source_map::{self, SourceMap},
+        spanned::Span,
+    },
+};
+
+use super::{
+    assignment, attributes, mutability,
+    names::{self, Environment},
+    nulls,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Something a check found before the program runs, labels point at the code that explains it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    pub labels: Vec<(Span, String)>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            labels: vec![],
+        }
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Self::error(message, span)
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push((span, message.into()));
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn report(&self, sources: &SourceMap) {
+        let _ = self.write(sources, std::io::stderr());
+    }
+
+    /// Renders the report `report` prints to `writer`
+    pub fn write(&self, sources: &SourceMap, writer: impl std::io::Write) -> std::io::Result<()> {
+        let (kind, color) = match self.severity {
+            Severity::Error => (ReportKind::Error, Color::Red),
+            Severity::Warning => (ReportKind::Warning, Color::Yellow),
+        };
+        source_map::report(kind, self.span)
+            .with_message(&self.message)
+            .with_label(Label::new(self.span).with_color(color))
+            .with_labels(self.labels.iter().map(|(span, message)| {
+                Label::new(*span)
+                    .with_message(message)
+                    .with_color(Color::Blue)
+            }))
+            .finish()
+            .write(sources.cache(), writer)
+    }
+}
+
+/// Runs every check over `program`, `input` is the source it was parsed from and
+/// `environment` has the names it can use without declaring them
+pub fn check(input: &str, program: &Program, environment: &Environment) -> Vec<Diagnostic> {
+    let (resolution, mut diagnostics) = names::resolve(input, program, environment);
+    diagnostics.extend(attributes::check(input, program, environment, &resolution));
+    diagnostics.extend(assignment::check(input, program, &resolution));
+    diagnostics.extend(mutability::check(input, program, &resolution));
+    diagnostics.extend(nulls::check(input, program, &resolution));
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    diagnostics
+}
+
+/// The statements nested in `stmt` that run as part of it, which leaves out function bodies
+pub(crate) fn nested(stmt: &Statement) -> Vec<StmtId> {
+    match stmt {
+        Statement::Block { statments }
+        | Statement::TestDeclaration { statments, .. }
+        | Statement::With { statments, .. } => statments.clone(),
+        Statement::IfStmt {
+            then_branch,
+            else_branch,
+            ..
+        } => [*then_branch].into_iter().chain(*else_branch).collect(),
+        Statement::MatchStmt(match_stmt) => match_stmt
+            .then_branches
+            .0
+            .iter()
+            .map(|(_, arm)| *arm)
+            .collect(),
+        Statement::WhileStmt { then_branch, .. } => vec![*then_branch],
+        Statement::Defer { defered_statment } => vec![*defered_statment],
+        _ => vec![],
+    }
+}
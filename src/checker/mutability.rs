@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{
+        arena::{Arena, ExprId, StmtId},
+        expr::Expr,
+        misc::{AssignOp, DeclarationType, FuncParameter, ParamRestrictor, ParamType},
+        stmt::{Program, Statement},
+    },
+    utils::spanned::Span,
+};
+
+use super::{
+    checker::{nested, Diagnostic},
+    names::{Declaration, Owner, Resolution},
+};
+
+/// Assignments to bindings that can't change, variables passed where a parameter needs one
+/// it can't have, and `var` bindings that never change
+pub fn check(input: &str, program: &Program, resolution: &Resolution) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        input,
+        arena: &program.arena,
+        resolution,
+        bindings: vec![],
+        declarations: HashMap::new(),
+        depth: 0,
+        diagnostics: vec![],
+    };
+    // Functions can run once the whole top level has, so they see every global
+    checker.globals(&program.statements);
+    checker.statements(&program.statements);
+    for binding in &checker.bindings {
+        if binding.var && !binding.mutated {
+            let name = &input[binding.span.into_range()];
+            checker.diagnostics.push(Diagnostic::warning(
+                format!("`{name}` is never mutated, declare it with `let`"),
+                binding.span,
+            ));
+        }
+    }
+    checker.diagnostics
+}
+
+/// A variable or parameter
+struct Binding {
+    span: Span,
+    mutable: bool,
+    /// Declared with `var`
+    var: bool,
+    mutated: bool,
+    /// Why the binding can't be assigned
+    note: &'static str,
+    /// How many functions and lambdas it's declared in, 0 for globals
+    depth: usize,
+}
+
+struct Checker<'a> {
+    input: &'a str,
+    arena: &'a Arena,
+    resolution: &'a Resolution,
+    bindings: Vec<Binding>,
+    /// The binding of each declaration, as indices into `bindings`
+    declarations: HashMap<Declaration, usize>,
+    /// How many functions and lambdas the walk is in
+    depth: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn text(&self, span: Span) -> &str {
+        self.input.get(span.into_range()).unwrap_or_default()
+    }
+
+    fn bind(&mut self, declaration: Declaration, binding: Binding) {
+        self.bindings.push(binding);
+        self.declarations
+            .insert(declaration, self.bindings.len() - 1);
+    }
+
+    fn declare(&mut self, id: StmtId, span: Span, declaration_type: DeclarationType) {
+        let var = matches!(declaration_type, DeclarationType::Mutable);
+        self.bind(
+            Declaration::Variable(id),
+            Binding {
+                span,
+                mutable: var,
+                var,
+                mutated: false,
+                note: "declared with `let` here",
+                depth: self.depth,
+            },
+        );
+    }
+
+    /// The binding `declaration` made and whether a lambda captured it, lambdas see the
+    /// locals of the functions around them
+    fn lookup(&self, declaration: Option<Declaration>) -> Option<(usize, bool)> {
+        let index = *self.declarations.get(&declaration?)?;
+        let depth = self.bindings[index].depth;
+        Some((index, depth != self.depth && depth != 0))
+    }
+
+    /// Binds the top level's declarations up front
+    fn globals(&mut self, statements: &[StmtId]) {
+        let arena = self.arena;
+        for stmt in statements {
+            match &arena[*stmt].0 {
+                Statement::Declaration {
+                    declaration_type,
+                    name,
+                    ..
+                } => self.declare(*stmt, name.1, *declaration_type),
+                stmt => self.globals(&nested(stmt)),
+            }
+        }
+    }
+
+    fn function(&mut self, owner: Owner, parameters: &[FuncParameter], statments: &[StmtId]) {
+        self.depth += 1;
+        for (index, param) in parameters.iter().enumerate() {
+            let note = match (param.param_type.0, param.param_restrictor.map(|r| r.0)) {
+                (ParamType::Input, _) => "`in` parameters are read-only",
+                (_, Some(ParamRestrictor::Constant)) => "declared `const` here",
+                _ => "parameters can only be assigned when they're `mut`",
+            };
+            self.bind(
+                Declaration::Parameter { owner, index },
+                Binding {
+                    span: param.param_value_name.1,
+                    mutable: param.is_mutable(),
+                    var: false,
+                    mutated: false,
+                    note,
+                    depth: self.depth,
+                },
+            );
+        }
+        self.statements(statments);
+        self.depth -= 1;
+    }
+
+    fn statements(&mut self, statements: &[StmtId]) {
+        for stmt in statements {
+            self.statement(*stmt);
+        }
+    }
+
+    fn statement(&mut self, id: StmtId) {
+        let arena = self.arena;
+        match &arena[id].0 {
+            Statement::Error | Statement::Empty | Statement::Use { .. } | Statement::Extern(_) => {}
+            Statement::Test(expr) | Statement::Expression { expr } => self.expr(*expr),
+            Statement::TestDeclaration { statments, .. } | Statement::Block { statments } => {
+                self.statements(statments)
+            }
+            Statement::Assign(assign) => {
+                self.expr(assign.value);
+                let Some((index, captured)) = self.lookup(self.resolution.stmt(id)) else {
+                    return;
+                };
+                self.bindings[index].mutated = true;
+                let binding = &self.bindings[index];
+                if binding.mutable && !captured {
+                    return;
+                }
+                let name = self.text(assign.name.1);
+                let message = match assign.operator.0 {
+                    AssignOp::Set => format!("Can't assign to `{name}`, it's immutable"),
+                    _ => format!(
+                        "Can't use `{}` on `{name}`, it's immutable",
+                        self.text(assign.operator.1)
+                    ),
+                };
+                let note = match captured {
+                    true => "lambdas get a read-only copy of the variables they capture",
+                    false => binding.note,
+                };
+                let diagnostic =
+                    Diagnostic::error(message, arena[id].1).with_label(binding.span, note);
+                self.diagnostics.push(diagnostic);
+            }
+            Statement::Declaration {
+                declaration_type,
+                name,
+                value,
+                ..
+            } => {
+                self.expr(*value);
+                // Globals were bound up front
+                if self.depth > 0 {
+                    self.declare(id, name.1, *declaration_type);
+                }
+            }
+            Statement::FuncDeclaration {
+                parameters,
+                statments,
+                ..
+            } => self.function(
+                Owner::Function(id),
+                parameters.as_deref().unwrap_or_default(),
+                statments,
+            ),
+            Statement::IfStmt {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(*condition);
+                self.statement(*then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(*else_branch);
+                }
+            }
+            Statement::MatchStmt(match_stmt) => {
+                self.expr(match_stmt.predicate);
+                for (_, arm) in &match_stmt.then_branches.0 {
+                    self.statement(*arm);
+                }
+            }
+            Statement::WhileStmt {
+                condition,
+                then_branch,
+            } => {
+                self.expr(*condition);
+                self.statement(*then_branch);
+            }
+            Statement::With {
+                name,
+                value,
+                statments,
+            } => {
+                self.expr(*value);
+                self.bind(
+                    Declaration::Variable(id),
+                    Binding {
+                        span: name.1,
+                        mutable: false,
+                        var: false,
+                        mutated: false,
+                        note: "bound by `with` here",
+                        depth: self.depth,
+                    },
+                );
+                self.statements(statments);
+            }
+            Statement::Defer { defered_statment } => self.statement(*defered_statment),
+        }
+    }
+
+    fn expr(&mut self, id: ExprId) {
+        let arena = self.arena;
+        match &arena[id].0 {
+            Expr::Literal(_) | Expr::Variable { .. } => (),
+            Expr::Unary(unary) => self.expr(unary.right),
+            Expr::Binary(binary) => {
+                self.expr(binary.left);
+                self.expr(binary.right);
+            }
+            Expr::TenaryIfStmt {
+                condition,
+                value,
+                else_value,
+            } => {
+                for e in [condition, value, else_value] {
+                    self.expr(*e);
+                }
+            }
+            Expr::FunctionCall {
+                callee, arguments, ..
+            } => {
+                self.expr(*callee);
+                let arguments = arguments.as_deref().unwrap_or_default();
+                for arg in arguments {
+                    self.expr(*arg);
+                }
+                self.call(*callee, arguments);
+            }
+            Expr::Interpolation { arguments, .. } => {
+                for arg in arguments {
+                    self.expr(*arg);
+                }
+            }
+            Expr::Lambda(lambda) => self.function(
+                Owner::Lambda(id),
+                lambda.parameters.as_deref().unwrap_or_default(),
+                &lambda.statments,
+            ),
+        }
+    }
+
+    /// Arguments of `ref` and `out` parameters have to be variables, and ones that can be
+    /// assigned when the parameter can assign to them. They count as mutated when it can
+    fn call(&mut self, callee: ExprId, arguments: &[ExprId]) {
+        let arena = self.arena;
+        let declaration = self.resolution.expr(callee);
+        let parameters = match (&arena[callee].0, declaration) {
+            (Expr::Variable { .. }, Some(Declaration::Function(stmt))) => match &arena[stmt].0 {
+                Statement::FuncDeclaration { parameters, .. } => {
+                    parameters.as_deref().unwrap_or_default()
+                }
+                // Extern functions only take values
+                _ => return,
+            },
+            // Natives only take values, imported functions and earlier runs' globals are
+            // checked where they're declared
+            (
+                Expr::Variable { .. },
+                None
+                | Some(Declaration::Native | Declaration::Imported { .. } | Declaration::Global),
+            ) => return,
+            // Function values could be anything
+            _ => {
+                for arg in arguments {
+                    if let Some((index, _)) = self.lookup(self.resolution.expr(*arg)) {
+                        self.bindings[index].mutated = true;
+                    }
+                }
+                return;
+            }
+        };
+        for (arg, param) in arguments.iter().zip(parameters) {
+            if param.is_alias() {
+                self.alias(*arg, param);
+            }
+        }
+    }
+
+    /// Checks the argument passed to the `ref` or `out` parameter `param`
+    fn alias(&mut self, arg: ExprId, param: &FuncParameter) {
+        let arena = self.arena;
+        let parameter = param.param_value_name.1;
+        let declaration = self.resolution.expr(arg);
+        let place = matches!(arena[arg].0, Expr::Variable { .. })
+            && !matches!(
+                declaration,
+                Some(Declaration::Function(_) | Declaration::Native)
+            );
+        if !place {
+            self.diagnostics.push(
+                Diagnostic::error(
+                    "Only variables can be passed to `ref` and `out` parameters",
+                    arena[arg].1,
+                )
+                .with_label(parameter, "it aliases the variable passed to it"),
+            );
+            return;
+        }
+        let Some((index, captured)) = self.lookup(declaration) else {
+            return;
+        };
+        if !param.is_mutable() {
+            return;
+        }
+        self.bindings[index].mutated = true;
+        let binding = &self.bindings[index];
+        if binding.mutable && !captured {
+            return;
+        }
+        let message = format!(
+            "Can't pass `{}` to `{}`, it's immutable",
+            self.text(arena[arg].1),
+            self.text(parameter)
+        );
+        let note = match captured {
+            true => "lambdas get a read-only copy of the variables they capture",
+            false => binding.note,
+        };
+        let diagnostic = Diagnostic::error(message, arena[arg].1)
+            .with_label(binding.span, note)
+            .with_label(parameter, "it can assign to the variable passed to it");
+        self.diagnostics.push(diagnostic);
+    }
+}
@@ -0,0 +1,743 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        arena::{Arena, ExprId, StmtId},
+        expr::Expr,
+        misc::{AssignOp, FuncParameter},
+        stmt::{ExternDeclaration, Program, Statement},
+    },
+    interperter::prelude::prelude,
+    lexer::lexer::convert_to_hash,
+    utils::{
+        interner::INTERNER,
+        object::Object,
+        spanned::{Span, Spanned},
+        valtype::Type,
+    },
+};
+
+use super::{
+    attributes::{AttributeSpec, Registry},
+    checker::{nested, Diagnostic},
+};
+
+/// The types annotations can name
+const TYPES: [&str; 7] = ["int", "long", "float", "bool", "string", "null", "function"];
+
+/// Names a program can use without declaring them
+#[derive(Debug, Clone)]
+pub struct Environment {
+    natives: Vec<String>,
+    /// Globals and functions earlier runs declared
+    globals: HashSet<u64>,
+    /// What `use` brought in, by the name it's used under, and the global it's stored in
+    imports: HashMap<u64, u64>,
+    attributes: Registry,
+}
+
+impl Environment {
+    /// No names at all
+    pub fn new() -> Self {
+        Environment {
+            natives: vec![],
+            globals: HashSet::new(),
+            imports: HashMap::new(),
+            attributes: Registry::new(),
+        }
+    }
+
+    /// What every engine starts with
+    pub fn prelude() -> Self {
+        let mut environment = Self::new();
+        for native in prelude() {
+            environment.native(&native.name);
+        }
+        environment
+    }
+
+    pub fn native(&mut self, name: &str) -> &mut Self {
+        self.natives.push(name.to_string());
+        self
+    }
+
+    pub fn global(&mut self, name: u64) -> &mut Self {
+        self.globals.insert(name);
+        self
+    }
+
+    /// Lets programs use an attribute of the embedder's
+    pub fn attribute(&mut self, spec: AttributeSpec) -> &mut Self {
+        self.attributes.register(spec);
+        self
+    }
+
+    pub fn attributes(&self) -> &Registry {
+        &self.attributes
+    }
+
+    /// Makes `name` refer to the global another module stores as `global`
+    pub fn import(&mut self, name: u64, global: u64) -> &mut Self {
+        self.imports.insert(name, global);
+        self
+    }
+
+    fn lookup(&self, name: u64) -> Option<Declaration> {
+        if let Some(global) = self.imports.get(&name) {
+            Some(Declaration::Imported { global: *global })
+        } else if self
+            .natives
+            .iter()
+            .any(|native| convert_to_hash(&native.as_str()) == name)
+        {
+            Some(Declaration::Native)
+        } else if self.globals.contains(&name) {
+            Some(Declaration::Global)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The function statement or lambda a parameter belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Owner {
+    Function(StmtId),
+    Lambda(ExprId),
+}
+
+/// What a name refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Declaration {
+    /// The `let` or `var` statement
+    Variable(StmtId),
+    /// The `fn` statement
+    Function(StmtId),
+    Parameter {
+        owner: Owner,
+        index: usize,
+    },
+    Native,
+    /// Declared by an earlier run or by the embedder
+    Global,
+    /// Brought in by a `use`, `global` is the name the module stores it under
+    Imported {
+        global: u64,
+    },
+}
+
+/// The declaration each variable expression and assignment refers to, indexed by node id
+#[derive(Debug, Clone, Default)]
+pub struct Resolution {
+    exprs: Vec<Option<Declaration>>,
+    stmts: Vec<Option<Declaration>>,
+}
+
+impl Resolution {
+    pub fn expr(&self, id: ExprId) -> Option<Declaration> {
+        self.exprs.get(id.index()).copied().flatten()
+    }
+
+    pub fn stmt(&self, id: StmtId) -> Option<Declaration> {
+        self.stmts.get(id.index()).copied().flatten()
+    }
+}
+
+/// Binds every name to its declaration, names that can't be bound, declarations that
+/// repeat one that already ran and declarations nothing uses are reported
+pub fn resolve(
+    input: &str,
+    program: &Program,
+    environment: &Environment,
+) -> (Resolution, Vec<Diagnostic>) {
+    let arena = &program.arena;
+    let mut resolver = walk(input, program, environment);
+    // `with` calls the functions `@enter` and `@exit` name
+    let hooks: HashSet<&str> = arena
+        .stmt_ids()
+        .flat_map(|id| arena[id].0.attributes())
+        .filter(|attribute| matches!(attribute.name.0.as_str(), "enter" | "exit"))
+        .filter_map(|attribute| match attribute.arguments.first() {
+            Some(Spanned(Object::String(hook), _)) => Some(INTERNER.resolve(hook)),
+            _ => None,
+        })
+        .collect();
+    for binding in &resolver.bindings {
+        let name = &input[binding.span.into_range()];
+        if binding.used || binding.parameter || binding.public || name.starts_with('_') {
+            continue;
+        }
+        // The test runner calls `@test` functions
+        if let Declaration::Function(stmt) = binding.declaration {
+            if arena[stmt].0.attribute("test").is_some() || hooks.contains(name) {
+                continue;
+            }
+        }
+        let message = match binding.declaration {
+            Declaration::Function(_) => format!("function `{name}` is never used"),
+            _ => format!("`{name}` is never used"),
+        };
+        resolver
+            .diagnostics
+            .push(Diagnostic::warning(message, binding.span));
+    }
+    (resolver.resolution, resolver.diagnostics)
+}
+
+/// Binds every name like `resolve` for engines, which don't have the source to report with
+pub(crate) fn bind(program: &Program, environment: &Environment) -> Resolution {
+    walk("", program, environment).resolution
+}
+
+fn walk<'a>(input: &'a str, program: &'a Program, environment: &'a Environment) -> Resolver<'a> {
+    let arena = &program.arena;
+    let mut resolver = Resolver {
+        input,
+        arena,
+        environment,
+        resolution: Resolution {
+            exprs: vec![None; arena.expr_count()],
+            stmts: vec![None; arena.stmt_count()],
+        },
+        bindings: vec![],
+        functions: HashMap::new(),
+        scopes: vec![],
+        diagnostics: vec![],
+    };
+    resolver.function_declarations(&program.statements);
+    resolver.body(&[], None, &program.statements, false);
+    resolver
+}
+
+pub fn check(input: &str, program: &Program, environment: &Environment) -> Vec<Diagnostic> {
+    resolve(input, program, environment).1
+}
+
+/// A declared variable, parameter or function
+struct Binding {
+    declaration: Declaration,
+    span: Span,
+    /// Whether the walk has passed the declaration
+    declared: bool,
+    parameter: bool,
+    /// Other files can use it
+    public: bool,
+    used: bool,
+}
+
+/// A function, lambda or the top level
+#[derive(Default)]
+struct Scope {
+    /// Every declaration in the body, up front, as indices into `bindings`
+    names: HashMap<u64, Vec<usize>>,
+    lambda: bool,
+    /// Names declared on every path to the current statement, and where
+    variables: HashMap<u64, Span>,
+    functions: HashMap<u64, Span>,
+    defers: Vec<StmtId>,
+    tests: Vec<StmtId>,
+}
+
+struct Resolver<'a> {
+    input: &'a str,
+    arena: &'a Arena,
+    environment: &'a Environment,
+    resolution: Resolution,
+    bindings: Vec<Binding>,
+    /// Declared functions are global wherever they're declared
+    functions: HashMap<u64, Vec<usize>>,
+    /// The top level first and the innermost function last
+    scopes: Vec<Scope>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Resolver<'_> {
+    fn text(&self, span: Span) -> &str {
+        self.input.get(span.into_range()).unwrap_or_default()
+    }
+
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("there's always a scope")
+    }
+
+    fn bind(&mut self, declaration: Declaration, span: Span, parameter: bool) -> usize {
+        let public = match declaration {
+            Declaration::Variable(stmt) | Declaration::Function(stmt) => matches!(
+                self.arena[stmt].0,
+                Statement::Declaration { public: true, .. }
+                    | Statement::FuncDeclaration { public: true, .. }
+                    | Statement::Extern(ExternDeclaration { public: true, .. })
+            ),
+            _ => false,
+        };
+        self.bindings.push(Binding {
+            declaration,
+            span,
+            declared: parameter,
+            parameter,
+            public,
+            used: false,
+        });
+        self.bindings.len() - 1
+    }
+
+    /// Binds every function declared anywhere in the program
+    fn function_declarations(&mut self, statements: &[StmtId]) {
+        let arena = self.arena;
+        for stmt in statements {
+            match &arena[*stmt].0 {
+                Statement::FuncDeclaration {
+                    name, statments, ..
+                } => {
+                    let index = self.bind(Declaration::Function(*stmt), name.1, false);
+                    self.functions.entry(name.0).or_default().push(index);
+                    self.function_declarations(statments);
+                }
+                Statement::Extern(declaration) => {
+                    let name = declaration.name;
+                    let index = self.bind(Declaration::Function(*stmt), name.1, false);
+                    self.functions.entry(name.0).or_default().push(index);
+                }
+                stmt => self.function_declarations(&nested(stmt)),
+            }
+        }
+    }
+
+    /// Binds the variables declared in a body, leaving out the bodies of functions in it
+    fn variable_declarations(&mut self, statements: &[StmtId]) {
+        let arena = self.arena;
+        for stmt in statements {
+            match &arena[*stmt].0 {
+                Statement::Declaration { name, .. } => {
+                    let index = self.bind(Declaration::Variable(*stmt), name.1, false);
+                    self.scope().names.entry(name.0).or_default().push(index);
+                }
+                Statement::With {
+                    name, statments, ..
+                } => {
+                    let index = self.bind(Declaration::Variable(*stmt), name.1, false);
+                    self.scope().names.entry(name.0).or_default().push(index);
+                    self.variable_declarations(statments);
+                }
+                stmt => self.variable_declarations(&nested(stmt)),
+            }
+        }
+    }
+
+    fn body(
+        &mut self,
+        parameters: &[FuncParameter],
+        owner: Option<Owner>,
+        statments: &[StmtId],
+        lambda: bool,
+    ) {
+        self.scopes.push(Scope {
+            lambda,
+            ..Scope::default()
+        });
+        for (index, param) in parameters.iter().enumerate() {
+            self.type_name(&param.param_value_type);
+            let name = *param.param_value_name;
+            if let Some(first) = self.scope().variables.get(&name.0).copied() {
+                self.duplicate(name.1, first);
+            }
+            let owner = owner.expect("only functions and lambdas have parameters");
+            let index = self.bind(Declaration::Parameter { owner, index }, name.1, true);
+            let scope = self.scope();
+            scope.names.entry(name.0).or_default().push(index);
+            scope.variables.insert(name.0, name.1);
+        }
+        self.variable_declarations(statments);
+        self.statements(statments);
+        // Deferred statements run once the body is done and tests once the script is
+        while let Some(stmt) = self.scope().defers.pop() {
+            self.branch(stmt);
+        }
+        let tests = std::mem::take(&mut self.scope().tests);
+        let arena = self.arena;
+        for test in tests {
+            if let Statement::TestDeclaration { statments, .. } = &arena[test].0 {
+                self.branch_statements(statments);
+            }
+        }
+        self.scopes.pop();
+    }
+
+    fn type_name(&mut self, type_name: &Spanned<Type>) {
+        let name = self.text(type_name.1).trim_end_matches('?').to_string();
+        if TYPES.contains(&name.as_str()) {
+            return;
+        }
+        let message = match suggestion(&name, TYPES) {
+            Some(suggested) => format!("Can't find type `{name}`, did you mean `{suggested}`?"),
+            None => format!("Can't find type `{name}`"),
+        };
+        self.diagnostics
+            .push(Diagnostic::error(message, type_name.1));
+    }
+
+    fn duplicate(&mut self, span: Span, first: Span) {
+        let name = self.text(span);
+        self.diagnostics.push(
+            Diagnostic::error(format!("`{name}` is already declared"), span)
+                .with_label(first, "first declared here"),
+        );
+    }
+
+    /// Walks a statement that might not run, what it declares isn't declared on every path
+    fn branch(&mut self, stmt: StmtId) {
+        self.branch_statements(&[stmt]);
+    }
+
+    fn branch_statements(&mut self, statements: &[StmtId]) {
+        let scope = self.scope();
+        let variables = scope.variables.clone();
+        let functions = scope.functions.clone();
+        self.statements(statements);
+        let scope = self.scope();
+        scope.variables = variables;
+        scope.functions = functions;
+    }
+
+    /// The binding `name` refers to. The body being walked only sees what it declared so
+    /// far, the bodies around it have declared everything by the time it runs
+    fn lookup(&self, name: u64) -> Option<usize> {
+        let innermost = self.scopes.len() - 1;
+        let mut depth = innermost;
+        loop {
+            let found =
+                self.scopes[depth]
+                    .names
+                    .get(&name)
+                    .and_then(|indices| match depth == innermost {
+                        true => indices
+                            .iter()
+                            .rev()
+                            .find(|index| self.bindings[**index].declared),
+                        false => indices.first(),
+                    });
+            if let Some(index) = found {
+                return Some(*index);
+            }
+            if depth == 0 {
+                break;
+            }
+            depth = match self.scopes[depth].lambda {
+                true => depth - 1,
+                false => 0,
+            };
+        }
+        let top_level = innermost == 0;
+        // A function the body has declared shadows the others of its name
+        let local = self.scopes[innermost].functions.get(&name).copied();
+        self.functions.get(&name).and_then(|indices| {
+            let mut indices = indices.iter().copied();
+            match local {
+                Some(span) => indices.find(|index| self.bindings[*index].span == span),
+                None => indices.find(|index| !top_level || self.bindings[*index].declared),
+            }
+        })
+    }
+
+    /// Binds a use of `name`, reporting it if there's nothing to bind it to. `called` is
+    /// whether it's the callee of a call
+    fn resolve(&mut self, name: Spanned<u64>, read: bool, called: bool) -> Option<Declaration> {
+        if let Some(index) = self.lookup(name.0) {
+            let binding = &mut self.bindings[index];
+            binding.used |= read;
+            return Some(binding.declaration);
+        }
+        if let Some(declaration) = self.environment.lookup(name.0) {
+            return Some(declaration);
+        }
+        let text = self.text(name.1).to_string();
+        // Declared later in the same body, or later in the script for the top level
+        let scope = self.scopes.last().expect("there's always a scope");
+        let later = scope
+            .names
+            .get(&name.0)
+            .into_iter()
+            .chain(
+                self.functions
+                    .get(&name.0)
+                    .filter(|_| self.scopes.len() == 1),
+            )
+            .flatten()
+            .copied()
+            .find(|index| self.bindings[*index].span.start > name.1.start);
+        let diagnostic = match later {
+            Some(index) => {
+                let binding = &mut self.bindings[index];
+                binding.used |= read;
+                let span = binding.span;
+                Diagnostic::error(format!("`{text}` is used before it's declared"), name.1)
+                    .with_label(span, "declared here")
+            }
+            None => match suggestion(&text, self.visible(called)) {
+                Some(suggested) => Diagnostic::error(
+                    format!("Can't find `{text}`, did you mean `{suggested}`?"),
+                    name.1,
+                ),
+                None => Diagnostic::error(format!("Can't find `{text}`"), name.1),
+            },
+        };
+        self.diagnostics.push(diagnostic);
+        None
+    }
+
+    /// The names that could have been meant at the current point, functions for calls and
+    /// variables otherwise
+    fn visible(&self, called: bool) -> Vec<&str> {
+        let text = |index: &usize| self.text(self.bindings[*index].span);
+        match called {
+            true => {
+                let natives = self.environment.natives.iter().map(String::as_str);
+                self.functions
+                    .values()
+                    .flatten()
+                    .map(text)
+                    .chain(natives)
+                    .collect()
+            }
+            false => self
+                .scopes
+                .iter()
+                .flat_map(|scope| scope.names.values())
+                .flatten()
+                .map(text)
+                .collect(),
+        }
+    }
+
+    fn statements(&mut self, statements: &[StmtId]) {
+        for stmt in statements {
+            self.statement(*stmt);
+        }
+    }
+
+    /// Marks the function `id` declares as declared from here on
+    fn declare_function(&mut self, id: StmtId, name: Spanned<u64>) {
+        if let Some(first) = self.scope().functions.get(&name.0).copied() {
+            self.duplicate(name.1, first);
+        }
+        let declaration = Declaration::Function(id);
+        let bound = self.functions.get(&name.0).and_then(|indices| {
+            indices
+                .iter()
+                .copied()
+                .find(|index| self.bindings[*index].declaration == declaration)
+        });
+        // Functions declared in lambdas aren't bound up front
+        let index = match bound {
+            Some(index) => index,
+            None => {
+                let index = self.bind(declaration, name.1, false);
+                self.functions.entry(name.0).or_default().push(index);
+                index
+            }
+        };
+        self.bindings[index].declared = true;
+        self.scope().functions.insert(name.0, name.1);
+    }
+
+    /// Marks the variable `id` declares as declared from here on
+    fn declare_variable(&mut self, id: StmtId, name: Spanned<u64>) -> usize {
+        if let Some(first) = self.scope().variables.get(&name.0).copied() {
+            self.duplicate(name.1, first);
+        }
+        let scope = self.scopes.last().expect("there's always a scope");
+        let index = scope.names[&name.0]
+            .iter()
+            .copied()
+            .find(|index| self.bindings[*index].declaration == Declaration::Variable(id))
+            .expect("declarations are bound up front");
+        self.bindings[index].declared = true;
+        self.scope().variables.insert(name.0, name.1);
+        index
+    }
+
+    fn statement(&mut self, id: StmtId) {
+        let arena = self.arena;
+        match &arena[id].0 {
+            // The loader binds what's imported
+            Statement::Error | Statement::Empty | Statement::Use { .. } => (),
+            Statement::Extern(declaration) => {
+                self.declare_function(id, declaration.name);
+                let mut parameters = HashMap::new();
+                for (name, _) in &declaration.parameters {
+                    if let Some(first) = parameters.insert(name.0, name.1) {
+                        self.duplicate(name.1, first);
+                    }
+                }
+            }
+            Statement::Test(expr) | Statement::Expression { expr } => self.expr(*expr),
+            Statement::TestDeclaration { .. } => self.scope().tests.push(id),
+            Statement::Block { statments } => self.statements(statments),
+            Statement::Assign(assign) => {
+                self.expr(assign.value);
+                // Compound assignments read the variable first
+                let read = assign.operator.0 != AssignOp::Set;
+                self.resolution.stmts[id.index()] = self.resolve(assign.name, read, false);
+            }
+            Statement::Declaration {
+                name,
+                manual_type,
+                value,
+                ..
+            } => {
+                if let Some(manual_type) = manual_type {
+                    self.type_name(manual_type);
+                }
+                self.expr(*value);
+                self.declare_variable(id, *name);
+            }
+            Statement::With {
+                name,
+                value,
+                statments,
+            } => {
+                self.expr(*value);
+                // The name is only bound for the body
+                let variables = self.scope().variables.clone();
+                let index = self.declare_variable(id, *name);
+                self.statements(statments);
+                self.bindings[index].declared = false;
+                self.scope().variables = variables;
+            }
+            Statement::FuncDeclaration {
+                name,
+                parameters,
+                return_type,
+                statments,
+                ..
+            } => {
+                self.declare_function(id, *name);
+                if let Some(return_type) = return_type {
+                    self.type_name(return_type);
+                }
+                self.body(
+                    parameters.as_deref().unwrap_or_default(),
+                    Some(Owner::Function(id)),
+                    statments,
+                    false,
+                );
+            }
+            Statement::IfStmt {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(*condition);
+                self.branch(*then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.branch(*else_branch);
+                }
+            }
+            Statement::MatchStmt(match_stmt) => {
+                self.expr(match_stmt.predicate);
+                for (_, arm) in &match_stmt.then_branches.0 {
+                    self.branch(*arm);
+                }
+            }
+            Statement::WhileStmt {
+                condition,
+                then_branch,
+            } => {
+                self.expr(*condition);
+                self.branch(*then_branch);
+            }
+            Statement::Defer { defered_statment } => self.scope().defers.push(*defered_statment),
+        }
+    }
+
+    fn expr(&mut self, id: ExprId) {
+        let arena = self.arena;
+        match &arena[id].0 {
+            Expr::Literal(_) => (),
+            Expr::Variable { name } => {
+                self.resolution.exprs[id.index()] = self.resolve(*name, true, false);
+            }
+            Expr::Unary(unary) => self.expr(unary.right),
+            Expr::Binary(binary) => {
+                self.expr(binary.left);
+                self.expr(binary.right);
+            }
+            Expr::TenaryIfStmt {
+                condition,
+                value,
+                else_value,
+            } => {
+                for e in [condition, value, else_value] {
+                    self.expr(*e);
+                }
+            }
+            Expr::FunctionCall {
+                callee, arguments, ..
+            } => {
+                match &arena[*callee].0 {
+                    Expr::Variable { name } => {
+                        self.resolution.exprs[callee.index()] = self.resolve(*name, true, true)
+                    }
+                    _ => self.expr(*callee),
+                }
+                for arg in arguments.iter().flatten() {
+                    self.expr(*arg);
+                }
+            }
+            Expr::Interpolation { arguments, .. } => {
+                for arg in arguments {
+                    self.expr(*arg);
+                }
+            }
+            Expr::Lambda(lambda) => {
+                if let Some(return_type) = &lambda.return_type {
+                    self.type_name(return_type);
+                }
+                self.body(
+                    lambda.parameters.as_deref().unwrap_or_default(),
+                    Some(Owner::Lambda(id)),
+                    &lambda.statments,
+                    true,
+                );
+            }
+        }
+    }
+}
+
+/// The closest of `candidates` to `name`, if it's close enough to be a typo. That's at most
+/// a third of its length away, so short names aren't matched to whatever is one letter off
+pub(super) fn suggestion<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let length = name.chars().count();
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(distance, _)| distance * 3 <= length)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The edit distance between `a` and `b`
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substituted = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
@@ -0,0 +1,602 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        arena::{Arena, ExprId, StmtId},
+        expr::{Binary, Expr},
+        misc::{AssignOp, BinaryOp, CType, FuncParameter, UnaryOp},
+        stmt::{Program, Statement},
+    },
+    utils::{
+        object::Object,
+        spanned::{Span, Spanned},
+        valtype::Type,
+    },
+};
+
+use super::{
+    checker::Diagnostic,
+    names::{Declaration, Owner, Resolution},
+};
+
+/// Null safety, `null` can only go where a `T?` is expected and can't be used with
+/// arithmetic. Comparing a variable with `null` narrows it in the branches that follow
+pub fn check(input: &str, program: &Program, resolution: &Resolution) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        input,
+        arena: &program.arena,
+        resolution,
+        quiet: false,
+        diagnostics: vec![],
+    };
+    checker.statements(&program.statements, &mut State::default());
+    checker.diagnostics
+}
+
+/// Whether a value can be null
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Nullness {
+    Never,
+    Maybe,
+    Always,
+}
+
+impl Nullness {
+    fn of(type_name: Type) -> Nullness {
+        match type_name.nullable {
+            true => Nullness::Maybe,
+            false => Nullness::Never,
+        }
+    }
+
+    /// Where two paths meet
+    fn join(self, other: Nullness) -> Nullness {
+        match self == other {
+            true => self,
+            false => Nullness::Maybe,
+        }
+    }
+
+    /// `self ?? other`, and what `?=` leaves in a variable
+    fn or(self, other: Nullness) -> Nullness {
+        match (self, other) {
+            (Nullness::Never, _) | (_, Nullness::Never) => Nullness::Never,
+            (Nullness::Always, other) => other,
+            (Nullness::Maybe, _) => Nullness::Maybe,
+        }
+    }
+}
+
+/// What's known about variables at a point in the code, the ones that aren't in here
+/// can be null if their type says so
+#[derive(Debug, Clone, Default)]
+struct State(HashMap<Declaration, Nullness>);
+
+/// What a condition says about variables when it's true and when it's false
+#[derive(Default)]
+struct Facts {
+    when_true: Vec<(Declaration, Nullness)>,
+    when_false: Vec<(Declaration, Nullness)>,
+}
+
+struct Checker<'a> {
+    input: &'a str,
+    arena: &'a Arena,
+    resolution: &'a Resolution,
+    /// Set while a loop body is walked the first time, its second walk reports
+    quiet: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn text(&self, span: Span) -> &'a str {
+        self.input.get(span.into_range()).unwrap_or_default()
+    }
+
+    fn report(&mut self, diagnostic: Diagnostic) {
+        if !self.quiet {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    /// The type a variable or parameter is declared with
+    fn declared(&self, declaration: Declaration) -> Option<Spanned<Type>> {
+        let arena = self.arena;
+        let parameters = match declaration {
+            Declaration::Variable(stmt) => match &arena[stmt].0 {
+                Statement::Declaration { manual_type, .. } => return *manual_type,
+                _ => return None,
+            },
+            Declaration::Parameter {
+                owner: Owner::Function(stmt),
+                ..
+            } => match &arena[stmt].0 {
+                Statement::FuncDeclaration { parameters, .. } => parameters.as_deref(),
+                _ => None,
+            },
+            Declaration::Parameter {
+                owner: Owner::Lambda(expr),
+                ..
+            } => match &arena[expr].0 {
+                Expr::Lambda(lambda) => lambda.parameters.as_deref(),
+                _ => None,
+            },
+            _ => None,
+        };
+        let Declaration::Parameter { index, .. } = declaration else {
+            return None;
+        };
+        parameters?.get(index).map(|param| *param.param_value_type)
+    }
+
+    fn get(&self, state: &State, declaration: Declaration) -> Nullness {
+        match state.0.get(&declaration) {
+            Some(nullness) => *nullness,
+            None => self
+                .declared(declaration)
+                .map_or(Nullness::Never, |type_name| Nullness::of(type_name.0)),
+        }
+    }
+
+    /// Where two paths meet
+    fn join(&self, state: &State, other: &State) -> State {
+        let declarations: HashSet<Declaration> =
+            state.0.keys().chain(other.0.keys()).copied().collect();
+        let joined = declarations.into_iter().map(|declaration| {
+            let nullness = self
+                .get(state, declaration)
+                .join(self.get(other, declaration));
+            (declaration, nullness)
+        });
+        State(joined.collect())
+    }
+
+    /// Reports `value` if it can be null and has to go where `expected` says it can't
+    fn expect(&mut self, value: ExprId, nullness: Nullness, expected: Spanned<Type>) {
+        if expected.0.nullable {
+            return;
+        }
+        let type_name = self.text(expected.1);
+        if let Some(diagnostic) = self.mismatch(value, nullness, type_name) {
+            let label = format!("declare it as `{type_name}?` to allow null");
+            self.report(diagnostic.with_label(expected.1, label));
+        }
+    }
+
+    fn mismatch(&self, value: ExprId, nullness: Nullness, type_name: &str) -> Option<Diagnostic> {
+        let span = self.arena[value].1;
+        let message = match nullness {
+            Nullness::Never => return None,
+            Nullness::Maybe => format!(
+                "Expected `{type_name}`, found `{}` which might be null",
+                self.text(span)
+            ),
+            Nullness::Always => format!("Expected `{type_name}`, found `null`"),
+        };
+        Some(Diagnostic::error(message, span))
+    }
+
+    /// Reports an operand of `operator` that can be null
+    fn operand(&mut self, value: ExprId, nullness: Nullness, operator: Span) {
+        let operator = self.text(operator);
+        let message = match nullness {
+            Nullness::Never => return,
+            Nullness::Maybe => {
+                let value = self.text(self.arena[value].1);
+                format!("`{value}` might be null, it can't be used with `{operator}`")
+            }
+            Nullness::Always => format!("`null` can't be used with `{operator}`"),
+        };
+        self.report(Diagnostic::error(message, self.arena[value].1).with_label(
+            self.arena[value].1,
+            "check it for null or default it with `??`",
+        ));
+    }
+
+    //----------------------------------------------------------------
+    //-Statements-----------------------------------------------------
+    //----------------------------------------------------------------
+
+    fn body(&mut self, statments: &[StmtId], mut state: State, return_type: Option<Spanned<Type>>) {
+        let Some((last, statments)) = statments.split_last() else {
+            return;
+        };
+        self.statements(statments, &mut state);
+        // The last expression is what the function returns
+        match (&self.arena[*last].0, return_type) {
+            (Statement::Expression { expr }, Some(return_type)) => {
+                let nullness = self.expr(*expr, &mut state);
+                self.expect(*expr, nullness, return_type);
+            }
+            _ => self.statement(*last, &mut state),
+        }
+    }
+
+    fn statements(&mut self, statements: &[StmtId], state: &mut State) {
+        for stmt in statements {
+            self.statement(*stmt, state);
+        }
+    }
+
+    fn statement(&mut self, id: StmtId, state: &mut State) {
+        let arena = self.arena;
+        match &arena[id].0 {
+            Statement::Error | Statement::Empty | Statement::Extern(_) | Statement::Use { .. } => {}
+            Statement::Test(expr) | Statement::Expression { expr } => {
+                self.expr(*expr, state);
+            }
+            Statement::TestDeclaration { statments, .. } => {
+                self.statements(statments, &mut state.clone())
+            }
+            Statement::Block { statments } => self.statements(statments, state),
+            Statement::Declaration {
+                manual_type, value, ..
+            } => {
+                let nullness = self.expr(*value, state);
+                if let Some(manual_type) = manual_type {
+                    self.expect(*value, nullness, *manual_type);
+                }
+                let nullness = match manual_type {
+                    Some(Spanned(
+                        Type {
+                            nullable: false, ..
+                        },
+                        _,
+                    )) => Nullness::Never,
+                    _ => nullness,
+                };
+                state.0.insert(Declaration::Variable(id), nullness);
+            }
+            Statement::Assign(assign) => {
+                let nullness = self.expr(assign.value, state);
+                let Some(declaration) = self.resolution.stmt(id) else {
+                    return;
+                };
+                let declared = self.declared(declaration);
+                let current = self.get(state, declaration);
+                let nullness = match assign.operator.0 {
+                    AssignOp::Set => {
+                        if let Some(declared) = declared {
+                            self.expect(assign.value, nullness, declared);
+                        }
+                        nullness
+                    }
+                    AssignOp::SetIfNull => {
+                        if let Some(declared) = declared.filter(|declared| !declared.0.nullable) {
+                            let name = self.text(assign.name.1);
+                            let type_name = self.text(declared.1);
+                            self.report(
+                                Diagnostic::warning(
+                                    format!("`{name}` is never null, so `?=` never assigns"),
+                                    assign.operator.1,
+                                )
+                                .with_label(declared.1, format!("declared as `{type_name}` here")),
+                            );
+                        }
+                        current.or(nullness)
+                    }
+                    _ => {
+                        if current != Nullness::Never {
+                            let name = self.text(assign.name.1);
+                            let operator = self.text(assign.operator.1);
+                            let message = match current {
+                                Nullness::Always => {
+                                    format!("`{name}` is null, it can't be used with `{operator}`")
+                                }
+                                _ => format!(
+                                    "`{name}` might be null, it can't be used with `{operator}`"
+                                ),
+                            };
+                            self.report(Diagnostic::error(message, assign.name.1));
+                        }
+                        self.operand(assign.value, nullness, assign.operator.1);
+                        Nullness::Never
+                    }
+                };
+                let nullness = match declared {
+                    Some(Spanned(
+                        Type {
+                            nullable: false, ..
+                        },
+                        _,
+                    )) => Nullness::Never,
+                    _ => nullness,
+                };
+                state.0.insert(declaration, nullness);
+            }
+            Statement::FuncDeclaration {
+                return_type,
+                statments,
+                ..
+            } => {
+                // Functions run later, so only what their types say is known about globals
+                self.body(statments, State::default(), *return_type);
+            }
+            Statement::IfStmt {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(*condition, state);
+                let facts = self.facts(*condition);
+                let mut then_state = state.clone();
+                then_state.0.extend(facts.when_true);
+                self.statement(*then_branch, &mut then_state);
+                let mut else_state = state.clone();
+                else_state.0.extend(facts.when_false);
+                if let Some(else_branch) = else_branch {
+                    self.statement(*else_branch, &mut else_state);
+                }
+                *state = self.join(&then_state, &else_state);
+            }
+            Statement::MatchStmt(match_stmt) => {
+                self.expr(match_stmt.predicate, state);
+                // No arm might match
+                let mut joined = state.clone();
+                for (_, arm) in &match_stmt.then_branches.0 {
+                    let mut arm_state = state.clone();
+                    self.statement(*arm, &mut arm_state);
+                    joined = self.join(&joined, &arm_state);
+                }
+                *state = joined;
+            }
+            Statement::WhileStmt {
+                condition,
+                then_branch,
+            } => {
+                // The second walk starts from what the first one could leave behind
+                let quiet = self.quiet;
+                let mut start = state.clone();
+                for walk in 0..2 {
+                    self.quiet = quiet || walk == 0;
+                    self.expr(*condition, &mut start);
+                    let mut body_state = start.clone();
+                    body_state.0.extend(self.facts(*condition).when_true);
+                    self.statement(*then_branch, &mut body_state);
+                    start = self.join(&start, &body_state);
+                }
+                self.quiet = quiet;
+                *state = start;
+                state.0.extend(self.facts(*condition).when_false);
+            }
+            Statement::With {
+                value, statments, ..
+            } => {
+                let nullness = self.expr(*value, state);
+                state.0.insert(Declaration::Variable(id), nullness);
+                self.statements(statments, state);
+            }
+            Statement::Defer { defered_statment } => {
+                self.statement(*defered_statment, &mut state.clone())
+            }
+        }
+    }
+
+    //----------------------------------------------------------------
+    //-Expressions----------------------------------------------------
+    //----------------------------------------------------------------
+
+    fn expr(&mut self, id: ExprId, state: &mut State) -> Nullness {
+        let arena = self.arena;
+        match &arena[id].0 {
+            Expr::Literal(literal) => match literal.value.0 {
+                Object::NullValue => Nullness::Always,
+                _ => Nullness::Never,
+            },
+            Expr::Variable { .. } => match self.resolution.expr(id) {
+                Some(declaration) => self.get(state, declaration),
+                None => Nullness::Never,
+            },
+            Expr::Unary(unary) => {
+                let nullness = self.expr(unary.right, state);
+                if unary.operator.0 == UnaryOp::Neg {
+                    self.operand(unary.right, nullness, unary.operator.1);
+                }
+                Nullness::Never
+            }
+            Expr::Binary(binary) => self.binary(binary, state),
+            Expr::TenaryIfStmt {
+                condition,
+                value,
+                else_value,
+            } => {
+                self.expr(*condition, state);
+                let facts = self.facts(*condition);
+                let mut value_state = state.clone();
+                value_state.0.extend(facts.when_true);
+                let mut else_state = state.clone();
+                else_state.0.extend(facts.when_false);
+                let value = self.expr(*value, &mut value_state);
+                let else_value = self.expr(*else_value, &mut else_state);
+                value.join(else_value)
+            }
+            Expr::FunctionCall {
+                callee,
+                arguments,
+                safe,
+            } => self.call(
+                *callee,
+                arguments.as_deref().unwrap_or_default(),
+                *safe,
+                state,
+            ),
+            Expr::Lambda(lambda) => {
+                self.body(&lambda.statments, state.clone(), lambda.return_type);
+                Nullness::Never
+            }
+            // Null is formatted like any other value
+            Expr::Interpolation { arguments, .. } => {
+                for arg in arguments {
+                    self.expr(*arg, state);
+                }
+                Nullness::Never
+            }
+        }
+    }
+
+    fn binary(&mut self, binary: &Binary, state: &mut State) -> Nullness {
+        let left = self.expr(binary.left, state);
+        let right = self.expr(binary.right, state);
+        match binary.operator.0 {
+            BinaryOp::Coalesce => left.or(right),
+            BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::And | BinaryOp::Or => Nullness::Never,
+            _ => {
+                self.operand(binary.left, left, binary.operator.1);
+                self.operand(binary.right, right, binary.operator.1);
+                Nullness::Never
+            }
+        }
+    }
+
+    /// Arguments are checked against the parameters of declared and `extern` functions,
+    /// the receiver of a safe call is known not to be null when the call is made
+    fn call(
+        &mut self,
+        callee: ExprId,
+        arguments: &[ExprId],
+        safe: bool,
+        state: &mut State,
+    ) -> Nullness {
+        let arena = self.arena;
+        self.expr(callee, state);
+        let mut receiver = Nullness::Never;
+        let mut nullness = vec![];
+        for (i, arg) in arguments.iter().enumerate() {
+            let mut arg_nullness = self.expr(*arg, state);
+            if safe && i == 0 {
+                receiver = arg_nullness;
+                arg_nullness = Nullness::Never;
+            }
+            nullness.push(arg_nullness);
+        }
+        let declaration = match self.resolution.expr(callee) {
+            Some(Declaration::Function(stmt))
+                if matches!(arena[callee].0, Expr::Variable { .. }) =>
+            {
+                Some(&arena[stmt].0)
+            }
+            _ => None,
+        };
+        let returned = match declaration {
+            Some(Statement::FuncDeclaration {
+                parameters,
+                return_type,
+                ..
+            }) => {
+                let parameters = parameters.as_deref().unwrap_or_default();
+                self.arguments(parameters, arguments, &nullness, state);
+                return_type.map_or(Nullness::Never, |return_type| Nullness::of(return_type.0))
+            }
+            Some(Statement::Extern(declaration)) => {
+                for ((_, c_type), (arg, arg_nullness)) in declaration
+                    .parameters
+                    .iter()
+                    .zip(arguments.iter().zip(&nullness))
+                {
+                    // C can take a null pointer, nothing else
+                    if matches!(c_type.0, CType::CStr | CType::Ptr) {
+                        continue;
+                    }
+                    if let Some(diagnostic) = self.mismatch(*arg, *arg_nullness, c_type.0.name()) {
+                        self.report(diagnostic);
+                    }
+                }
+                match declaration.return_type.map(|c_type| c_type.0) {
+                    None => Nullness::Always,
+                    Some(CType::CStr) => Nullness::Maybe,
+                    Some(_) => Nullness::Never,
+                }
+            }
+            _ => Nullness::Never,
+        };
+        match receiver {
+            Nullness::Never => returned,
+            Nullness::Maybe => Nullness::Maybe.join(returned),
+            Nullness::Always => Nullness::Always,
+        }
+    }
+
+    fn arguments(
+        &mut self,
+        parameters: &[FuncParameter],
+        arguments: &[ExprId],
+        nullness: &[Nullness],
+        state: &mut State,
+    ) {
+        for (param, (arg, arg_nullness)) in parameters.iter().zip(arguments.iter().zip(nullness)) {
+            let param_type = *param.param_value_type;
+            self.expect(*arg, *arg_nullness, param_type);
+            // `ref` and `out` parameters can leave anything their type allows in the variable
+            if param.is_alias() {
+                if let Some(declaration) = self.resolution.expr(*arg) {
+                    state.0.insert(declaration, Nullness::of(param_type.0));
+                }
+            }
+        }
+    }
+
+    /// What `condition` says about the variables it compares with `null`
+    fn facts(&self, condition: ExprId) -> Facts {
+        let arena = self.arena;
+        match &arena[condition].0 {
+            Expr::Unary(unary) if unary.operator.0 == UnaryOp::Not => {
+                let facts = self.facts(unary.right);
+                Facts {
+                    when_true: facts.when_false,
+                    when_false: facts.when_true,
+                }
+            }
+            Expr::Binary(binary) => match binary.operator.0 {
+                BinaryOp::Equal | BinaryOp::NotEqual => {
+                    let compared = match (&arena[binary.left].0, &arena[binary.right].0) {
+                        (Expr::Variable { .. }, Expr::Literal(literal))
+                            if literal.value.0 == Object::NullValue =>
+                        {
+                            self.resolution.expr(binary.left)
+                        }
+                        (Expr::Literal(literal), Expr::Variable { .. })
+                            if literal.value.0 == Object::NullValue =>
+                        {
+                            self.resolution.expr(binary.right)
+                        }
+                        _ => None,
+                    };
+                    let Some(declaration) = compared else {
+                        return Facts::default();
+                    };
+                    let (null, not_null) = (
+                        vec![(declaration, Nullness::Always)],
+                        vec![(declaration, Nullness::Never)],
+                    );
+                    match binary.operator.0 {
+                        BinaryOp::Equal => Facts {
+                            when_true: null,
+                            when_false: not_null,
+                        },
+                        _ => Facts {
+                            when_true: not_null,
+                            when_false: null,
+                        },
+                    }
+                }
+                // Both sides are true when `and` is, and both are false when `or` isn't
+                BinaryOp::And => Facts {
+                    when_true: [self.facts(binary.left), self.facts(binary.right)]
+                        .into_iter()
+                        .flat_map(|facts| facts.when_true)
+                        .collect(),
+                    when_false: vec![],
+                },
+                BinaryOp::Or => Facts {
+                    when_true: vec![],
+                    when_false: [self.facts(binary.left), self.facts(binary.right)]
+                        .into_iter()
+                        .flat_map(|facts| facts.when_false)
+                        .collect(),
+                },
+                _ => Facts::default(),
+            },
+            _ => Facts::default(),
+        }
+    }
+}
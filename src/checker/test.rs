@@ -0,0 +1,572 @@
+use crate::{
+    ast::{expr::Expr, stmt::Statement},
+    lexer::lexer::scan,
+    parser::parser::{get_stream, try_parse},
+    Engine,
+};
+
+use super::{
+    attributes::{AttributeSpec, Target},
+    checker::{check, Diagnostic, Severity},
+    names::{resolve, Declaration, Environment, Owner},
+};
+
+fn check_source(source: &str) -> Vec<Diagnostic> {
+    let tokens = scan(source).unwrap();
+    let program = try_parse(get_stream((tokens, source))).unwrap();
+    check(source, &program, &Environment::prelude())
+}
+
+/// The message of every error, followed by its labels as `text: message`
+fn diagnostics(source: &str) -> Vec<Vec<String>> {
+    check_source(source)
+        .into_iter()
+        .filter(Diagnostic::is_error)
+        .map(|diagnostic| {
+            let mut lines = vec![diagnostic.message];
+            lines.extend(
+                diagnostic
+                    .labels
+                    .into_iter()
+                    .map(|(span, message)| format!("{}: {message}", &source[span.into_range()])),
+            );
+            lines
+        })
+        .collect()
+}
+
+#[test]
+fn out_parameters_are_assigned_on_every_path() {
+    let source = "fn sign(val n: int, out s: int) {\nif n < 0 { s = -1 } else { s = 1 }\n}\nfn pick(val n: int, out s: int) {\nif n < 0 { s = -1 }\n}\nfn never(out s: int) {\nprintln();\n}";
+    assert_eq!(
+        vec![
+            vec![
+                "`out` parameter `s` isn't assigned on every path".to_string(),
+                "n < 0: it isn't assigned when this is false".to_string()
+            ],
+            vec!["`out` parameter `s` is never assigned".to_string()],
+        ],
+        diagnostics(source)
+    );
+    let source = "fn m(val n: int, out s: int) {\nmatch n {\n1 => s = 1,\n_ => println(),\n}\n}\nfn w(out s: int) {\nwhile false { s = 1 }\n}\nfn d(out s: int) {\ndefer s = 1;\n}";
+    let found = diagnostics(source);
+    assert_eq!(2, found.len());
+    assert_eq!("println(): this arm doesn't assign it", found[0][1]);
+    assert_eq!(
+        "false: it isn't assigned if the loop doesn't run",
+        found[1][1]
+    );
+}
+
+#[test]
+fn deferred_assignments_count_where_the_defer_is_reached() {
+    let source = "fn d(out s: int) {\ndefer s = 1;\n}\nfn c(val n: int, out s: int) {\nif n > 0 { defer s = 1 }\n}";
+    assert_eq!(
+        vec![vec![
+            "`out` parameter `s` isn't assigned on every path".to_string(),
+            "defer s = 1: this `defer` might not be reached".to_string()
+        ]],
+        diagnostics(source)
+    );
+}
+
+#[test]
+fn reads_need_an_assignment() {
+    assert_eq!(
+        vec![vec!["`s` is read before it's assigned".to_string()]],
+        diagnostics("fn f(out s: int) {\nlet t = s;\ns = 1;\n}")
+    );
+    assert_eq!(
+        vec![vec!["`s` is read before it's assigned".to_string()]],
+        diagnostics("fn f(out s: int) {\ns += 1;\n}")
+    );
+    assert_eq!(
+        vec![vec![
+            "`y` might not be assigned here".to_string(),
+            "println(): this branch doesn't assign it".to_string()
+        ]],
+        diagnostics("fn f(val x: bool) {\nif x { println() } else { let y = 1; }\nprintln(y);\n}")
+    );
+    // A local read before its declaration is the global of the same name
+    assert!(diagnostics("let x = 1;\nfn f() {\nlet y = x;\nlet x = y;\n}").is_empty());
+    assert!(diagnostics("fn f(out s: int) {\ns = 1;\nlet t = s;\n}").is_empty());
+}
+
+#[test]
+fn immutable_bindings_cant_be_assigned() {
+    assert_eq!(
+        vec![vec![
+            "Can't assign to `x`, it's immutable".to_string(),
+            "x: declared with `let` here".to_string()
+        ]],
+        diagnostics("let x = 1;\nx = 2;")
+    );
+    let source = "fn f(in a: int, val b: const int, val c: int, ref d: mut int) {\na += 1;\nb = 2;\nc = 3;\nd = 4;\n}";
+    assert_eq!(
+        vec![
+            vec![
+                "Can't use `+=` on `a`, it's immutable".to_string(),
+                "a: `in` parameters are read-only".to_string()
+            ],
+            vec![
+                "Can't assign to `b`, it's immutable".to_string(),
+                "b: declared `const` here".to_string()
+            ],
+            vec![
+                "Can't assign to `c`, it's immutable".to_string(),
+                "c: parameters can only be assigned when they're `mut`".to_string()
+            ],
+        ],
+        diagnostics(source)
+    );
+    assert_eq!(
+        vec![vec![
+            "Can't use `+=` on `count`, it's immutable".to_string(),
+            "count: lambdas get a read-only copy of the variables they capture".to_string()
+        ]],
+        diagnostics("fn counter() {\nvar count = 0;\nlet f = fn() { count += 1 };\n}")
+    );
+    // Globals can be assigned from functions and a local declared later is still the global
+    assert!(diagnostics("var total = 0;\nfn add() {\ntotal += 1;\nlet total = 1;\n}").is_empty());
+}
+
+#[test]
+fn arguments_fit_their_parameter_modes() {
+    let source = "fn set(out v: int) {\nv = 1;\n}
+fn bump(ref target: mut int) {\ntarget += 1;\n}
+fn read(ref n: int), int {\nn = 2;\nn\n}
+fn show(in n: int) {\nbump(n);\n}
+let fixed = 1;
+var counter = 0;
+set(1);
+bump(fixed);
+println(read(2 + 3), read(set));
+set(counter);
+bump(counter);
+println(read(fixed));
+show(fixed);";
+    let only_variables = "Only variables can be passed to `ref` and `out` parameters";
+    assert_eq!(
+        vec![
+            vec![
+                "Can't assign to `n`, it's immutable".to_string(),
+                "n: parameters can only be assigned when they're `mut`".to_string()
+            ],
+            vec![
+                "Can't pass `n` to `target`, it's immutable".to_string(),
+                "n: `in` parameters are read-only".to_string(),
+                "target: it can assign to the variable passed to it".to_string()
+            ],
+            vec![
+                only_variables.to_string(),
+                "v: it aliases the variable passed to it".to_string()
+            ],
+            vec![
+                "Can't pass `fixed` to `target`, it's immutable".to_string(),
+                "fixed: declared with `let` here".to_string(),
+                "target: it can assign to the variable passed to it".to_string()
+            ],
+            vec![
+                only_variables.to_string(),
+                "n: it aliases the variable passed to it".to_string()
+            ],
+            vec![
+                only_variables.to_string(),
+                "n: it aliases the variable passed to it".to_string()
+            ],
+        ],
+        diagnostics(source)
+    );
+    // Each call is checked against the function it resolves to, not another of its name
+    let source =
+        "fn a() {\nfn helper(out x: int) {\nx = 1;\n}\nvar v = 0;\nhelper(v);\nprintln(v);\n}
+fn b() {\nfn helper(val x: int) {\nprintln(x);\n}\nhelper(1);\n}
+a();\nb();";
+    assert!(
+        check_source(source).is_empty(),
+        "{:?}",
+        check_source(source)
+    );
+}
+
+#[test]
+fn each_parameter_mode_is_enforced() {
+    let error = |lines: &[&str]| {
+        lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>()
+    };
+    let only_variables = "Only variables can be passed to `ref` and `out` parameters";
+    // `val` copies, so it takes any value and is only assigned when it's `mut`
+    assert!(diagnostics("fn f(val a: mut int) {\na += 1;\n}\nf(1 + 2);").is_empty());
+    // `ref` aliases a variable, and only assigns it when it's `mut`
+    assert!(diagnostics("let x = 1;\nfn f(ref a: int), int {\na\n}\nprintln(f(x));").is_empty());
+    assert_eq!(
+        vec![error(&[
+            only_variables,
+            "a: it aliases the variable passed to it"
+        ])],
+        diagnostics("fn f(ref a: int), int {\na\n}\nprintln(f(1));")
+    );
+    assert!(diagnostics("var x = 1;\nfn f(ref a: mut int) {\na += 1;\n}\nf(x);").is_empty());
+    assert_eq!(
+        vec![error(&[
+            "Can't pass `x` to `a`, it's immutable",
+            "x: declared with `let` here",
+            "a: it can assign to the variable passed to it"
+        ])],
+        diagnostics("let x = 1;\nfn f(ref a: mut int) {\na += 1;\n}\nf(x);")
+    );
+    // `out` needs a variable it can assign
+    assert!(
+        diagnostics("var x = 0;\nfn f(out a: int) {\na = 1;\n}\nf(x);\nprintln(x);").is_empty()
+    );
+    assert_eq!(
+        vec![error(&[
+            only_variables,
+            "a: it aliases the variable passed to it"
+        ])],
+        diagnostics("fn f(out a: int) {\na = 1;\n}\nf(println);")
+    );
+    assert_eq!(
+        vec![error(&[
+            "Can't pass `x` to `a`, it's immutable",
+            "x: declared with `let` here",
+            "a: it can assign to the variable passed to it"
+        ])],
+        diagnostics("let x = 0;\nfn f(out a: int) {\na = 1;\n}\nf(x);")
+    );
+    // `in` is read-only, so it can't be handed on to a parameter that assigns either
+    let source = "fn g(out b: int) {\nb = 1;\n}\nfn f(in a: int) {\na = 1;\ng(a);\n}\nf(1);";
+    assert_eq!(
+        vec![
+            error(&[
+                "Can't assign to `a`, it's immutable",
+                "a: `in` parameters are read-only"
+            ]),
+            error(&[
+                "Can't pass `a` to `b`, it's immutable",
+                "a: `in` parameters are read-only",
+                "b: it can assign to the variable passed to it"
+            ]),
+        ],
+        diagnostics(source)
+    );
+}
+
+#[test]
+fn warns_about_vars_that_never_change() {
+    let source = "var x = 1;\nprintln(x);\nvar y = 2;\ny += 1;\nfn set(out v: int) {\nv = 1;\n}\nvar z = 0;\nset(z);";
+    let found = check_source(source);
+    assert_eq!(1, found.len());
+    assert_eq!(Severity::Warning, found[0].severity);
+    assert_eq!(
+        "`x` is never mutated, declare it with `let`",
+        found[0].message
+    );
+    assert_eq!("x", &source[found[0].span.into_range()]);
+}
+
+#[test]
+fn names_have_to_be_declared() {
+    assert_eq!(
+        vec![
+            vec!["Can't find `cout`, did you mean `count`?".to_string()],
+            vec!["Can't find `lenn`, did you mean `len`?".to_string()],
+            vec!["Can't find `missing`".to_string()],
+        ],
+        diagnostics("var count = 0;\nfn bump() { cout += 1; }\nbump();\nprintln(lenn(\"a\"), missing, count);")
+    );
+    // Calls are only matched to functions and short names to nothing
+    assert_eq!(
+        vec![
+            vec!["Can't find `g`".to_string()],
+            vec!["Can't find `totl`".to_string()],
+            vec!["Can't find `sumup`, did you mean `sum_up`?".to_string()],
+        ],
+        diagnostics("let q = 1;\nlet total = 2;\nfn sum_up() {\nprintln();\n}\ng();\ntotl();\nsumup();\nprintln(q, total);")
+    );
+    assert_eq!(
+        vec![vec![
+            "Can't find type `intt`, did you mean `int`?".to_string()
+        ]],
+        diagnostics("fn f(val n: intt), int { n }\nf(1);")
+    );
+    assert_eq!(
+        vec![vec![
+            "`later` is used before it's declared".to_string(),
+            "later: declared here".to_string()
+        ]],
+        diagnostics("println(later);\nlet later = 1;")
+    );
+    assert_eq!(
+        vec![vec![
+            "`f` is used before it's declared".to_string(),
+            "f: declared here".to_string()
+        ]],
+        diagnostics("f();\nfn f() {\nprintln();\n}")
+    );
+    // Function bodies run once the top level has declared everything they use
+    assert!(diagnostics(
+        "fn f() {\nprintln(g(), later);\n}\nfn g(), int { 1 }\nlet later = 2;\nf();"
+    )
+    .is_empty());
+    // Lambdas run after the function that made them has declared its locals
+    assert!(diagnostics(
+        "fn outer() {\nlet inner = fn() -> later;\nlet later = 1;\nprintln(inner());\n}\nouter();"
+    )
+    .is_empty());
+    let mut engine = Engine::new();
+    engine.register_fn("host", || 1);
+    engine.set_global("limit", 3).unwrap();
+    assert!(engine
+        .compile("println(host(), limit, format(\"limit\"));")
+        .is_ok());
+}
+
+#[test]
+fn declarations_cant_repeat() {
+    assert_eq!(
+        vec![vec![
+            "`a` is already declared".to_string(),
+            "a: first declared here".to_string()
+        ]],
+        diagnostics("fn f(val a: int) {\nlet a = 2;\nprintln(a);\n}\nf(1);")
+    );
+    assert_eq!(
+        vec![vec![
+            "`f` is already declared".to_string(),
+            "f: first declared here".to_string()
+        ]],
+        diagnostics("fn f() {\nprintln();\n}\nfn f() {\nprintln();\n}\nf();")
+    );
+    // Only one branch runs
+    assert!(diagnostics(
+        "fn f(val c: bool) {\nif c { let x = 1; } else { let x = 2; }\nprintln(x);\n}\nf(true);"
+    )
+    .is_empty());
+}
+
+#[test]
+fn format_can_be_declared() {
+    assert!(diagnostics(
+        "fn format(val s: string), string { s }
+fn f(val format: int) { println(format); }
+f(1);
+println(format(\"{1}\"), \"{1}\");"
+    )
+    .is_empty());
+}
+
+#[test]
+fn warns_about_unused_names() {
+    let source = "let x = 1;\nlet _y = 2;\nfn unused(val n: int) {\nprintln();\n}\nfn used() {\nprintln(x);\n}\nused();";
+    let warnings: Vec<_> = check_source(source)
+        .into_iter()
+        .map(|diagnostic| (diagnostic.message, &source[diagnostic.span.into_range()]))
+        .collect();
+    assert_eq!(
+        vec![("function `unused` is never used".to_string(), "unused")],
+        warnings
+    );
+}
+
+#[test]
+fn uses_are_bound_to_declarations() {
+    let source = "let x = 1;\nfn f(val n: int), int { n + x }\nprintln(f(x));";
+    let tokens = scan(source).unwrap();
+    let program = try_parse(get_stream((tokens, source))).unwrap();
+    let (resolution, diagnostics) = resolve(source, &program, &Environment::prelude());
+    assert!(diagnostics.is_empty());
+    let arena = &program.arena;
+    let [declaration, function, call, ..] = program.statements[..] else {
+        panic!("expected three statements");
+    };
+    let Statement::FuncDeclaration { statments, .. } = &arena[function].0 else {
+        panic!("expected a function");
+    };
+    let Statement::Expression { expr } = arena[statments[0]].0 else {
+        panic!("expected an expression");
+    };
+    let Expr::Binary(binary) = &arena[expr].0 else {
+        panic!("expected `n + x`");
+    };
+    assert_eq!(
+        Some(Declaration::Parameter {
+            owner: Owner::Function(function),
+            index: 0
+        }),
+        resolution.expr(binary.left)
+    );
+    assert_eq!(
+        Some(Declaration::Variable(declaration)),
+        resolution.expr(binary.right)
+    );
+    let Statement::Expression { expr } = arena[call].0 else {
+        panic!("expected an expression");
+    };
+    let Expr::FunctionCall {
+        callee, arguments, ..
+    } = &arena[expr].0
+    else {
+        panic!("expected a call");
+    };
+    assert_eq!(Some(Declaration::Native), resolution.expr(*callee));
+    let Expr::FunctionCall { callee, .. } = &arena[arguments.as_ref().unwrap()[0]].0 else {
+        panic!("expected a call");
+    };
+    assert_eq!(
+        Some(Declaration::Function(function)),
+        resolution.expr(*callee)
+    );
+}
+
+#[test]
+fn attributes_are_checked_against_the_registry() {
+    let source = "@inlne\nfn a() { 1; }\n@memoize\ntest \"t\" {}\n@deprecated(\"use b\", 2)\nfn c() { 1; }\n@test\nfn d(val n: int) { n; }\n@deprecated(\"use e\")\n@inline\n@inline\nfn old() { 1; }\na(); c(); d(1); old();";
+    let diagnostics: Vec<_> = check_source(source)
+        .into_iter()
+        .map(|diagnostic| {
+            (
+                diagnostic.severity,
+                diagnostic.message,
+                &source[diagnostic.span.into_range()],
+            )
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                Severity::Warning,
+                "Unknown attribute `@inlne`, did you mean `@inline`?".to_string(),
+                "inlne"
+            ),
+            (
+                Severity::Error,
+                "`@memoize` can't be used on tests".to_string(),
+                "memoize"
+            ),
+            (
+                Severity::Error,
+                "`@deprecated` takes 0 to 1 argument(s), found 2".to_string(),
+                "deprecated"
+            ),
+            (
+                Severity::Error,
+                "`@test` functions can't take parameters".to_string(),
+                "test"
+            ),
+            (
+                Severity::Warning,
+                "`@inline` is repeated".to_string(),
+                "inline"
+            ),
+            (
+                Severity::Warning,
+                "`c` is deprecated, use b".to_string(),
+                "c"
+            ),
+            (
+                Severity::Warning,
+                "`old` is deprecated, use e".to_string(),
+                "old"
+            ),
+        ],
+        diagnostics
+    );
+
+    let mut environment = Environment::prelude();
+    environment.attribute(AttributeSpec::new("route", &[Target::Function], 1..=1));
+    let source = "@route(\"/\")\nfn index() { 1; }\nindex();";
+    let tokens = scan(source).unwrap();
+    let program = try_parse(get_stream((tokens, source))).unwrap();
+    assert!(check(source, &program, &environment).is_empty());
+    let route = program.arena[program.statements[0]].0.attribute("route");
+    assert_eq!(1, route.map_or(0, |route| route.arguments.len()));
+}
+
+#[test]
+fn null_only_goes_in_nullable_types() {
+    let source = "fn half(val x: int), int { x / 2 }
+fn maybe(val x: int), int? { if x > 0: x else null }
+let a: int = null;
+var b: int? = maybe(1);
+println(b + 1, half(b), a);
+if b != null { half(b) }
+while b == null { b = 1 }
+let c: int = b;
+fn first(val x: int?), int { x }";
+    assert_eq!(
+        vec![
+            vec![
+                "Expected `int`, found `null`".to_string(),
+                "int: declare it as `int?` to allow null".to_string()
+            ],
+            vec![
+                "`b` might be null, it can't be used with `+`".to_string(),
+                "b: check it for null or default it with `??`".to_string()
+            ],
+            vec![
+                "Expected `int`, found `b` which might be null".to_string(),
+                "int: declare it as `int?` to allow null".to_string()
+            ],
+            vec![
+                "Expected `int`, found `x` which might be null".to_string(),
+                "int: declare it as `int?` to allow null".to_string()
+            ],
+        ],
+        diagnostics(source)
+    );
+    let source =
+        "var n: int = 1;\nn ?= 2;\nvar m: int? = null;\nm ?= 2;\nlet k: int = m;\nprintln(n, k);";
+    let warnings: Vec<_> = check_source(source)
+        .into_iter()
+        .map(|diagnostic| (diagnostic.severity, diagnostic.message))
+        .collect();
+    assert_eq!(
+        vec![(
+            Severity::Warning,
+            "`n` is never null, so `?=` never assigns".to_string()
+        )],
+        warnings
+    );
+}
+
+#[test]
+fn with_names_and_hooks() {
+    let source = "@enter(1)\n@exit(\"shut\")\nfn open(), int { 1 }\n@enter(\"start\")\nfn begin(), int { 2 }\nfn shut(val x: int) { x; }\nwith f = open() { f; }\nf;";
+    let diagnostics: Vec<_> = check_source(source)
+        .into_iter()
+        .map(|diagnostic| (diagnostic.message, &source[diagnostic.span.into_range()]))
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                "`@enter` takes the name of the function to call".to_string(),
+                "enter"
+            ),
+            (
+                "`@enter` needs an `@exit` to go with it".to_string(),
+                "enter"
+            ),
+            ("function `begin` is never used".to_string(), "begin"),
+            ("Can't find `f`".to_string(), "f"),
+        ],
+        diagnostics
+    );
+    let source = "@exit(\"shut\")\nfn open(), int { 1 }\nfn plain(), int { 2 }\nfn shut(val x: int) { x; }\nlet h = open();\nwith f = h { f; }\nwith g = plain() { g; }\nwith k = open() { k; }";
+    let diagnostics: Vec<_> = check_source(source)
+        .into_iter()
+        .map(|diagnostic| {
+            let labels: Vec<_> = diagnostic
+                .labels
+                .iter()
+                .map(|(span, _)| &source[span.into_range()])
+                .collect();
+            (&source[diagnostic.span.into_range()], labels)
+        })
+        .collect();
+    // Only the values that don't come straight from `open`
+    assert_eq!(vec![("h", vec![]), ("plain()", vec!["plain"])], diagnostics);
+}
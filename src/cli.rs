@@ -0,0 +1,3 @@
+pub mod cli;
+#[cfg(test)]
+mod test;
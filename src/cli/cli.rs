@@ -0,0 +1,325 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Parser, Subcommand};
+
+use crate::{
+    ast::stmt::Program,
+    engine::{
+        engine::{Backend, Engine},
+        error::Error,
+    },
+    formatter::formatter::{format_source, FormatConfig},
+    lexer::lexer::scan,
+    lsp,
+    runner::{
+        report::{report, ReportFormat},
+        runner::{self, run_tests, Outcome, TestOptions},
+    },
+    utils::{object::Object, source_map::SourceMap},
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "scorpionest",
+    version,
+    about = "The scorpionest language toolchain"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Runs a script, everything after the file is passed on to the script
+    Run {
+        #[arg(long, value_enum, default_value_t)]
+        engine: Backend,
+        file: PathBuf,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Lexes, parses and checks scripts without running them
+    Check {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Rewrites scripts in the canonical style
+    Fmt {
+        /// Don't write anything, fail if a file isn't formatted
+        #[arg(long)]
+        check: bool,
+        #[arg(long, default_value_t = FormatConfig::default().width)]
+        width: usize,
+        #[arg(long, default_value_t = FormatConfig::default().indent)]
+        indent: usize,
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Runs the `test` declarations of scripts, directories are searched for `.sn` files
+    Test {
+        #[arg(default_value = ".")]
+        paths: Vec<PathBuf>,
+        /// Only runs tests whose name contains this
+        #[arg(long)]
+        filter: Option<String>,
+        /// Runs `@ignore` tests as well
+        #[arg(long)]
+        include_ignored: bool,
+        #[arg(long, value_enum, default_value_t)]
+        format: ReportFormat,
+        #[arg(long, value_enum, default_value_t)]
+        engine: Backend,
+    },
+    /// Starts an interactive session
+    Repl {
+        #[arg(long, value_enum, default_value_t)]
+        engine: Backend,
+    },
+    /// Prints the tokens of a script
+    Tokens { file: PathBuf },
+    /// Prints the syntax tree of a script
+    Ast { file: PathBuf },
+    /// Starts a language server speaking LSP over stdio
+    Lsp,
+}
+
+/// Why a command failed, engine errors are reported against their source before they're returned
+#[derive(Debug, thiserror::Error)]
+pub enum Failure {
+    #[error("{path}: {error}")]
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("{0}")]
+    Engine(Error),
+    /// `fmt --check` found files that aren't formatted
+    #[error("{0} file(s) are not formatted")]
+    Unformatted(usize),
+    #[error("{0} test(s) failed")]
+    TestsFailed(usize),
+    #[error("Language server failed, {0}")]
+    Server(std::io::Error),
+}
+
+impl Failure {
+    /// Exit codes are stable so scripts can tell failures apart,
+    /// 2 is left to clap for usage errors
+    pub fn exit_code(&self) -> u8 {
+        let Failure::Engine(error) = self else {
+            return 1;
+        };
+        match error.innermost() {
+            Error::Lex(_) => 3,
+            Error::Parse(_) => 4,
+            Error::Check(_) => 5,
+            Error::Runtime(_) => 6,
+            _ => 1,
+        }
+    }
+}
+
+pub fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match execute(cli.command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            if !matches!(failure, Failure::Engine(_)) {
+                eprintln!("Error: {failure}");
+            }
+            ExitCode::from(failure.exit_code())
+        }
+    }
+}
+
+pub fn execute(command: Command) -> Result<(), Failure> {
+    match command {
+        Command::Run { engine, file, args } => {
+            let input = read_file(&file)?;
+            let backend = engine;
+            let mut engine = script_engine(args);
+            engine.set_backend(backend);
+            let program = compile(&engine, &file, &input)?;
+            engine.run(&program).map_err(reported(&file, &input))
+        }
+        Command::Check { files } => {
+            for file in files {
+                let input = read_file(&file)?;
+                compile(&script_engine(vec![]), &file, &input)?;
+            }
+            Ok(())
+        }
+        Command::Fmt {
+            check,
+            width,
+            indent,
+            files,
+        } => fmt(&files, check, &FormatConfig { width, indent }),
+        Command::Test {
+            paths,
+            filter,
+            include_ignored,
+            format,
+            engine,
+        } => test(
+            &paths,
+            &TestOptions {
+                filter,
+                include_ignored,
+                backend: engine,
+                search_path: search_path(),
+            },
+            format,
+        ),
+        Command::Repl { engine } => repl(engine),
+        Command::Tokens { file } => {
+            let input = read_file(&file)?;
+            let tokens = scan(&input).map_err(|e| reported(&file, &input)(e.into()))?;
+            for (token, span) in tokens {
+                println!("{span:?} {token:?}");
+            }
+            Ok(())
+        }
+        Command::Ast { file } => {
+            let input = read_file(&file)?;
+            let program = Engine::new()
+                .compile_file(&file, &input)
+                .map_err(reported(&file, &input))?;
+            println!("{program:#?}");
+            Ok(())
+        }
+        Command::Lsp => lsp::lsp::run(std::io::stdin().lock(), std::io::stdout(), search_path())
+            .map_err(Failure::Server),
+    }
+}
+
+/// The directories of `SCORPIONEST_PATH`, where `run` and `test` look for modules
+fn search_path() -> Vec<PathBuf> {
+    std::env::var_os("SCORPIONEST_PATH")
+        .map(|paths| std::env::split_paths(&paths).collect())
+        .unwrap_or_default()
+}
+
+/// An engine with the natives `run` gives scripts to read their arguments, modules are also
+/// looked for in the directories of `SCORPIONEST_PATH`
+fn script_engine(args: Vec<String>) -> Engine {
+    let mut engine = Engine::new();
+    for dir in search_path() {
+        engine.add_search_path(dir);
+    }
+    let count = args.len() as i32;
+    engine
+        .register_fn("arg_count", move || count)
+        .register_fn("arg", move |index: i32| {
+            usize::try_from(index)
+                .ok()
+                .and_then(|index| args.get(index).cloned())
+        });
+    engine
+}
+
+fn read_file(path: &PathBuf) -> Result<String, Failure> {
+    std::fs::read_to_string(path).map_err(|error| Failure::Io {
+        path: path.clone(),
+        error,
+    })
+}
+
+/// Compiles `input`, read from `file`, reporting any warnings on the way
+fn compile(engine: &Engine, file: &Path, input: &str) -> Result<Program, Failure> {
+    let (program, warnings) = engine
+        .compile_file_with_warnings(file, input)
+        .map_err(reported(file, input))?;
+    let sources = SourceMap::single(file, input);
+    warnings.iter().for_each(|warning| warning.report(&sources));
+    Ok(program)
+}
+
+/// Reports an engine error against the file it came from
+fn reported(file: &Path, input: &str) -> impl FnOnce(Error) -> Failure {
+    let sources = SourceMap::single(file, input);
+    move |error| {
+        error.report(&sources);
+        Failure::Engine(error)
+    }
+}
+
+fn fmt(files: &[PathBuf], check: bool, config: &FormatConfig) -> Result<(), Failure> {
+    let mut unformatted = 0;
+    for file in files {
+        let input = read_file(file)?;
+        let output = format_source(&input, config).map_err(|error| {
+            eprintln!("Failed to format {}", file.display());
+            reported(file, &input)(error)
+        })?;
+        if input == output {
+            continue;
+        }
+        if check {
+            eprintln!("{} is not formatted", file.display());
+            unformatted += 1;
+        } else {
+            std::fs::write(file, output).map_err(|error| Failure::Io {
+                path: file.clone(),
+                error,
+            })?;
+        }
+    }
+    match unformatted {
+        0 => Ok(()),
+        count => Err(Failure::Unformatted(count)),
+    }
+}
+
+fn test(paths: &[PathBuf], options: &TestOptions, format: ReportFormat) -> Result<(), Failure> {
+    let files = runner::discover(paths).map_err(|error| Failure::Io {
+        path: paths.first().cloned().unwrap_or_default(),
+        error,
+    })?;
+    let mut results = vec![];
+    for file in files {
+        let input = read_file(&file)?;
+        results.extend(run_tests(&file, &input, options).map_err(reported(&file, &input))?);
+    }
+    print!("{}", report(&results, format));
+    match results
+        .iter()
+        .filter(|result| matches!(result.outcome, Outcome::Failed(_)))
+        .count()
+    {
+        0 => Ok(()),
+        failed => Err(Failure::TestsFailed(failed)),
+    }
+}
+
+/// Every line is run by the same engine, expressions print their value
+fn repl(backend: Backend) -> Result<(), Failure> {
+    let mut engine = Engine::new();
+    engine.set_backend(backend);
+    let mut line = String::new();
+    loop {
+        print!("> ");
+        let _ = std::io::stdout().flush();
+        line.clear();
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) => return Ok(()),
+            Ok(_) => (),
+            Err(error) => {
+                return Err(Failure::Io {
+                    path: PathBuf::from("<stdin>"),
+                    error,
+                })
+            }
+        }
+        match engine.eval::<Object>(&line) {
+            Ok(Object::NullValue) => (),
+            Ok(value) => println!("{value}"),
+            Err(error) => error.report(&SourceMap::single("<repl>", line.as_str())),
+        }
+    }
+}
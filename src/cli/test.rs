@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::engine::engine::Backend;
+
+use super::cli::{execute, Cli, Command};
+
+/// A script in the temp directory, removed once the test is done with it
+struct Script(PathBuf);
+
+impl Script {
+    /// The process id keeps test runs going at the same time from writing the same file
+    fn new(name: &str, source: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("scorpionest_cli_{name}_{}.sn", std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        Script(path)
+    }
+}
+
+impl Drop for Script {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn exit_code(command: Command) -> u8 {
+    execute(command).map_or_else(|failure| failure.exit_code(), |_| 0)
+}
+
+#[test]
+fn run_passes_script_arguments() {
+    let cli = Cli::parse_from(["scorpionest", "run", "main.sn", "--verbose", "input.txt"]);
+    let Command::Run { file, args, .. } = cli.command else {
+        panic!("expected the run command");
+    };
+    assert_eq!(PathBuf::from("main.sn"), file);
+    assert_eq!(vec!["--verbose", "input.txt"], args);
+}
+
+#[test]
+fn exit_codes_per_failure() {
+    let run = |name, source| {
+        let script = Script::new(name, source);
+        exit_code(Command::Run {
+            engine: Backend::default(),
+            file: script.0.clone(),
+            args: vec![],
+        })
+    };
+    assert_eq!(0, run("ok", "let x = 1 + 2;"));
+    assert_eq!(3, run("lex", "let x = \"unterminated;"));
+    assert_eq!(4, run("parse", "let = ;"));
+    assert_eq!(5, run("check", "let x = 1; let x = 2;"));
+    assert_eq!(6, run("runtime", "panic(\"oops\");"));
+    assert_eq!(
+        1,
+        exit_code(Command::Check {
+            files: vec![PathBuf::from("does_not_exist.sn")]
+        })
+    );
+}
@@ -0,0 +1,4 @@
+pub mod cst;
+#[cfg(test)]
+mod test;
+pub mod trivia;
@@ -0,0 +1,311 @@
+use crate::{
+    ast::{
+        arena::{Arena, ExprId, StmtId},
+        expr::Expr,
+        stmt::{Program, Statement},
+    },
+    lexer::lexer::LexingError,
+    utils::spanned::Span,
+};
+
+use super::trivia::{scan_lossless, SyntaxToken, Trivia};
+
+/// The typed AST node a `SyntaxNode` was built from
+#[derive(Debug, Clone, Copy)]
+pub enum AstRef<'a> {
+    Program(&'a Program),
+    Statement(&'a Statement),
+    Expr(&'a Expr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Program,
+    Error,
+    Empty,
+    Test,
+    TestDeclaration,
+    Assign,
+    Block,
+    Expression,
+    Declaration,
+    FuncDeclaration,
+    IfStmt,
+    MatchStmt,
+    WhileStmt,
+    With,
+    Defer,
+    Extern,
+    Use,
+    Binary,
+    Unary,
+    Literal,
+    Variable,
+    TenaryIf,
+    FunctionCall,
+    Lambda,
+    Interpolation,
+}
+
+impl<'a> AstRef<'a> {
+    pub fn kind(&self) -> SyntaxKind {
+        match self {
+            AstRef::Program(_) => SyntaxKind::Program,
+            AstRef::Statement(stmt) => match stmt {
+                Statement::Error => SyntaxKind::Error,
+                Statement::Empty => SyntaxKind::Empty,
+                Statement::Test(_) => SyntaxKind::Test,
+                Statement::TestDeclaration { .. } => SyntaxKind::TestDeclaration,
+                Statement::Assign(_) => SyntaxKind::Assign,
+                Statement::Block { .. } => SyntaxKind::Block,
+                Statement::Expression { .. } => SyntaxKind::Expression,
+                Statement::Declaration { .. } => SyntaxKind::Declaration,
+                Statement::FuncDeclaration { .. } => SyntaxKind::FuncDeclaration,
+                Statement::IfStmt { .. } => SyntaxKind::IfStmt,
+                Statement::MatchStmt(_) => SyntaxKind::MatchStmt,
+                Statement::WhileStmt { .. } => SyntaxKind::WhileStmt,
+                Statement::With { .. } => SyntaxKind::With,
+                Statement::Defer { .. } => SyntaxKind::Defer,
+                Statement::Extern(_) => SyntaxKind::Extern,
+                Statement::Use { .. } => SyntaxKind::Use,
+            },
+            AstRef::Expr(expr) => match expr {
+                Expr::Binary(_) => SyntaxKind::Binary,
+                Expr::Unary(_) => SyntaxKind::Unary,
+                Expr::Literal(_) => SyntaxKind::Literal,
+                Expr::Variable { .. } => SyntaxKind::Variable,
+                Expr::TenaryIfStmt { .. } => SyntaxKind::TenaryIf,
+                Expr::FunctionCall { .. } => SyntaxKind::FunctionCall,
+                Expr::Lambda(_) => SyntaxKind::Lambda,
+                Expr::Interpolation { .. } => SyntaxKind::Interpolation,
+            },
+        }
+    }
+
+    /// The direct children of this node that carry a span
+    pub fn children(&self, arena: &'a Arena) -> Vec<(AstRef<'a>, Span)> {
+        let stmt = |id: &StmtId| {
+            let s = &arena[*id];
+            (AstRef::Statement(&s.0), s.1)
+        };
+        let expr = |id: &ExprId| {
+            let e = &arena[*id];
+            (AstRef::Expr(&e.0), e.1)
+        };
+        match self {
+            AstRef::Program(program) => program.statements.iter().map(stmt).collect(),
+            AstRef::Statement(statement) => match statement {
+                Statement::Error | Statement::Empty => vec![],
+                Statement::Test(e) => vec![expr(e)],
+                Statement::Assign(assign) => vec![expr(&assign.value)],
+                Statement::Block { statments } => statments.iter().map(stmt).collect(),
+                Statement::Expression { expr: e } => vec![expr(e)],
+                Statement::Declaration { value, .. } => vec![expr(value)],
+                Statement::FuncDeclaration { statments, .. }
+                | Statement::TestDeclaration { statments, .. } => {
+                    statments.iter().map(stmt).collect()
+                }
+                Statement::IfStmt {
+                    condition,
+                    then_branch,
+                    else_branch,
+                } => {
+                    let mut children = vec![expr(condition), stmt(then_branch)];
+                    children.extend(else_branch.as_ref().map(stmt));
+                    children
+                }
+                Statement::MatchStmt(match_stmt) => std::iter::once(expr(&match_stmt.predicate))
+                    .chain(match_stmt.then_branches.0.iter().map(|(_, arm)| stmt(arm)))
+                    .collect(),
+                Statement::WhileStmt {
+                    condition,
+                    then_branch,
+                } => vec![expr(condition), stmt(then_branch)],
+                Statement::With {
+                    value, statments, ..
+                } => std::iter::once(expr(value))
+                    .chain(statments.iter().map(stmt))
+                    .collect(),
+                Statement::Defer { defered_statment } => vec![stmt(defered_statment)],
+                Statement::Extern(_) | Statement::Use { .. } => vec![],
+            },
+            AstRef::Expr(e) => match e {
+                Expr::Binary(binary) => vec![expr(&binary.left), expr(&binary.right)],
+                Expr::Unary(unary) => vec![expr(&unary.right)],
+                Expr::Literal(_) | Expr::Variable { .. } => vec![],
+                Expr::TenaryIfStmt {
+                    condition,
+                    value,
+                    else_value,
+                } => vec![expr(condition), expr(value), expr(else_value)],
+                // The receiver of `a?.f(b)` comes before the callee in the source
+                Expr::FunctionCall {
+                    callee,
+                    arguments,
+                    safe: true,
+                } => {
+                    let arguments = arguments.as_deref().unwrap_or_default();
+                    arguments[..1]
+                        .iter()
+                        .chain(std::iter::once(callee))
+                        .chain(&arguments[1..])
+                        .map(expr)
+                        .collect()
+                }
+                Expr::FunctionCall {
+                    callee, arguments, ..
+                } => std::iter::once(expr(callee))
+                    .chain(arguments.iter().flatten().map(expr))
+                    .collect(),
+                Expr::Lambda(lambda) => lambda.statments.iter().map(stmt).collect(),
+                Expr::Interpolation { arguments, .. } => arguments.iter().map(expr).collect(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement<'a> {
+    Node(SyntaxNode<'a>),
+    /// Index into `SyntaxTree::tokens`
+    Token(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxNode<'a> {
+    pub ast: AstRef<'a>,
+    pub span: Span,
+    pub children: Vec<SyntaxElement<'a>>,
+}
+
+impl<'a> SyntaxNode<'a> {
+    pub fn kind(&self) -> SyntaxKind {
+        self.ast.kind()
+    }
+
+    pub fn child_nodes(&self) -> impl Iterator<Item = &SyntaxNode<'a>> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// Indices of every token below this node, in source order
+    pub fn token_indices(&self) -> Vec<usize> {
+        let mut indices = vec![];
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => indices.extend(node.token_indices()),
+                SyntaxElement::Token(index) => indices.push(*index),
+            }
+        }
+        indices
+    }
+}
+
+/// Lossless view of a source file, every byte of the input is held by exactly one
+/// token or piece of trivia, and tokens are grouped into nodes following the AST
+#[derive(Debug, Clone)]
+pub struct SyntaxTree<'a> {
+    pub tokens: Vec<SyntaxToken>,
+    pub root: SyntaxNode<'a>,
+}
+
+impl<'a> SyntaxTree<'a> {
+    pub fn build(input: &str, program: &'a Program) -> Result<SyntaxTree<'a>, LexingError> {
+        let tokens = scan_lossless(input)?;
+        let mut cursor = 0;
+        let root = build_node(
+            AstRef::Program(program),
+            &program.arena,
+            (0..input.len()).into(),
+            &tokens,
+            &mut cursor,
+        );
+        Ok(SyntaxTree { tokens, root })
+    }
+
+    /// Rebuilds the exact input the tree was built from
+    pub fn text(&self) -> String {
+        self.tokens.iter().map(SyntaxToken::full_text).collect()
+    }
+
+    /// The source of a node without the trivia around its first and last token
+    pub fn node_text(&self, node: &SyntaxNode) -> String {
+        let indices = node.token_indices();
+        let mut text = String::new();
+        for (i, index) in indices.iter().enumerate() {
+            let token = &self.tokens[*index];
+            if i > 0 {
+                token.leading.iter().for_each(|t| text.push_str(&t.text));
+            }
+            text.push_str(&token.text);
+            if i + 1 < indices.len() {
+                token.trailing.iter().for_each(|t| text.push_str(&t.text));
+            }
+        }
+        text
+    }
+
+    /// The innermost node whose span contains `offset`
+    pub fn node_at(&self, offset: usize) -> &SyntaxNode<'a> {
+        let mut node = &self.root;
+        while let Some(child) = node
+            .child_nodes()
+            .find(|child| child.span.start <= offset && offset < child.span.end)
+        {
+            node = child;
+        }
+        node
+    }
+
+    /// Every comment in the file, in source order
+    pub fn comments(&self) -> impl Iterator<Item = &Trivia> {
+        self.tokens
+            .iter()
+            .flat_map(|token| token.leading.iter().chain(&token.trailing))
+            .filter(|trivia| trivia.is_comment())
+    }
+}
+
+/// Tokens inside of `span` become children of the node, unless a child AST node covers them
+fn build_node<'a>(
+    ast: AstRef<'a>,
+    arena: &'a Arena,
+    span: Span,
+    tokens: &[SyntaxToken],
+    cursor: &mut usize,
+) -> SyntaxNode<'a> {
+    let mut child_asts = ast.children(arena);
+    child_asts.sort_by_key(|(_, child_span)| child_span.start);
+    let mut child_asts = child_asts.into_iter().peekable();
+    let mut children = vec![];
+    let is_root = matches!(ast, AstRef::Program(_));
+
+    while let Some(token) = tokens.get(*cursor) {
+        if !is_root && token.span.start >= span.end {
+            break;
+        }
+        match child_asts.peek() {
+            Some((_, child_span)) if child_span.start <= token.span.start => {
+                let (child, child_span) = child_asts.next().unwrap();
+                let node = build_node(child, arena, child_span, tokens, cursor);
+                children.push(SyntaxElement::Node(node));
+            }
+            _ => {
+                children.push(SyntaxElement::Token(*cursor));
+                *cursor += 1;
+            }
+        }
+    }
+    // Children not backed by tokens of their own, like the arguments of an interpolated string
+    for (child, child_span) in child_asts {
+        let node = build_node(child, arena, child_span, tokens, cursor);
+        children.push(SyntaxElement::Node(node));
+    }
+    SyntaxNode {
+        ast,
+        span,
+        children,
+    }
+}
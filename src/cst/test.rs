@@ -0,0 +1,51 @@
+use crate::{
+    lexer::lexer::scan,
+    parser::parser::{get_stream, try_parse},
+};
+
+use super::{
+    cst::{SyntaxKind, SyntaxTree},
+    trivia::{scan_lossless, TriviaKind},
+};
+
+const SOURCE: &str =
+    "//! docs\nlet x = 1 + /* two */ 2; // trailing\r\n\n{\n\tx += 3;\n}\n// end\n";
+
+#[test]
+fn lossless_round_trip() {
+    let tokens = scan_lossless(SOURCE).unwrap();
+    let text: String = tokens.iter().map(|token| token.full_text()).collect();
+    assert_eq!(SOURCE, text);
+
+    let semi = tokens.iter().find(|token| token.text == ";").unwrap();
+    assert_eq!(
+        vec![TriviaKind::Whitespace, TriviaKind::LineComment],
+        semi.trailing.iter().map(|t| t.kind).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn syntax_tree_nodes() {
+    let program = try_parse(get_stream((scan(SOURCE).unwrap(), SOURCE))).unwrap();
+    let tree = SyntaxTree::build(SOURCE, &program).unwrap();
+    assert_eq!(SOURCE, tree.text());
+
+    let kinds: Vec<_> = tree.root.child_nodes().map(|node| node.kind()).collect();
+    assert_eq!(vec![SyntaxKind::Declaration, SyntaxKind::Block], kinds);
+
+    let offset = SOURCE.find('2').unwrap();
+    let literal = tree.node_at(offset);
+    assert_eq!(SyntaxKind::Literal, literal.kind());
+    assert_eq!("2", tree.node_text(literal));
+
+    let binary = tree
+        .root
+        .child_nodes()
+        .next()
+        .unwrap()
+        .child_nodes()
+        .next()
+        .unwrap();
+    assert_eq!("1 + /* two */ 2", tree.node_text(binary));
+    assert_eq!(3, tree.comments().count());
+}
@@ -0,0 +1,143 @@
+use crate::{
+    lexer::{
+        lexer::{scan, LexingError},
+        token::TokenType,
+    },
+    utils::spanned::Span,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    LineComment,
+    BlockComment,
+}
+
+/// Source text the parser never sees, kept so the input can be rebuilt exactly
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+impl Trivia {
+    pub fn is_comment(&self) -> bool {
+        matches!(
+            self.kind,
+            TriviaKind::LineComment | TriviaKind::BlockComment
+        )
+    }
+}
+
+/// A token together with its text and surrounding trivia
+///
+/// Trailing trivia is whatever follows the token on the same line,
+/// everything else before the next token is that token's leading trivia
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxToken {
+    pub kind: TokenType,
+    pub text: String,
+    pub span: Span,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+impl SyntaxToken {
+    /// The token text preceded and followed by its trivia
+    pub fn full_text(&self) -> String {
+        let mut text = String::new();
+        self.leading.iter().for_each(|t| text.push_str(&t.text));
+        text.push_str(&self.text);
+        self.trailing.iter().for_each(|t| text.push_str(&t.text));
+        text
+    }
+}
+
+/// Lexes `input` without dropping anything, the last token is always an empty
+/// `TokenType::EOF` holding the trivia at the end of the input
+pub fn scan_lossless(input: &str) -> Result<Vec<SyntaxToken>, LexingError> {
+    let mut tokens = scan(input)?;
+    tokens.push((TokenType::EOF, (input.len()..input.len()).into()));
+
+    let mut result: Vec<SyntaxToken> = Vec::with_capacity(tokens.len());
+    let mut pos = 0;
+    for (kind, span) in tokens {
+        let mut gap = split_trivia(&input[pos..span.start], pos).into_iter();
+        if let Some(previous) = result.last_mut() {
+            while let Some(trivia) = gap.as_slice().first() {
+                if trivia.kind == TriviaKind::Newline {
+                    break;
+                }
+                previous.trailing.push(trivia.clone());
+                gap.next();
+            }
+        }
+        result.push(SyntaxToken {
+            kind,
+            text: input[span.into_range()].to_string(),
+            span,
+            leading: gap.collect(),
+            trailing: vec![],
+        });
+        pos = span.end;
+    }
+    Ok(result)
+}
+
+/// Splits the text between two tokens into whitespace, newlines and comments
+fn split_trivia(gap: &str, offset: usize) -> Vec<Trivia> {
+    let mut trivia = vec![];
+    let mut pos = 0;
+    while pos < gap.len() {
+        let rest = &gap[pos..];
+        let (kind, len) = if rest.starts_with("//") {
+            (
+                TriviaKind::LineComment,
+                rest.find('\n').unwrap_or(rest.len()),
+            )
+        } else if rest.starts_with("/*") {
+            (TriviaKind::BlockComment, block_comment_len(rest))
+        } else if rest.starts_with('\n') {
+            (TriviaKind::Newline, 1)
+        } else if rest.starts_with("\r\n") {
+            (TriviaKind::Newline, 2)
+        } else {
+            let len = rest
+                .char_indices()
+                .skip(1)
+                .find(|(i, c)| !c.is_whitespace() || rest[*i..].starts_with(['\n', '\r']))
+                .map_or(rest.len(), |(i, _)| i);
+            (TriviaKind::Whitespace, len)
+        };
+        trivia.push(Trivia {
+            kind,
+            text: rest[..len].to_string(),
+            span: (offset + pos..offset + pos + len).into(),
+        });
+        pos += len;
+    }
+    trivia
+}
+
+fn block_comment_len(comment: &str) -> usize {
+    let bytes = comment.as_bytes();
+    let mut depth = 0;
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match &bytes[pos..] {
+            [b'/', b'*', ..] => depth += 1,
+            [b'*', b'/', ..] => depth -= 1,
+            _ => {
+                pos += 1;
+                continue;
+            }
+        }
+        pos += 2;
+        if depth == 0 {
+            return pos;
+        }
+    }
+    bytes.len()
+}
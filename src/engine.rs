@@ -0,0 +1,5 @@
+pub mod convert;
+pub mod engine;
+pub mod error;
+#[cfg(test)]
+mod test;
@@ -0,0 +1,200 @@
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+use crate::utils::{
+    error::{RuntimeError, RuntimeResult},
+    interner::INTERNER,
+    object::Object,
+};
+
+/// Rust values a script value can be turned into
+pub trait FromObject: Sized {
+    fn from_object(object: Object) -> RuntimeResult<Self>;
+}
+
+/// Rust values that can be handed to a script
+pub trait IntoObject {
+    fn into_object(self) -> RuntimeResult<Object>;
+}
+
+fn mismatch<T>(expected: &str, found: Object) -> RuntimeResult<T> {
+    Err(RuntimeError::new(format!(
+        "Expected {expected}, found '{found}'!"
+    )))
+}
+
+impl FromObject for Object {
+    fn from_object(object: Object) -> RuntimeResult<Self> {
+        Ok(object)
+    }
+}
+
+impl FromObject for i32 {
+    fn from_object(object: Object) -> RuntimeResult<Self> {
+        match object {
+            Object::Integer(i) => Ok(i),
+            Object::Long(l) => i32::try_from(l).or_else(|_| mismatch("an int", object)),
+            _ => mismatch("an int", object),
+        }
+    }
+}
+
+impl FromObject for i64 {
+    fn from_object(object: Object) -> RuntimeResult<Self> {
+        match object {
+            Object::Integer(i) => Ok(i.into()),
+            Object::Long(l) => Ok(l),
+            _ => mismatch("a long", object),
+        }
+    }
+}
+
+impl FromObject for Decimal {
+    fn from_object(object: Object) -> RuntimeResult<Self> {
+        match object {
+            Object::Integer(i) => Ok(i.into()),
+            Object::Long(l) => Ok(l.into()),
+            Object::Float(f) => Ok(f),
+            _ => mismatch("a number", object),
+        }
+    }
+}
+
+impl FromObject for f64 {
+    fn from_object(object: Object) -> RuntimeResult<Self> {
+        Decimal::from_object(object)?
+            .to_f64()
+            .map_or_else(|| mismatch("a float", object), Ok)
+    }
+}
+
+impl FromObject for bool {
+    fn from_object(object: Object) -> RuntimeResult<Self> {
+        match object {
+            Object::Boolean(b) => Ok(b),
+            _ => mismatch("a bool", object),
+        }
+    }
+}
+
+impl FromObject for String {
+    fn from_object(object: Object) -> RuntimeResult<Self> {
+        match object {
+            Object::String(s) => Ok(INTERNER.resolve(&s).to_string()),
+            _ => mismatch("a string", object),
+        }
+    }
+}
+
+/// `null` becomes `None`
+impl<T: FromObject> FromObject for Option<T> {
+    fn from_object(object: Object) -> RuntimeResult<Self> {
+        match object {
+            Object::NullValue => Ok(None),
+            object => T::from_object(object).map(Some),
+        }
+    }
+}
+
+impl IntoObject for Object {
+    fn into_object(self) -> RuntimeResult<Object> {
+        Ok(self)
+    }
+}
+
+impl IntoObject for () {
+    fn into_object(self) -> RuntimeResult<Object> {
+        Ok(Object::NullValue)
+    }
+}
+
+impl IntoObject for i32 {
+    fn into_object(self) -> RuntimeResult<Object> {
+        Ok(Object::Integer(self))
+    }
+}
+
+impl IntoObject for i64 {
+    fn into_object(self) -> RuntimeResult<Object> {
+        Ok(Object::Long(self))
+    }
+}
+
+impl IntoObject for Decimal {
+    fn into_object(self) -> RuntimeResult<Object> {
+        Ok(Object::Float(self))
+    }
+}
+
+impl IntoObject for f64 {
+    fn into_object(self) -> RuntimeResult<Object> {
+        Decimal::try_from(self)
+            .map(Object::Float)
+            .map_err(|_| RuntimeError::new(format!("{self} can't be represented!")))
+    }
+}
+
+impl IntoObject for bool {
+    fn into_object(self) -> RuntimeResult<Object> {
+        Ok(Object::Boolean(self))
+    }
+}
+
+impl IntoObject for String {
+    fn into_object(self) -> RuntimeResult<Object> {
+        Ok(Object::String(INTERNER.get_or_intern(self)))
+    }
+}
+
+impl IntoObject for &str {
+    fn into_object(self) -> RuntimeResult<Object> {
+        Ok(Object::String(INTERNER.get_or_intern(self)))
+    }
+}
+
+impl<T: IntoObject> IntoObject for Option<T> {
+    fn into_object(self) -> RuntimeResult<Object> {
+        self.map_or(Ok(Object::NullValue), T::into_object)
+    }
+}
+
+/// Natives can fail by returning a `RuntimeError`
+impl<T: IntoObject> IntoObject for RuntimeResult<T> {
+    fn into_object(self) -> RuntimeResult<Object> {
+        self?.into_object()
+    }
+}
+
+/// Rust closures `Engine::register_fn` accepts, `Args` is the tuple of their argument types
+pub trait NativeFn<Args>: 'static {
+    fn call(&self, args: &[Object]) -> RuntimeResult<Object>;
+}
+
+macro_rules! impl_native_fn {
+    ($($arg:ident),*) => {
+        impl<Func, Ret, $($arg),*> NativeFn<($($arg,)*)> for Func
+        where
+            Func: Fn($($arg),*) -> Ret + 'static,
+            Ret: IntoObject,
+            $($arg: FromObject),*
+        {
+            #[allow(non_snake_case)]
+            fn call(&self, args: &[Object]) -> RuntimeResult<Object> {
+                let arity = <[&str]>::len(&[$(stringify!($arg)),*]);
+                let [$($arg),*] = args else {
+                    return Err(RuntimeError::new(format!(
+                        "Expected {arity} argument(s), found {}!",
+                        args.len()
+                    )));
+                };
+                (self)($($arg::from_object(*$arg)?),*).into_object()
+            }
+        }
+    };
+}
+
+impl_native_fn!();
+impl_native_fn!(A);
+impl_native_fn!(A, B);
+impl_native_fn!(A, B, C);
+impl_native_fn!(A, B, C, D);
+impl_native_fn!(A, B, C, D, E);
@@ -0,0 +1,288 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ast::stmt::{Program, Statement},
+    checker::{
+        attributes::AttributeSpec,
+        checker::{check, Diagnostic},
+        names::Environment,
+    },
+    interperter::{
+        interperter::{Interperter, ResourceHooks},
+        io::{BufferSink, InputSource, OutputSink},
+        native::NativeFunction,
+    },
+    lexer::lexer::{convert_to_hash, scan_file},
+    loader::loader::{link, Loader},
+    parser::parser::{get_stream, try_parse},
+    utils::{error::RuntimeResult, object::Object, source_map::SourceMap},
+    vm::{compiler::compile, vm::Vm},
+};
+
+use super::{
+    convert::{FromObject, IntoObject, NativeFn},
+    error::Error,
+};
+
+/// How an engine runs programs, both give the same results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backend {
+    /// Walks the syntax tree
+    #[default]
+    Tree,
+    /// Compiles to bytecode first and runs that on a stack machine
+    Vm,
+}
+
+/// Entry point for running scripts from Rust
+///
+/// ```
+/// # fn main() -> Result<(), scorpionest::Error> {
+/// let mut engine = scorpionest::Engine::new();
+/// engine.register_fn("double", |x: i32| x * 2);
+/// let answer: i32 = engine.eval("double(21)")?;
+/// assert_eq!(42, answer);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Engine {
+    interperter: Interperter,
+    backend: Backend,
+    vm: Vm,
+    captured: Option<BufferSink>,
+    /// Where `use` looks for modules that aren't next to the importing file
+    search_path: Vec<PathBuf>,
+    /// The modules that have run, by path
+    modules: HashSet<PathBuf>,
+    /// Attributes the embedder registered on top of the built in ones
+    attributes: Vec<AttributeSpec>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Switches how programs are run, globals and natives carry over but functions don't
+    pub fn set_backend(&mut self, backend: Backend) -> &mut Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Adds a directory `use` looks for modules in, after the importing file's own
+    pub fn add_search_path(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.search_path.push(dir.into());
+        self
+    }
+
+    /// Lexes, parses and checks `source`, the program can be run any number of times.
+    /// Modules it imports are looked for in the current directory
+    pub fn compile(&self, source: &str) -> Result<Program, Error> {
+        Ok(self.compile_with_warnings(source)?.0)
+    }
+
+    /// Like `compile`, but also returns the warnings the checks found
+    pub fn compile_with_warnings(&self, source: &str) -> Result<(Program, Vec<Diagnostic>), Error> {
+        self.build(source, None)
+    }
+
+    /// Compiles `source`, which was read from `path`, modules it imports are looked for next to it
+    pub fn compile_file(&self, path: &Path, source: &str) -> Result<Program, Error> {
+        Ok(self.compile_file_with_warnings(path, source)?.0)
+    }
+
+    pub fn compile_file_with_warnings(
+        &self,
+        path: &Path,
+        source: &str,
+    ) -> Result<(Program, Vec<Diagnostic>), Error> {
+        self.build(source, Some(path))
+    }
+
+    fn build(
+        &self,
+        source: &str,
+        path: Option<&Path>,
+    ) -> Result<(Program, Vec<Diagnostic>), Error> {
+        let mut sources = SourceMap::new();
+        let file = sources.add(path.unwrap_or(Path::new("<input>")), source);
+        let tokens = scan_file(source, file)?;
+        let mut program = try_parse(get_stream((tokens, source)))?;
+        let natives = self.natives();
+        let mut loader = Loader::new(&self.search_path, &natives, &mut sources);
+        if let Some(path) = path {
+            loader.enter(path);
+        }
+        let dir = path.and_then(Path::parent).unwrap_or(Path::new("."));
+        let mut environment = self.environment();
+        let mut diagnostics = loader.imports(source, &program, dir, &mut environment)?;
+        diagnostics.extend(check(source, &program, &environment));
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            return Err(Error::Check(diagnostics));
+        }
+        link(&mut program, source, &environment, None);
+        program.modules = loader.finish();
+        Ok((program, diagnostics))
+    }
+
+    /// The names programs can use without declaring them, natives and what earlier runs declared
+    pub fn environment(&self) -> Environment {
+        let mut environment = self.natives();
+        for name in self.interperter.declared().chain(self.vm.declared()) {
+            environment.global(name);
+        }
+        environment
+    }
+
+    /// The natives and attributes, all a module can use without importing it
+    fn natives(&self) -> Environment {
+        let mut environment = Environment::new();
+        for native in self.interperter.native_names() {
+            environment.native(native);
+        }
+        for spec in &self.attributes {
+            environment.attribute(spec.clone());
+        }
+        environment
+    }
+
+    /// Runs the modules `program` imports that haven't run yet
+    fn run_modules(&mut self, program: &Program) -> Result<(), Error> {
+        for module in &program.modules {
+            if !self.modules.insert(module.path.clone()) {
+                continue;
+            }
+            match self.backend {
+                Backend::Tree => self.interperter.program_eval(&module.program)?,
+                Backend::Vm => {
+                    self.run_compiled(&module.program)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `program`, globals it declares stay around for later runs
+    pub fn run(&mut self, program: &Program) -> Result<(), Error> {
+        self.run_modules(program)?;
+        match self.backend {
+            Backend::Tree => self.interperter.program_eval(program)?,
+            Backend::Vm => {
+                self.run_compiled(program)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles `program` against the interpreter's globals and runs it on the vm
+    fn run_compiled(&mut self, program: &Program) -> RuntimeResult<Object> {
+        let script = compile(program, self.interperter.globals_mut());
+        self.vm.run(&mut self.interperter, script)
+    }
+
+    pub fn run_source(&mut self, source: &str) -> Result<(), Error> {
+        let program = self.compile(source)?;
+        self.run(&program)
+    }
+
+    /// Runs `source` and converts the value of its final expression, `null` if it doesn't end in one
+    pub fn eval<T: FromObject>(&mut self, source: &str) -> Result<T, Error> {
+        let mut program = self.compile(source)?;
+        let last = program
+            .statements
+            .iter()
+            .rposition(|stmt| !matches!(program.arena[*stmt].0, Statement::Empty));
+        // Both engines return the trailing expression, so trailing empty statements are dropped
+        program
+            .statements
+            .truncate(last.map_or(0, |index| index + 1));
+        self.run_modules(&program)?;
+        let value = match self.backend {
+            Backend::Tree => self.interperter.program_value_eval(&program)?,
+            Backend::Vm => self.run_compiled(&program)?,
+        };
+        Ok(T::from_object(value)?)
+    }
+
+    /// Makes `func` callable from scripts as `name`, arguments and the return value
+    /// are converted with `FromObject` and `IntoObject`
+    pub fn register_fn<Args>(&mut self, name: &str, func: impl NativeFn<Args>) -> &mut Self {
+        self.interperter
+            .register_native(NativeFunction::new(name, move |_, args| func.call(args)));
+        self
+    }
+
+    /// Lets scripts use what the function `producer` returns with `with`, the functions
+    /// named `enter` and `exit` are called with it when the body starts and however it ends
+    pub fn register_resource(
+        &mut self,
+        producer: &str,
+        enter: Option<&str>,
+        exit: &str,
+    ) -> &mut Self {
+        let hooks = ResourceHooks {
+            enter: enter.map(|enter| convert_to_hash(&enter)),
+            exit: convert_to_hash(&exit),
+        };
+        self.interperter
+            .set_resource(convert_to_hash(&producer), Some(hooks));
+        self
+    }
+
+    /// Lets scripts use `@name` without a warning, the embedder can then look it up on
+    /// declarations with `Statement::attribute`
+    pub fn register_attribute(&mut self, spec: AttributeSpec) -> &mut Self {
+        self.attributes.push(spec);
+        self
+    }
+
+    /// Declares a mutable global, replacing any existing one with the same name
+    pub fn set_global(&mut self, name: &str, value: impl IntoObject) -> Result<(), Error> {
+        let value = value.into_object()?;
+        self.interperter
+            .set_var(convert_to_hash(&name), value, true);
+        Ok(())
+    }
+
+    /// `None` if no global is called `name`
+    pub fn get_global<T: FromObject>(&self, name: &str) -> Result<Option<T>, Error> {
+        match self.interperter.get_var(convert_to_hash(&name)) {
+            Some(value) => Ok(Some(T::from_object(value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Sends everything scripts print to `output` instead of stdout
+    pub fn set_output(&mut self, output: impl OutputSink + 'static) -> &mut Self {
+        self.captured = None;
+        self.interperter.set_output(output);
+        self
+    }
+
+    /// Makes `input` read from `input` instead of stdin
+    pub fn set_input(&mut self, input: impl InputSource + 'static) -> &mut Self {
+        self.interperter.set_input(input);
+        self
+    }
+
+    /// Collects everything scripts print in memory, read it back with `take_output`
+    pub fn capture_output(&mut self) -> &mut Self {
+        let buffer = BufferSink::new();
+        self.interperter.set_output(buffer.clone());
+        self.captured = Some(buffer);
+        self
+    }
+
+    /// The output captured since the last call, empty if output isn't being captured
+    pub fn take_output(&mut self) -> String {
+        self.captured
+            .as_ref()
+            .map(BufferSink::take)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+use crate::{
+    checker::checker::Diagnostic,
+    lexer::lexer::LexingError,
+    parser::parser::ParseError,
+    utils::{
+        error::RuntimeError,
+        source_map::{SourceFile, SourceMap},
+        spanned::FileId,
+    },
+};
+
+/// Everything that can go wrong between source text and a finished run
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    Lex(#[from] LexingError),
+    #[error("{}", .0.iter().map(|e| e.message.as_str()).collect::<Vec<_>>().join("\n"))]
+    Parse(Vec<ParseError>),
+    /// Static checks found errors, the diagnostics include any warnings
+    #[error("{}", .0.iter().filter(|d| d.is_error()).map(|d| d.message.as_str()).collect::<Vec<_>>().join("\n"))]
+    Check(Vec<Diagnostic>),
+    #[error("{0}")]
+    Runtime(#[from] RuntimeError),
+    /// The formatter couldn't produce output equivalent to its input
+    #[error("{0}")]
+    Format(String),
+    /// Loading a module failed, the spans in `error` point into `input`
+    #[error("{}: {error}", .path.display())]
+    Module {
+        file: FileId,
+        path: PathBuf,
+        input: String,
+        error: Box<Error>,
+    },
+}
+
+impl From<Vec<ParseError>> for Error {
+    fn from(errors: Vec<ParseError>) -> Self {
+        Error::Parse(errors)
+    }
+}
+
+impl Error {
+    /// Prints the error to stderr, pointing into the files of `sources` where there's a span
+    /// to point at
+    pub fn report(&self, sources: &SourceMap) {
+        match self {
+            Error::Lex(e) => e.report(sources),
+            Error::Parse(errors) => errors.iter().for_each(|e| e.report(sources)),
+            Error::Check(diagnostics) => diagnostics.iter().for_each(|d| d.report(sources)),
+            Error::Module {
+                file,
+                path,
+                input,
+                error,
+            } => {
+                let mut sources = sources.clone();
+                sources.insert(*file, SourceFile::new(path, input.as_str()));
+                error.report(&sources);
+            }
+            e => eprintln!("Error: {e}"),
+        }
+    }
+
+    /// The error itself, or the one a module failed with
+    pub fn innermost(&self) -> &Error {
+        match self {
+            Error::Module { error, .. } => error.innermost(),
+            e => e,
+        }
+    }
+}
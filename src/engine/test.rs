@@ -0,0 +1,194 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::utils::{error::RuntimeError, object::Object};
+
+use super::{
+    engine::{Backend, Engine},
+    error::Error,
+};
+
+#[test]
+fn compile_once_run_many() {
+    let mut engine = Engine::new();
+    engine.set_global("total", 0).unwrap();
+    let program = engine.compile("total += 2;").unwrap();
+    for _ in 0..3 {
+        engine.run(&program).unwrap();
+    }
+    assert_eq!(Some(6), engine.get_global::<i32>("total").unwrap());
+    assert_eq!(None, engine.get_global::<i32>("missing").unwrap());
+}
+
+#[test]
+fn eval_expressions() {
+    let mut engine = Engine::new();
+    assert_eq!(7, engine.eval::<i32>("1 + 2 * 3").unwrap());
+    assert_eq!("a1", engine.eval::<String>("let x = 1; \"a{x}\"").unwrap());
+    assert_eq!(
+        Object::NullValue,
+        engine.eval::<Object>("let y = 2;").unwrap()
+    );
+    assert!(matches!(
+        engine.eval::<bool>("1"),
+        Err(Error::Runtime(RuntimeError { .. }))
+    ));
+}
+
+#[test]
+fn native_functions_convert_arguments() {
+    let mut engine = Engine::new();
+    engine
+        .register_fn("add", |a: i64, b: i64| a + b)
+        .register_fn("shout", |s: String| s.to_uppercase())
+        .register_fn("fail", || -> Result<i32, RuntimeError> {
+            Err(RuntimeError::new("Nope!"))
+        });
+    assert_eq!(5, engine.eval::<i64>("add(2, 3)").unwrap());
+    assert_eq!("HI", engine.eval::<String>("shout(\"hi\")").unwrap());
+    assert_eq!(
+        Err(Error::Runtime(RuntimeError::new(
+            "Expected a string, found '1'!"
+        ))),
+        engine.eval::<String>("shout(1)")
+    );
+    assert_eq!(
+        Err(Error::Runtime(RuntimeError::new(
+            "Expected 2 argument(s), found 1!"
+        ))),
+        engine.eval::<i64>("add(1)")
+    );
+    assert_eq!(
+        Err(Error::Runtime(RuntimeError::new("Nope!"))),
+        engine.eval::<i32>("fail()")
+    );
+}
+
+#[test]
+fn capture_output_and_errors() {
+    let mut engine = Engine::new();
+    engine.capture_output();
+    engine.run_source("test 1 + 1;").unwrap();
+    assert_eq!("Test Output:2\n", engine.take_output());
+    assert_eq!("", engine.take_output());
+
+    assert!(matches!(
+        engine.compile("let x = \"oops;"),
+        Err(Error::Lex(_))
+    ));
+    assert!(matches!(engine.compile("let = ;"), Err(Error::Parse(_))));
+}
+
+#[test]
+fn extern_functions() {
+    for backend in [Backend::Tree, Backend::Vm] {
+        let mut engine = Engine::new();
+        engine.set_backend(backend).capture_output();
+        engine
+            .run_source(
+                "extern \"C\" fn strlen(s: cstr) -> long;
+                extern \"C\" fn abs(x: int) -> int;
+                extern \"C\" fn getenv(name: cstr) -> cstr;
+                println(strlen(\"hello\"), abs(-4), getenv(\"SCORPIONEST_UNSET\"));",
+            )
+            .unwrap();
+        assert_eq!("5 4 null\n", engine.take_output());
+
+        assert_eq!(
+            Err(Error::Runtime(RuntimeError::new(
+                "Can't find `no_such_fn` in libc!"
+            ))),
+            engine.run_source("extern \"C\" fn no_such_fn();")
+        );
+        assert_eq!(
+            Err(Error::Runtime(RuntimeError::new(
+                "Can't pass 'true' to `strlen` as a cstr!"
+            ))),
+            engine.run_source("extern \"C\" fn strlen(s: cstr) -> long; strlen(true);")
+        );
+    }
+}
+
+#[test]
+fn memoized_functions() {
+    for backend in [Backend::Tree, Backend::Vm] {
+        let mut engine = Engine::new();
+        engine.set_backend(backend);
+        let calls = engine
+            .eval::<i32>(
+                "var calls = 0;
+                @memoize
+                fn fib(val n: int), int {
+                    calls += 1;
+                    if n < 2: n else fib(n - 1) + fib(n - 2)
+                }
+                fib(20);
+                fib(20);
+                calls",
+            )
+            .unwrap();
+        assert_eq!(21, calls);
+    }
+}
+
+#[test]
+fn null_safety() {
+    let source = "fn twice(val x: int), int { x * 2 }
+        var calls = 0;
+        fn count(), int { calls += 1; calls }
+        var a: int? = null;
+        let f = fn(val x: int), int -> x + 100;
+        println(a ?? 5, a?.twice(), a?.f(), a?.twice() ?? count());
+        a ?= 3;
+        a ?= 4;
+        println(a ?? count(), a?.twice(), a?.f(), calls);";
+    for backend in [Backend::Tree, Backend::Vm] {
+        let mut engine = Engine::new();
+        engine.set_backend(backend).capture_output();
+        engine.run_source(source).unwrap();
+        assert_eq!("5 null null 1\n3 6 103 1\n", engine.take_output());
+    }
+}
+
+#[test]
+fn with_statements() {
+    let source = "@enter(\"opened\")
+        @exit(\"closed\")
+        fn open(val path: string), string { print(\"make \" + path + \";\"); path }
+        fn opened(val f: string) { print(\"open \" + f + \";\"); }
+        fn closed(val f: string) { print(\"close \" + f + \";\"); }
+        fn count(val path: string), int {
+            var n = 0;
+            while n < 2 {
+                with f = open(path) { n += 1; }
+            }
+            n
+        }
+        with f = open(\"a\") {
+            print(count(\"b\"), \";\");
+            with g = temp_dir() { print(f, g, \";\"); }
+            with h = open(\"c\") { panic(\"failed\"); }
+        }";
+    for backend in [Backend::Tree, Backend::Vm] {
+        let removed = Rc::new(RefCell::new(vec![]));
+        let record = removed.clone();
+        let mut engine = Engine::new();
+        engine
+            .set_backend(backend)
+            .capture_output()
+            .register_fn("temp_dir", || "tmp".to_string())
+            .register_fn("remove_dir", move |dir: String| {
+                record.borrow_mut().push(dir)
+            })
+            .register_resource("temp_dir", None, "remove_dir");
+        assert_eq!(
+            Err(Error::Runtime(RuntimeError::new("failed"))),
+            engine.run_source(source)
+        );
+        assert_eq!(
+            "make a;open a;make b;open b;close b;make b;open b;close b;2 ;a tmp ;\
+             make c;open c;close c;close a;",
+            engine.take_output()
+        );
+        assert_eq!(vec!["tmp".to_string()], *removed.borrow());
+    }
+}
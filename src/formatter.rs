@@ -0,0 +1,3 @@
+pub mod formatter;
+#[cfg(test)]
+mod test;
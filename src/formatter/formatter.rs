@@ -0,0 +1,767 @@
+use std::{cell::Cell, collections::HashMap};
+
+use pretty::RcDoc;
+
+use crate::{
+    ast::{
+        arena::{Arena, ExprId, StmtId},
+        expr::Expr,
+        misc::{
+            AssignOp, Attribute, BinaryOp, DeclarationType, FuncParameter, ParamRestrictor,
+            ParamType, UnaryOp,
+        },
+        pattern::Pattern,
+        stmt::{Program, Statement},
+    },
+    cst::trivia::{scan_lossless, SyntaxToken, Trivia},
+    engine::error::Error,
+    lexer::{
+        lexer::scan,
+        token::{StringSegment, TokenType},
+    },
+    parser::parser::{get_stream, try_parse},
+    utils::spanned::{Span, Spanned},
+};
+
+type Doc = RcDoc<'static, ()>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FormatConfig {
+    /// Lines longer than this are broken where possible
+    pub width: usize,
+    /// Spaces per indentation level
+    pub indent: usize,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            width: 100,
+            indent: 4,
+        }
+    }
+}
+
+/// Formats `input` into the canonical style, keeping every comment
+///
+/// Fails if the input doesn't parse, or if the output wouldn't lex
+/// into the same tokens as the input
+pub fn format_source(input: &str, config: &FormatConfig) -> Result<String, Error> {
+    let tokens = scan_lossless(input)?;
+    let program = try_parse(get_stream((scan(input)?, input)))?;
+
+    let formatter = Formatter::new(input, &tokens, &program.arena, config);
+    let doc = formatter.program(&program);
+    let mut output = Vec::new();
+    doc.render(config.width, &mut output)
+        .map_err(|e| Error::Format(e.to_string()))?;
+    let output = String::from_utf8_lossy(&output)
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let output = format!("{}\n", output.trim_matches('\n'));
+
+    if significant_tokens(input)? != significant_tokens(&output)? {
+        return Err(Error::Format(
+            "Formatting would change the meaning of the program!".to_string(),
+        ));
+    }
+    Ok(output)
+}
+
+/// The tokens of `input`, minus the separators that are allowed to trail a list. `?=` is
+/// split into `?` and `=`, since `let a: int?= b` gets a space before its `=`
+fn significant_tokens(input: &str) -> Result<Vec<TokenType>, Error> {
+    let tokens: Vec<_> = scan(input)?
+        .into_iter()
+        .flat_map(|token| match token.0 {
+            TokenType::IsNull => vec![TokenType::NullChecker, TokenType::Assign],
+            token => vec![without_spans(token)],
+        })
+        .collect();
+    Ok(tokens
+        .iter()
+        .enumerate()
+        .filter(|(i, token)| {
+            **token != TokenType::Comma
+                || !matches!(
+                    tokens.get(i + 1),
+                    Some(TokenType::RightBracket | TokenType::RightParenthesis)
+                )
+        })
+        .map(|(_, token)| token.clone())
+        .collect())
+}
+
+/// Interpolations know where they are in the file, which moves when whitespace before them does
+fn without_spans(token: TokenType) -> TokenType {
+    let TokenType::InterpolatedString(segments) = token else {
+        return token;
+    };
+    let segments = segments
+        .into_iter()
+        .map(|segment| match segment {
+            StringSegment::Interpolation { tokens, spec, .. } => StringSegment::Interpolation {
+                tokens: tokens
+                    .into_iter()
+                    .map(|(token, _)| (without_spans(token), Span::default()))
+                    .collect(),
+                spec,
+                span: Span::default(),
+            },
+            literal => literal,
+        })
+        .collect();
+    TokenType::InterpolatedString(segments)
+}
+
+struct Formatter<'a> {
+    input: &'a str,
+    arena: &'a Arena,
+    indent: isize,
+    names: HashMap<u64, String>,
+    comments: Vec<Trivia>,
+    next_comment: Cell<usize>,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(
+        input: &'a str,
+        tokens: &[SyntaxToken],
+        arena: &'a Arena,
+        config: &FormatConfig,
+    ) -> Self {
+        let names = tokens
+            .iter()
+            .filter_map(|token| match token.kind {
+                TokenType::Identifier(hash) => Some((hash, token.text.clone())),
+                _ => None,
+            })
+            .collect();
+        let comments = tokens
+            .iter()
+            .flat_map(|token| token.leading.iter().chain(&token.trailing))
+            .filter(|trivia| trivia.is_comment())
+            .cloned()
+            .collect();
+        Self {
+            input,
+            arena,
+            indent: config.indent as isize,
+            names,
+            comments,
+            next_comment: Cell::new(0),
+        }
+    }
+
+    fn source(&self, span: Span) -> &'a str {
+        &self.input[span.into_range()]
+    }
+
+    fn name(&self, hash: u64) -> Doc {
+        Doc::text(self.names.get(&hash).cloned().unwrap_or_default())
+    }
+
+    /// A name that might be qualified, `a :: b` is printed as `a::b`
+    fn path(&self, name: Spanned<u64>) -> Doc {
+        match self.names.get(&name.0) {
+            Some(text) => Doc::text(text.clone()),
+            None => Doc::text(self.source(name.1).split_whitespace().collect::<String>()),
+        }
+    }
+
+    //----------------------------------------------------------------
+    //-Comments-------------------------------------------------------
+    //----------------------------------------------------------------
+
+    /// Every comment not printed yet that starts before `pos`, one per line
+    fn comments_before(&self, pos: usize) -> Doc {
+        let mut doc = Doc::nil();
+        while let Some(comment) = self.comments.get(self.next_comment.get()) {
+            if comment.span.start >= pos {
+                break;
+            }
+            doc = doc.append(Doc::text(comment.text.trim_end().to_string()));
+            doc = doc.append(Doc::hardline());
+            self.next_comment.set(self.next_comment.get() + 1);
+        }
+        doc
+    }
+
+    /// Whether a comment not printed yet starts before `pos`
+    fn has_comments_before(&self, pos: usize) -> bool {
+        self.comments
+            .get(self.next_comment.get())
+            .is_some_and(|c| c.span.start < pos)
+    }
+
+    /// The comment following `pos` on the same line, if there is one
+    fn trailing_comment(&self, pos: usize) -> Doc {
+        match self.comments.get(self.next_comment.get()) {
+            Some(comment)
+                if comment.span.start >= pos
+                    && !self.input[pos..comment.span.start].contains('\n') =>
+            {
+                self.next_comment.set(self.next_comment.get() + 1);
+                Doc::text(format!(" {}", comment.text.trim_end()))
+            }
+            _ => Doc::nil(),
+        }
+    }
+
+    //----------------------------------------------------------------
+    //-Statements-----------------------------------------------------
+    //----------------------------------------------------------------
+
+    fn program(&self, program: &Program) -> Doc {
+        let docs = match &program.docs {
+            Some(docs) => {
+                let next = program
+                    .statements
+                    .first()
+                    .map_or(docs.1.end, |s| self.arena[*s].1.start);
+                let gap = match self.input[docs.1.end..next].matches('\n').count() {
+                    0 | 1 => Doc::hardline(),
+                    _ => Doc::hardline().append(Doc::hardline()),
+                };
+                self.doc_lines("//!", &docs.0).append(gap)
+            }
+            None => Doc::nil(),
+        };
+        docs.append(self.statements(&program.statements, self.input.len()))
+    }
+
+    /// Statements one per line, with the comments between them and at most one blank line
+    fn statements(&self, statements: &[StmtId], end: usize) -> Doc {
+        let mut doc = Doc::nil();
+        let mut previous: Option<&Spanned<Statement>> = None;
+        for stmt in statements.iter().map(|id| &self.arena[*id]) {
+            if let (Statement::Empty, Some(prev)) = (&stmt.0, previous) {
+                if !is_terminated(&prev.0, self.arena) {
+                    doc = doc.append(";").append(self.trailing_comment(stmt.1.end));
+                    previous = Some(stmt);
+                    continue;
+                }
+            }
+            if let Some(prev) = previous {
+                doc = doc.append(Doc::hardline());
+                let next = self
+                    .comments
+                    .get(self.next_comment.get())
+                    .map_or(stmt.1.start, |c| {
+                        c.span.start.clamp(prev.1.end, stmt.1.start)
+                    });
+                if self.input[prev.1.end..next].matches('\n').count() > 1 {
+                    doc = doc.append(Doc::hardline());
+                }
+            }
+            // Comments inside of a simple statement have nowhere to go, so they're moved above it
+            let leading = match is_compound(&stmt.0, self.arena) {
+                true => stmt.1.start,
+                false => stmt.1.end,
+            };
+            doc = doc
+                .append(self.comments_before(leading))
+                .append(self.statement(&stmt.0, stmt.1));
+            if is_terminated(&stmt.0, self.arena) {
+                doc = doc.append(self.trailing_comment(stmt.1.end));
+            }
+            previous = Some(stmt);
+        }
+        if let Some(prev) = previous {
+            doc = doc.append(self.trailing_comment(prev.1.end));
+        }
+        if previous.is_some() && self.has_comments_before(end) {
+            doc = doc.append(Doc::hardline());
+        }
+        doc.append(self.comments_before(end))
+    }
+
+    fn statement(&self, stmt: &Statement, span: Span) -> Doc {
+        match stmt {
+            Statement::Error => Doc::text(self.source(span).to_string()),
+            Statement::Empty => Doc::text(";"),
+            Statement::Test(expr) => Doc::text("test ")
+                .append(self.spanned_expr(*expr))
+                .append(";"),
+            Statement::TestDeclaration {
+                name,
+                attributes,
+                statments,
+            } => {
+                let doc = self
+                    .attributes(attributes)
+                    .append("test ")
+                    .append(self.source(name.1).to_string());
+                match statments.is_empty() && !self.has_comments_before(span.end) {
+                    true => doc.append(" {}"),
+                    false => doc
+                        .append(" {")
+                        .append(
+                            Doc::hardline()
+                                .append(self.statements(statments, span.end))
+                                .nest(self.indent),
+                        )
+                        .append(Doc::hardline())
+                        .append("}"),
+                }
+            }
+            Statement::Assign(assign) => self
+                .name(assign.name.0)
+                .append(" ")
+                .append(assign_op(assign.operator.0))
+                .append(" ")
+                .append(self.spanned_expr(assign.value)),
+            Statement::Block { statments } => self.block(statments, span.end),
+            Statement::Expression { expr } => self.spanned_expr(*expr),
+            Statement::Declaration {
+                docs,
+                public,
+                declaration_type,
+                name,
+                manual_type,
+                value,
+            } => {
+                let keyword = match declaration_type {
+                    DeclarationType::Mutable => "var ",
+                    DeclarationType::Immutable => "let ",
+                };
+                let mut doc = self
+                    .outer_docs(docs)
+                    .append(visibility(*public))
+                    .append(keyword)
+                    .append(self.name(name.0));
+                if let Some(manual_type) = manual_type {
+                    doc = doc
+                        .append(": ")
+                        .append(self.source(manual_type.1).to_string());
+                }
+                doc.append(" = ")
+                    .append(self.spanned_expr(*value))
+                    .append(";")
+            }
+            Statement::FuncDeclaration {
+                docs,
+                inner_docs,
+                attributes,
+                public,
+                name,
+                parameters,
+                return_type,
+                statments,
+            } => {
+                let params = parameters
+                    .iter()
+                    .flatten()
+                    .map(|param| self.parameter(param));
+                let mut doc = self
+                    .outer_docs(docs)
+                    .append(self.attributes(attributes))
+                    .append(visibility(*public))
+                    .append("fn ")
+                    .append(self.name(name.0))
+                    .append(self.delimited_list("(", params, ")"));
+                if let Some(return_type) = return_type {
+                    doc = doc
+                        .append(", ")
+                        .append(self.source(return_type.1).to_string());
+                }
+                let inner = match inner_docs {
+                    Some(docs) => self.doc_lines("//!", &docs.0).append(Doc::hardline()),
+                    None => Doc::nil(),
+                };
+                let body = inner.append(self.statements(statments, span.end));
+                doc.append(" {")
+                    .append(Doc::hardline().append(body).nest(self.indent))
+                    .append(Doc::hardline())
+                    .append("}")
+            }
+            Statement::IfStmt {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let mut doc = Doc::text("if ")
+                    .append(self.spanned_expr(*condition))
+                    .append(" ")
+                    .append(self.braced(*then_branch));
+                if let Some(else_branch) = else_branch {
+                    doc = doc.append(" else ").append(self.stmt(*else_branch));
+                }
+                doc
+            }
+            Statement::MatchStmt(match_stmt) => {
+                let arms = match_stmt.then_branches.0.iter().map(|(pattern, stmt)| {
+                    self.pattern(pattern)
+                        .append(" => ")
+                        .append(self.stmt(*stmt))
+                        .append(",")
+                });
+                let arms = Doc::intersperse(arms, Doc::hardline());
+                Doc::text("match ")
+                    .append(self.spanned_expr(match_stmt.predicate))
+                    .append(" {")
+                    .append(Doc::hardline().append(arms).nest(self.indent))
+                    .append(Doc::hardline())
+                    .append("}")
+            }
+            Statement::WhileStmt {
+                condition,
+                then_branch,
+            } => Doc::text("while ")
+                .append(self.spanned_expr(*condition))
+                .append(" ")
+                .append(self.braced(*then_branch)),
+            Statement::With {
+                name,
+                value,
+                statments,
+            } => Doc::text("with ")
+                .append(self.name(name.0))
+                .append(" = ")
+                .append(self.spanned_expr(*value))
+                .append(" ")
+                .append(self.block(statments, span.end)),
+            Statement::Defer { defered_statment } => {
+                Doc::text("defer ").append(self.stmt(*defered_statment))
+            }
+            Statement::Extern(declaration) => {
+                let params = declaration
+                    .parameters
+                    .iter()
+                    .map(|(name, c_type)| self.name(name.0).append(": ").append(c_type.0.name()));
+                let mut doc = self
+                    .outer_docs(&declaration.docs)
+                    .append(self.attributes(&declaration.attributes))
+                    .append(visibility(declaration.public))
+                    .append("extern \"C\" ");
+                if let Some(library) = &declaration.library {
+                    doc = doc
+                        .append("in ")
+                        .append(self.source(library.1).to_string())
+                        .append(" ");
+                }
+                doc = doc
+                    .append("fn ")
+                    .append(declaration.symbol.clone())
+                    .append(self.delimited_list("(", params, ")"));
+                if let Some(return_type) = declaration.return_type {
+                    doc = doc.append(" -> ").append(return_type.0.name());
+                }
+                doc.append(";")
+            }
+            Statement::Use { path, items } => {
+                let path = path.iter().map(|segment| self.name(segment.0));
+                let mut doc = Doc::text("use ").append(Doc::intersperse(path, "::"));
+                if let Some(items) = items {
+                    let items = items.iter().map(|item| self.name(item.0));
+                    doc = doc
+                        .append("::")
+                        .append(self.delimited_list("{", items, "}"));
+                }
+                doc.append(";")
+            }
+        }
+    }
+
+    fn stmt(&self, id: StmtId) -> Doc {
+        let stmt = &self.arena[id];
+        self.statement(&stmt.0, stmt.1)
+    }
+
+    fn block(&self, statements: &[StmtId], end: usize) -> Doc {
+        Doc::text("{")
+            .append(
+                Doc::hardline()
+                    .append(self.statements(statements, end))
+                    .nest(self.indent),
+            )
+            .append(Doc::hardline())
+            .append("}")
+    }
+
+    /// The single statement `if` and `while` hold between their braces
+    fn braced(&self, id: StmtId) -> Doc {
+        let stmt_end = self.arena[id].1.end;
+        let end = self.input[stmt_end..]
+            .find('}')
+            .map_or(stmt_end, |i| stmt_end + i);
+        self.block(&[id], end)
+    }
+
+    fn outer_docs(&self, docs: &Option<Spanned<String>>) -> Doc {
+        match docs {
+            Some(docs) => self.doc_lines("///", &docs.0).append(Doc::hardline()),
+            None => Doc::nil(),
+        }
+    }
+
+    /// Each attribute on a line of its own, arguments are kept as they were written
+    fn attributes(&self, attributes: &[Attribute]) -> Doc {
+        attributes.iter().fold(Doc::nil(), |doc, attribute| {
+            let mut line = Doc::text(format!("@{}", attribute.name.0));
+            if !attribute.arguments.is_empty() {
+                let arguments = attribute
+                    .arguments
+                    .iter()
+                    .map(|argument| Doc::text(self.source(argument.1).to_string()));
+                line = line.append(self.delimited_list("(", arguments, ")"));
+            }
+            doc.append(line).append(Doc::hardline())
+        })
+    }
+
+    fn doc_lines(&self, marker: &str, docs: &str) -> Doc {
+        let lines = docs.split('\n').map(|line| match line {
+            "" => Doc::text(marker.to_string()),
+            line => Doc::text(format!("{marker} {line}")),
+        });
+        Doc::intersperse(lines, Doc::hardline())
+    }
+
+    fn parameter(&self, param: &FuncParameter) -> Doc {
+        let param_type = match param.param_type.0 {
+            ParamType::Reference => "ref ",
+            ParamType::Value => "val ",
+            ParamType::Input => "in ",
+            ParamType::Output => "out ",
+        };
+        let restrictor = match param.param_restrictor.map(|r| r.0) {
+            Some(ParamRestrictor::Mutable) => "mut ",
+            Some(ParamRestrictor::Constant) => "const ",
+            None => "",
+        };
+        Doc::text(param_type)
+            .append(self.name(param.param_value_name.0))
+            .append(": ")
+            .append(restrictor)
+            .append(self.source(param.param_value_type.1).to_string())
+    }
+
+    fn pattern(&self, pattern: &Pattern) -> Doc {
+        match pattern {
+            Pattern::WildCard => Doc::text("_"),
+            Pattern::Literal(literal) => Doc::text(self.source(literal.value.1).to_string()),
+            Pattern::TypeName(type_name) => self.name(type_name.name),
+        }
+    }
+
+    //----------------------------------------------------------------
+    //-Expressions----------------------------------------------------
+    //----------------------------------------------------------------
+
+    fn spanned_expr(&self, id: ExprId) -> Doc {
+        let expr = &self.arena[id];
+        self.expr(&expr.0, expr.1)
+    }
+
+    /// Prints `expr`, keeping the parentheses the source wrapped it in
+    fn expr(&self, expr: &Expr, span: Span) -> Doc {
+        let mut doc = match expr {
+            Expr::Binary(binary) => self
+                .spanned_expr(binary.left)
+                .append(" ")
+                .append(binary_op(binary.operator.0))
+                .append(
+                    Doc::line()
+                        .append(self.spanned_expr(binary.right))
+                        .nest(self.indent),
+                )
+                .group(),
+            Expr::Unary(unary) => {
+                let op = match unary.operator.0 {
+                    UnaryOp::Neg => "-",
+                    UnaryOp::Not => "not ",
+                };
+                Doc::text(op).append(self.spanned_expr(unary.right))
+            }
+            Expr::Literal(literal) => Doc::text(self.source(literal.value.1).to_string()),
+            Expr::Variable { name } => self.path(*name),
+            Expr::TenaryIfStmt {
+                condition,
+                value,
+                else_value,
+            } => Doc::text("if ")
+                .append(self.spanned_expr(*condition))
+                .append(":")
+                .append(
+                    Doc::line()
+                        .append(self.spanned_expr(*value))
+                        .append(Doc::line())
+                        .append("else ")
+                        .append(self.spanned_expr(*else_value))
+                        .nest(self.indent),
+                )
+                .group(),
+            Expr::FunctionCall {
+                callee,
+                arguments,
+                safe: true,
+            } => {
+                let arguments = arguments.as_deref().unwrap_or_default();
+                let args = arguments[1..].iter().map(|arg| self.spanned_expr(*arg));
+                self.spanned_expr(arguments[0])
+                    .append("?.")
+                    .append(self.spanned_expr(*callee))
+                    .append(self.delimited_list("(", args, ")"))
+            }
+            Expr::FunctionCall {
+                callee, arguments, ..
+            } => {
+                let args = arguments
+                    .iter()
+                    .flatten()
+                    .map(|arg| self.spanned_expr(*arg));
+                self.spanned_expr(*callee)
+                    .append(self.delimited_list("(", args, ")"))
+            }
+            Expr::Interpolation { .. } => Doc::text(self.source(span).to_string()),
+            Expr::Lambda(lambda) => {
+                let params = lambda
+                    .parameters
+                    .iter()
+                    .flatten()
+                    .map(|param| self.parameter(param));
+                let mut doc = Doc::text("fn").append(self.delimited_list("(", params, ")"));
+                if let Some(return_type) = &lambda.return_type {
+                    doc = doc
+                        .append(", ")
+                        .append(self.source(return_type.1).to_string());
+                }
+                match (lambda.arrow, &self.arena[lambda.statments[0]].0) {
+                    (true, Statement::Expression { expr }) => {
+                        doc.append(" -> ").append(self.spanned_expr(*expr))
+                    }
+                    _ => doc
+                        .append(" {")
+                        .append(
+                            Doc::hardline()
+                                .append(self.statements(&lambda.statments, span.end))
+                                .nest(self.indent),
+                        )
+                        .append(Doc::hardline())
+                        .append("}"),
+                }
+            }
+        };
+        for _ in 0..paren_depth(self.source(span)) {
+            doc = Doc::text("(").append(doc).append(")");
+        }
+        doc
+    }
+
+    /// `(a, b)` on one line, or one item per line with a trailing comma if it doesn't fit
+    fn delimited_list(
+        &self,
+        open: &'static str,
+        items: impl Iterator<Item = Doc>,
+        close: &'static str,
+    ) -> Doc {
+        let items: Vec<_> = items.collect();
+        if items.is_empty() {
+            return Doc::text(open).append(close);
+        }
+        let separator = Doc::text(",").append(Doc::line());
+        Doc::text(open)
+            .append(
+                Doc::line_()
+                    .append(Doc::intersperse(items, separator))
+                    .append(Doc::text(",").flat_alt(Doc::nil()))
+                    .nest(self.indent),
+            )
+            .append(Doc::line_())
+            .append(close)
+            .group()
+    }
+}
+
+fn visibility(public: bool) -> &'static str {
+    match public {
+        true => "pub ",
+        false => "",
+    }
+}
+
+/// Statements that consume their own `;`, the others are followed by an `Empty` statement
+fn is_terminated(stmt: &Statement, arena: &Arena) -> bool {
+    match stmt {
+        Statement::Assign(_) | Statement::Expression { .. } | Statement::Error => false,
+        Statement::Defer { defered_statment } => is_terminated(&arena[*defered_statment].0, arena),
+        _ => true,
+    }
+}
+
+/// Statements holding other statements, which place their own comments
+fn is_compound(stmt: &Statement, arena: &Arena) -> bool {
+    match stmt {
+        Statement::Block { .. }
+        | Statement::FuncDeclaration { .. }
+        | Statement::TestDeclaration { .. }
+        | Statement::IfStmt { .. }
+        | Statement::MatchStmt(_)
+        | Statement::WhileStmt { .. }
+        | Statement::With { .. } => true,
+        Statement::Defer { defered_statment } => is_compound(&arena[*defered_statment].0, arena),
+        _ => false,
+    }
+}
+
+/// How many pairs of parentheses wrap the whole of `source`
+fn paren_depth(source: &str) -> usize {
+    let mut depth = 0;
+    let mut source = source.trim();
+    while source.starts_with('(') && source.ends_with(')') && closes_at_end(source) {
+        depth += 1;
+        source = source[1..source.len() - 1].trim();
+    }
+    depth
+}
+
+/// Whether the `(` at the start of `source` is closed by the `)` at its end
+fn closes_at_end(source: &str) -> bool {
+    let Ok(tokens) = scan(source) else {
+        return false;
+    };
+    let mut depth = 0;
+    for (i, (token, _)) in tokens.iter().enumerate() {
+        match token {
+            TokenType::LeftParenthesis => depth += 1,
+            TokenType::RightParenthesis => depth -= 1,
+            _ => (),
+        }
+        if depth == 0 {
+            return i == tokens.len() - 1;
+        }
+    }
+    false
+}
+
+fn binary_op(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::GreaterThan => ">",
+        BinaryOp::GreaterThanEqual => ">=",
+        BinaryOp::LessThan => "<",
+        BinaryOp::LessThanEqual => "<=",
+        BinaryOp::Equal => "==",
+        BinaryOp::NotEqual => "!=",
+        BinaryOp::And => "and",
+        BinaryOp::Or => "or",
+        BinaryOp::Coalesce => "??",
+    }
+}
+
+fn assign_op(op: AssignOp) -> &'static str {
+    match op {
+        AssignOp::Add => "+=",
+        AssignOp::Sub => "-=",
+        AssignOp::Mul => "*=",
+        AssignOp::Div => "/=",
+        AssignOp::Set => "=",
+        AssignOp::SetIfNull => "?=",
+    }
+}
@@ -0,0 +1,130 @@
+use super::formatter::{format_source, FormatConfig};
+
+const MESSY: &str = "//! Module docs\n\n\n/// Adds things\nfn   add( val a:int,val b : int ),int{\n//! Inner\nlet c=a+b;   // sum\n\n\n\nc\n;}\nvar   x = ( 1+2 )*3 ;\n\n// loop forever\nwhile x>0{x-=1}\nif x==0 {test x;} else {\n  x = 1\n}\n";
+
+#[test]
+fn format_is_idempotent() {
+    let config = FormatConfig::default();
+    let once = format_source(MESSY, &config).unwrap();
+    let twice = format_source(&once, &config).unwrap();
+    assert_eq!(once, twice);
+}
+
+#[test]
+fn format_keeps_comments_and_parens() {
+    let formatted = format_source(MESSY, &FormatConfig::default()).unwrap();
+    assert_eq!(
+        "//! Module docs\n\n/// Adds things\nfn add(val a: int, val b: int), int {\n    //! Inner\n    let c = a + b; // sum\n\n    c;\n}\nvar x = (1 + 2) * 3;\n\n// loop forever\nwhile x > 0 {\n    x -= 1\n}\nif x == 0 {\n    test x;\n} else {\n    x = 1\n}\n",
+        formatted
+    );
+}
+
+#[test]
+fn format_breaks_long_lines() {
+    let config = FormatConfig {
+        width: 20,
+        indent: 2,
+    };
+    let formatted = format_source("let total = first + second + third;", &config).unwrap();
+    assert!(
+        formatted.lines().all(|line| line.len() <= 20),
+        "{formatted}"
+    );
+    assert_eq!(formatted, format_source(&formatted, &config).unwrap());
+}
+
+#[test]
+fn format_rejects_invalid_input() {
+    assert!(format_source("let = ;", &FormatConfig::default()).is_err());
+}
+
+#[test]
+fn format_lambdas_and_calls() {
+    let formatted = format_source(
+        "let f = fn( val x:int )->x*2;\nlet g = fn(),int{\nlet y=1;\ny\n};\nmake(1) ( 2 );",
+        &FormatConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        "let f = fn(val x: int) -> x * 2;\nlet g = fn(), int {\n    let y = 1;\n    y\n};\nmake(1)(2);\n",
+        formatted
+    );
+}
+
+#[test]
+fn format_modules() {
+    let formatted = format_source(
+        "use lib :: math;use util::{ nine,ten };\npub  fn f(),int{ math :: triple(nine()) }\npub let x=1;",
+        &FormatConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        "use lib::math;\nuse util::{nine, ten};\npub fn f(), int {\n    math::triple(nine())\n}\npub let x = 1;\n",
+        formatted
+    );
+}
+
+#[test]
+fn format_extern_functions() {
+    let formatted = format_source(
+        "/// Raises x\npub extern \"C\" in \"libm.so.6\" fn pow( x:float,y : float )->float;extern \"C\" fn exit(code:int);",
+        &FormatConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        "/// Raises x\npub extern \"C\" in \"libm.so.6\" fn pow(x: float, y: float) -> float;\nextern \"C\" fn exit(code: int);\n",
+        formatted
+    );
+}
+
+#[test]
+fn format_attributes() {
+    let formatted = format_source(
+        "/// Old\n@deprecated( \"use g\" )  @inline pub fn f(),int{1}\n@ignore test \"slow\" {}",
+        &FormatConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        "/// Old\n@deprecated(\"use g\")\n@inline\npub fn f(), int {\n    1\n}\n@ignore\ntest \"slow\" {}\n",
+        formatted
+    );
+}
+
+#[test]
+fn format_null_safety() {
+    let formatted = format_source(
+        "var a:int?=b??c ?. f( 1 ,2);a?=3;",
+        &FormatConfig::default(),
+    )
+    .unwrap();
+    assert_eq!("var a: int? = b ?? c?.f(1, 2);\na ?= 3;\n", formatted);
+}
+
+#[test]
+fn format_with() {
+    let formatted = format_source(
+        "with f=open( \"a\" ){print(f);g(f);}",
+        &FormatConfig::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        "with f = open(\"a\") {\n    print(f);\n    g(f);\n}\n",
+        formatted
+    );
+}
+
+#[test]
+fn format_moves_interpolated_strings() {
+    let formatted = format_source(
+        "let x  = 1;\nprintln(  \"{x} and {x + 1}\");",
+        &FormatConfig::default(),
+    )
+    .unwrap();
+    assert_eq!("let x = 1;\nprintln(\"{x} and {x + 1}\");\n", formatted);
+}
+
+#[test]
+fn format_keeps_stray_doc_comments() {
+    let formatted = format_source("/// note\nprintln(1);", &FormatConfig::default()).unwrap();
+    assert_eq!("/// note\nprintln(1);\n", formatted);
+}
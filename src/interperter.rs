@@ -1 +1,8 @@
+pub mod ffi;
 pub mod interperter;
+pub mod io;
+pub mod native;
+pub mod prelude;
+pub(crate) mod resolver;
+#[cfg(test)]
+mod test;
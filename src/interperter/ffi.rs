@@ -0,0 +1,218 @@
+//! Calls into C. Only the part of the C ABI that needs no per-signature code is supported:
+//! `int`, `long`, `double`, `bool`, `char *` and `void *` parameters and results, at most
+//! six non-float and eight float parameters so every one is passed in a register, and no
+//! variadic functions. The parser rejects declarations outside of that, declaring a
+//! variadic function like `printf` with a fixed parameter list is undefined behaviour
+
+use std::ffi::{c_void, CStr, CString};
+
+use rust_decimal::{
+    prelude::{FromPrimitive, ToPrimitive},
+    Decimal,
+};
+
+use crate::{
+    ast::{misc::CType, stmt::ExternDeclaration},
+    utils::{
+        error::{RuntimeError, RuntimeResult},
+        interner::INTERNER,
+        object::Object,
+    },
+};
+
+use super::native::NativeFunction;
+
+/// How many int and float arguments an extern call can pass, they all go in registers
+const INT_ARGUMENTS: usize = ExternDeclaration::INT_PARAMETERS;
+const FLOAT_ARGUMENTS: usize = ExternDeclaration::FLOAT_PARAMETERS;
+
+/// Looks up the function `declaration` names in its library, calling the native marshals
+/// its arguments and result between objects and C values
+pub fn bind(declaration: &ExternDeclaration) -> RuntimeResult<NativeFunction> {
+    let symbol = declaration.symbol.clone();
+    let library = declaration
+        .library
+        .as_ref()
+        .map(|library| library.0.as_str());
+    let address = sys::lookup(library, &symbol)?;
+    let parameters: Vec<CType> = declaration
+        .parameters
+        .iter()
+        .map(|(_, c_type)| c_type.0)
+        .collect();
+    let return_type = declaration.return_type.map(|c_type| c_type.0);
+    Ok(NativeFunction::new(symbol.clone(), move |_, args| {
+        if args.len() != parameters.len() {
+            return Err(RuntimeError::new(format!(
+                "Expected {} argument(s), found {}!",
+                parameters.len(),
+                args.len()
+            )));
+        }
+        let mut ints = [0; INT_ARGUMENTS];
+        let mut floats = [0.0; FLOAT_ARGUMENTS];
+        let (mut next_int, mut next_float) = (0, 0);
+        // Kept alive until the call returns
+        let mut strings = vec![];
+        for (c_type, arg) in parameters.iter().zip(args) {
+            let cant_pass = || {
+                RuntimeError::new(format!(
+                    "Can't pass '{arg}' to `{symbol}` as a {}!",
+                    c_type.name()
+                ))
+            };
+            if *c_type == CType::Float {
+                floats[next_float] = match arg {
+                    Object::Float(f) => f.to_f64().ok_or_else(cant_pass)?,
+                    Object::Integer(i) => f64::from(*i),
+                    Object::Long(l) => *l as f64,
+                    _ => return Err(cant_pass()),
+                };
+                next_float += 1;
+                continue;
+            }
+            ints[next_int] = match (c_type, arg) {
+                (CType::Int, Object::Integer(i)) => i64::from(*i),
+                (CType::Int, Object::Long(l)) => {
+                    i64::from(i32::try_from(*l).map_err(|_| cant_pass())?)
+                }
+                (CType::Long | CType::Ptr, Object::Integer(i)) => i64::from(*i),
+                (CType::Long | CType::Ptr, Object::Long(l)) => *l,
+                (CType::Bool, Object::Boolean(b)) => i64::from(*b),
+                (CType::CStr, Object::String(s)) => {
+                    let string = CString::new(INTERNER.resolve(s)).map_err(|_| cant_pass())?;
+                    let pointer = string.as_ptr() as i64;
+                    strings.push(string);
+                    pointer
+                }
+                (CType::CStr | CType::Ptr, Object::NullValue) => 0,
+                _ => return Err(cant_pass()),
+            };
+            next_int += 1;
+        }
+        // SAFETY: the declaration promises the function takes these arguments, the ones it
+        // doesn't take are left in registers it never reads
+        unsafe {
+            Ok(match return_type {
+                Some(CType::Float) => Decimal::from_f64(sys::call(address, &ints, &floats))
+                    .map_or(Object::NullValue, Object::Float),
+                return_type => from_c(return_type, sys::call(address, &ints, &floats)),
+            })
+        }
+    }))
+}
+
+/// A result that comes back in an int register
+///
+/// # Safety
+/// For `cstr` results, `value` has to be null or point to a C string
+unsafe fn from_c(return_type: Option<CType>, value: i64) -> Object {
+    match return_type {
+        None => Object::NullValue,
+        Some(CType::Int) => Object::Integer(value as i32),
+        Some(CType::Long | CType::Ptr) => Object::Long(value),
+        Some(CType::Bool) => Object::Boolean(value as i32 != 0),
+        Some(CType::CStr) if value == 0 => Object::NullValue,
+        Some(CType::CStr) => {
+            let string = CStr::from_ptr(value as *const _).to_string_lossy();
+            Object::String(INTERNER.get_or_intern(string))
+        }
+        Some(CType::Float) => unreachable!("floats come back in a float register"),
+    }
+}
+
+/// `dlopen` and `dlsym`, and calls that put every argument in a register. Ints and floats
+/// are handed out to their own registers in order on both architectures, so one signature
+/// with every register covers any mix of parameters
+#[cfg(all(unix, any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod sys {
+    use super::*;
+
+    pub fn lookup(library: Option<&str>, symbol: &str) -> RuntimeResult<*mut c_void> {
+        let name = library.map(CString::new).transpose().map_err(|_| {
+            RuntimeError::new(format!("Can't load `{}`!", library.unwrap_or_default()))
+        })?;
+        // SAFETY: `dlopen` takes a C string or null for the program itself, which has libc
+        let handle = unsafe {
+            libc::dlopen(
+                name.as_ref().map_or(std::ptr::null(), |name| name.as_ptr()),
+                libc::RTLD_NOW,
+            )
+        };
+        let library = library.map_or("libc".to_string(), |library| format!("`{library}`"));
+        if handle.is_null() {
+            return Err(RuntimeError::new(format!(
+                "Can't load {library}, {}!",
+                last_error()
+            )));
+        }
+        let missing = || RuntimeError::new(format!("Can't find `{symbol}` in {library}!"));
+        let symbol = CString::new(symbol).map_err(|_| missing())?;
+        // SAFETY: the handle is open and never closed, so the address stays valid
+        let address = unsafe { libc::dlsym(handle, symbol.as_ptr()) };
+        match address.is_null() {
+            true => Err(missing()),
+            false => Ok(address),
+        }
+    }
+
+    fn last_error() -> String {
+        // SAFETY: `dlerror` returns null or a C string that lives until the next call
+        let error = unsafe { libc::dlerror() };
+        match error.is_null() {
+            true => "unknown error".to_string(),
+            false => unsafe { CStr::from_ptr(error) }
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+
+    /// # Safety
+    /// `address` has to be a C function taking at most the given ints and floats and
+    /// returning `R`
+    pub unsafe fn call<R>(
+        address: *mut c_void,
+        i: &[i64; INT_ARGUMENTS],
+        f: &[f64; FLOAT_ARGUMENTS],
+    ) -> R {
+        type Signature<R> = unsafe extern "C" fn(
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            i64,
+            f64,
+            f64,
+            f64,
+            f64,
+            f64,
+            f64,
+            f64,
+            f64,
+        ) -> R;
+        let function: Signature<R> = std::mem::transmute(address);
+        function(
+            i[0], i[1], i[2], i[3], i[4], i[5], f[0], f[1], f[2], f[3], f[4], f[5], f[6], f[7],
+        )
+    }
+}
+
+#[cfg(not(all(unix, any(target_arch = "x86_64", target_arch = "aarch64"))))]
+mod sys {
+    use super::*;
+
+    pub fn lookup(_: Option<&str>, _: &str) -> RuntimeResult<*mut c_void> {
+        Err(RuntimeError::new(
+            "Extern functions aren't supported on this platform!",
+        ))
+    }
+
+    pub unsafe fn call<R>(
+        _: *mut c_void,
+        _: &[i64; INT_ARGUMENTS],
+        _: &[f64; FLOAT_ARGUMENTS],
+    ) -> R {
+        unreachable!("nothing can be looked up")
+    }
+}
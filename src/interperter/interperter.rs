@@ -1,182 +1,806 @@
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
+use lasso::Spur;
+
+use super::{
+    ffi,
+    io::{InputSource, Io, OutputSink},
+    native::NativeFunction,
+    prelude::prelude,
+    resolver::{resolve, Code, Slot},
+};
 use crate::{
     ast::{
-        expr::{Binary, Expr, Literal, Unary},
+        arena::{Arena, ExprId, StmtId},
+        expr::{Binary, Expr, Lambda, Unary},
+        misc::{AssignOp, Attribute, BinaryOp, DeclarationType, FuncParameter, ParamType, UnaryOp},
         pattern::Pattern,
-        stmt::{Assign, MatchStmt, Statement},
+        stmt::{Assign, ExternDeclaration, MatchStmt, Program, Statement},
+    },
+    lexer::lexer::convert_to_hash,
+    utils::{
+        error::{RuntimeError, RuntimeResult},
+        format::format_template,
+        interner::INTERNER,
+        object::Object,
+        spanned::Spanned,
     },
-    lexer::token::TokenType,
-    utils::{object::Object, spanned::Spanned},
 };
 
 //----------------------------------------------------------------
 //-Expr Functions-------------------------------------------------
 //----------------------------------------------------------------
+/// A function declared by a script
+#[derive(Debug)]
+pub struct UserFunction {
+    parameters: Vec<FuncParameter>,
+    statments: Vec<StmtId>,
+    /// The program the function was declared in
+    code: Rc<Code>,
+    frame_size: usize,
+    /// Marked `@memoize`
+    memoize: bool,
+}
+
+type Variable = Option<(Object, bool)>;
+
+/// Where the variable a `ref` or `out` parameter stands for lives
+#[derive(Debug, Clone, Copy)]
+enum Place {
+    Global(u32),
+    Local { frame: usize, slot: u32 },
+}
+
+/// A `ref` or `out` parameter of the function being called
+#[derive(Debug, Clone, Copy)]
+struct Reference {
+    place: Place,
+    mutable: bool,
+    /// `out` parameters can't be read until they're assigned and have to be before returning
+    output: bool,
+    assigned: bool,
+}
+
+impl Reference {
+    fn check_read(&self) -> RuntimeResult<()> {
+        match self.output && !self.assigned {
+            true => Err(RuntimeError::new(
+                "Out parameter read before it was assigned!",
+            )),
+            false => Ok(()),
+        }
+    }
+}
+
+/// The locals of a function call
+#[derive(Debug, Clone, Default)]
+struct Frame {
+    variables: Vec<Variable>,
+    /// Indexed by slot, set for `ref` and `out` parameters
+    references: Vec<Option<Reference>>,
+}
+
+/// Values a lambda copied from the frames around it, along with the slot each one goes into
+pub(crate) type Captures = Rc<[(u32, Option<Object>)]>;
+
+/// What an `Object::Function` refers to
+#[derive(Debug, Clone)]
+pub(crate) enum Callable {
+    /// A declared function or native, looked up when it's called like a direct call
+    Named(u64),
+    Lambda {
+        function: Rc<UserFunction>,
+        captures: Captures,
+    },
+    /// A lambda compiled by the vm
+    Compiled {
+        function: Rc<crate::vm::chunk::Function>,
+        captures: Captures,
+    },
+}
+
+/// The functions `with` calls with what a resource function returned, by name
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResourceHooks {
+    /// Called before the body runs
+    pub enter: Option<u64>,
+    /// Called however the body ends, even when it fails
+    pub exit: u64,
+}
+
+impl ResourceHooks {
+    /// From `@enter("name")` and `@exit("name")`, functions without an `@exit` don't make resources
+    pub fn of(attributes: &[Attribute]) -> Option<Self> {
+        let hook = |name: &str| {
+            let attribute = attributes
+                .iter()
+                .find(|attribute| attribute.name.0 == name)?;
+            match attribute.arguments.first()? {
+                Spanned(Object::String(hook), _) => Some(convert_to_hash(&INTERNER.resolve(hook))),
+                _ => None,
+            }
+        };
+        Some(ResourceHooks {
+            enter: hook("enter"),
+            exit: hook("exit")?,
+        })
+    }
+}
+
+/// The function the value of a `with` comes from, it has to be called by name
+pub(crate) fn producer(arena: &Arena, value: ExprId) -> Option<u64> {
+    let Expr::FunctionCall {
+        callee,
+        safe: false,
+        ..
+    } = &arena[value].0
+    else {
+        return None;
+    };
+    match &arena[*callee].0 {
+        Expr::Variable { name } => Some(name.0),
+        _ => None,
+    }
+}
+
+/// Globals are kept in slots handed out by name, resolved code only ever indexes them
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Globals {
+    names: HashMap<u64, u32>,
+    /// The name each slot was handed out for
+    keys: Vec<u64>,
+    slots: Vec<Variable>,
+}
+
+impl Globals {
+    /// The slot of `name`, which stays empty until the global is declared
+    pub fn slot(&mut self, name: u64) -> u32 {
+        *self.names.entry(name).or_insert_with(|| {
+            self.keys.push(name);
+            self.slots.push(None);
+            self.slots.len() as u32 - 1
+        })
+    }
+
+    pub fn name(&self, slot: u32) -> u64 {
+        self.keys[slot as usize]
+    }
+
+    /// The global in `slot`, `None` if it hasn't been declared
+    pub fn at(&mut self, slot: u32) -> &mut Variable {
+        &mut self.slots[slot as usize]
+    }
+
+    pub fn get(&self, name: &u64) -> Option<&(Object, bool)> {
+        self.slots[*self.names.get(name)? as usize].as_ref()
+    }
+
+    /// The globals that have been declared
+    pub fn declared(&self) -> impl Iterator<Item = u64> + '_ {
+        self.names
+            .iter()
+            .filter(|(_, slot)| self.slots[**slot as usize].is_some())
+            .map(|(name, _)| *name)
+    }
+
+    pub fn insert(&mut self, name: u64, var: (Object, bool)) {
+        let slot = self.slot(name);
+        self.slots[slot as usize] = Some(var);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Interperter {
-    vars: HashMap<u64, (Object, bool)>,
+    vars: Globals,
+    /// Locals of the functions being called, innermost last
+    frames: Vec<Frame>,
+    /// What `defer` queued in each body being run, innermost last
+    defers: Vec<Vec<StmtId>>,
+    functions: HashMap<u64, Rc<UserFunction>>,
+    natives: HashMap<u64, NativeFunction>,
+    /// Function values are indices into this, like strings are into the interner
+    callables: Vec<Callable>,
+    /// Where the value of each declared function or native that's been used as one is
+    named: HashMap<u64, u32>,
+    /// What each `@memoize` function returned, by its arguments
+    memos: HashMap<u64, HashMap<Vec<Object>, Object>>,
+    /// The hooks of the functions whose results can be used with `with`
+    resources: HashMap<u64, ResourceHooks>,
+    io: Io,
 }
 
 impl Default for Interperter {
     fn default() -> Self {
-        Self {
-            vars: HashMap::new(),
-        }
+        let mut interperter = Self {
+            vars: Globals::default(),
+            frames: vec![],
+            defers: vec![],
+            functions: HashMap::new(),
+            natives: HashMap::new(),
+            callables: vec![],
+            named: HashMap::new(),
+            memos: HashMap::new(),
+            resources: HashMap::new(),
+            io: Io::default(),
+        };
+        prelude()
+            .into_iter()
+            .for_each(|native| interperter.register_native(native));
+        interperter
     }
 }
+
 impl Interperter {
-    pub fn unary_eval(&mut self, unary: Unary) -> anyhow::Result<Object> {
-        let value = self.expr_eval(unary.right.0)?;
-        use crate::ast::misc::UnaryOp::*;
-        match unary.operator.0 {
-            Neg => match value {
-                Object::Integer(i) => return Ok(Object::Integer(-i)),
-                Object::Float(f) => return Ok(Object::Float(-f)),
-                _ => return Err(anyhow::anyhow!("Invalid value type!")),
-            },
-            Not => {
-                if let Object::Boolean(b) = value {
-                    return Ok(Object::Boolean(!b));
-                } else {
-                    return Err(anyhow::anyhow!("Invalid value type!"));
+    pub fn register_native(&mut self, native: NativeFunction) {
+        self.natives
+            .insert(convert_to_hash(&native.name.as_str()), native);
+    }
+
+    /// Looks up an `extern` function and makes it callable by its declared name
+    pub(crate) fn define_extern(&mut self, declaration: &ExternDeclaration) -> RuntimeResult<()> {
+        let native = ffi::bind(declaration)?;
+        self.natives.insert(declaration.name.0, native);
+        self.set_resource(
+            declaration.name.0,
+            ResourceHooks::of(&declaration.attributes),
+        );
+        Ok(())
+    }
+
+    /// Makes what `producer` returns usable with `with`, `None` makes it a plain function again
+    pub fn set_resource(&mut self, producer: u64, hooks: Option<ResourceHooks>) {
+        match hooks {
+            Some(hooks) => self.resources.insert(producer, hooks),
+            None => self.resources.remove(&producer),
+        };
+    }
+
+    /// The hooks `with` calls on what `producer` returns
+    pub(crate) fn resource(&self, producer: Option<u64>) -> RuntimeResult<ResourceHooks> {
+        producer
+            .and_then(|producer| self.resources.get(&producer).copied())
+            .ok_or_else(|| {
+                RuntimeError::new(
+                    "Only what a function with an exit hook returns can be used with `with`!",
+                )
+            })
+    }
+
+    /// What the `@memoize` function `name` returned when it was called with `args`
+    pub(crate) fn memoized(&self, name: u64, args: &[Object]) -> Option<Object> {
+        self.memos.get(&name)?.get(args).copied()
+    }
+
+    pub(crate) fn memoize(&mut self, name: u64, args: Vec<Object>, value: Object) {
+        self.memos.entry(name).or_default().insert(args, value);
+    }
+
+    /// Drops what `name` returned, a new declaration doesn't remember the old one's results
+    pub(crate) fn forget(&mut self, name: u64) {
+        self.memos.remove(&name);
+    }
+
+    pub fn native_names(&self) -> impl Iterator<Item = &str> {
+        self.natives.values().map(|native| native.name.as_str())
+    }
+
+    /// The globals and functions runs have declared so far
+    pub fn declared(&self) -> impl Iterator<Item = u64> + '_ {
+        self.vars.declared().chain(self.functions.keys().copied())
+    }
+
+    pub fn get_var(&self, name: u64) -> Option<Object> {
+        self.vars.get(&name).map(|var| var.0)
+    }
+
+    /// Declares or overwrites a variable, unlike `declar_eval` this never fails
+    pub fn set_var(&mut self, name: u64, value: Object, mutable: bool) {
+        self.vars.insert(name, (value, mutable));
+    }
+
+    pub fn set_output(&mut self, output: impl OutputSink + 'static) {
+        self.io.output = Rc::new(RefCell::new(output));
+    }
+
+    pub fn set_input(&mut self, input: impl InputSource + 'static) {
+        self.io.input = Rc::new(RefCell::new(input));
+    }
+
+    pub fn write_output(&mut self, text: &str) -> RuntimeResult<()> {
+        self.io.output.borrow_mut().write(text)
+    }
+
+    /// Flushes pending output first, so prompts show up before waiting for input
+    pub fn read_line(&mut self) -> RuntimeResult<Option<String>> {
+        self.io.output.borrow_mut().flush()?;
+        self.io.input.borrow_mut().read_line()
+    }
+
+    /// The variable in `slot`, `None` if it hasn't been declared
+    fn variable_mut(&mut self, slot: Slot) -> &mut Variable {
+        match slot {
+            Slot::Global(global) => &mut self.vars.slots[global as usize],
+            Slot::Local { slot, global } => {
+                let frame = self.frames.last_mut().expect("locals live in a frame");
+                match &mut frame.variables[slot as usize] {
+                    Some(_) => &mut frame.variables[slot as usize],
+                    // Not declared yet, so the name still refers to the global
+                    None => &mut self.vars.slots[global as usize],
                 }
             }
+            Slot::Reference { slot } => {
+                let place = self.reference_mut(slot).place;
+                self.place_mut(place)
+            }
+        }
+    }
+
+    fn place_mut(&mut self, place: Place) -> &mut Variable {
+        match place {
+            Place::Global(global) => &mut self.vars.slots[global as usize],
+            Place::Local { frame, slot } => &mut self.frames[frame].variables[slot as usize],
+        }
+    }
+
+    fn reference_mut(&mut self, slot: u32) -> &mut Reference {
+        self.frames
+            .last_mut()
+            .and_then(|frame| frame.references.get_mut(slot as usize)?.as_mut())
+            .expect("references are parameters")
+    }
+
+    /// The value of a local of the current frame, reading through references
+    fn local_value(&mut self, slot: u32) -> Option<Object> {
+        let frame = self.frames.last().expect("locals live in a frame");
+        match frame.references.get(slot as usize).copied().flatten() {
+            Some(reference) => self.place_mut(reference.place).map(|var| var.0),
+            None => frame.variables[slot as usize].map(|var| var.0),
+        }
+    }
+
+    /// Where a declaration in `slot` goes, locals never fall back to the globals
+    fn scope_mut(&mut self, slot: Slot) -> &mut Variable {
+        match slot {
+            Slot::Global(global) => &mut self.vars.slots[global as usize],
+            Slot::Local { slot, .. } | Slot::Reference { slot } => {
+                &mut self
+                    .frames
+                    .last_mut()
+                    .expect("locals live in a frame")
+                    .variables[slot as usize]
+            }
         }
     }
 
-    pub fn binary_eval(&mut self, binary: Binary) -> anyhow::Result<Object> {
-        let lhs = self.expr_eval(binary.left.0)?;
-        let rhs = self.expr_eval(binary.right.0)?;
+    /// The globals the vm reads and writes
+    pub(crate) fn globals_mut(&mut self) -> &mut Globals {
+        &mut self.vars
+    }
 
-        use crate::ast::misc::BinaryOp::*;
-        match binary.operator.0 {
-            Add => lhs + rhs,
-            Sub => lhs - rhs,
-            Mul => lhs * rhs,
-            Div => lhs / rhs,
-            GreaterThan => Ok(Object::Boolean(lhs > rhs)),
-            GreaterThanEqual => Ok(Object::Boolean(lhs >= rhs)),
-            LessThan => Ok(Object::Boolean(lhs < rhs)),
-            LessThanEqual => Ok(Object::Boolean(lhs <= rhs)),
-            Equal => Ok(Object::Boolean(lhs == rhs)),
-            NotEqual => Ok(Object::Boolean(lhs != rhs)),
-            And => Ok(Object::Boolean(lhs.into() && rhs.into())),
-            Or => Ok(Object::Boolean(lhs.into() || rhs.into())),
+    pub(crate) fn native(&self, name: u64) -> Option<NativeFunction> {
+        self.natives.get(&name).cloned()
+    }
+
+    pub(crate) fn function_value(&mut self, callable: Callable) -> Object {
+        self.callables.push(callable);
+        Object::Function(self.callables.len() as u32 - 1)
+    }
+
+    /// The value of the function or native called `name`, the same one every time
+    pub(crate) fn named_value(&mut self, name: u64) -> Object {
+        match self.named.get(&name) {
+            Some(index) => Object::Function(*index),
+            None => {
+                let value = self.function_value(Callable::Named(name));
+                if let Object::Function(index) = value {
+                    self.named.insert(name, index);
+                }
+                value
+            }
         }
     }
 
-    fn var_eval(&mut self, name: u64) -> anyhow::Result<Object> {
-        match self.vars.get(&name) {
+    pub(crate) fn callable(&self, value: Object) -> RuntimeResult<Callable> {
+        match value {
+            Object::Function(index) => Ok(self.callables[index as usize].clone()),
+            _ => Err(RuntimeError::new("Not a function!")),
+        }
+    }
+
+    fn unary_eval(&mut self, code: &Rc<Code>, unary: &Unary) -> RuntimeResult<Object> {
+        let value = self.expr_eval(code, unary.right)?;
+        unary_op(unary.operator.0, value)
+    }
+
+    fn binary_eval(&mut self, code: &Rc<Code>, binary: &Binary) -> RuntimeResult<Object> {
+        let lhs = self.expr_eval(code, binary.left)?;
+        if binary.operator.0 == BinaryOp::Coalesce && lhs != Object::NullValue {
+            return Ok(lhs);
+        }
+        let rhs = self.expr_eval(code, binary.right)?;
+        binary_op(binary.operator.0, lhs, rhs)
+    }
+
+    /// Declared functions and natives can be used as values by their name
+    fn var_eval(&mut self, slot: Slot, name: u64) -> RuntimeResult<Object> {
+        if let Slot::Reference { slot } = slot {
+            self.reference_mut(slot).check_read()?;
+        }
+        let var = *self.variable_mut(slot);
+        match var {
             Some(var) => Ok(var.0),
-            None => return Err(anyhow::anyhow!("Variable doesn't exist!")),
+            None if self.functions.contains_key(&name) || self.natives.contains_key(&name) => {
+                Ok(self.named_value(name))
+            }
+            None => return Err(RuntimeError::new("Variable doesn't exist!")),
         }
     }
 
     fn tenary_if_eval(
         &mut self,
-        condition: Expr,
-        value: Expr,
-        else_value: Expr,
-    ) -> anyhow::Result<Object> {
-        return if self.expr_eval(condition)?.into() {
-            self.expr_eval(value)
+        code: &Rc<Code>,
+        condition: ExprId,
+        value: ExprId,
+        else_value: ExprId,
+    ) -> RuntimeResult<Object> {
+        return if self.expr_eval(code, condition)?.into() {
+            self.expr_eval(code, value)
         } else {
-            self.expr_eval(else_value)
+            self.expr_eval(code, else_value)
         };
     }
 
-    pub fn expr_eval(&mut self, expr: Expr) -> anyhow::Result<Object> {
-        match expr {
-            Expr::Binary(b) => self.binary_eval(b),
+    fn expr_eval(&mut self, code: &Rc<Code>, id: ExprId) -> RuntimeResult<Object> {
+        match &code.arena[id].0 {
+            Expr::Binary(b) => self.binary_eval(code, b),
             Expr::Literal(l) => Ok(l.value.0),
-            Expr::Unary(u) => self.unary_eval(u),
-            Expr::Variable { name } => self.var_eval(name.0),
+            Expr::Unary(u) => self.unary_eval(code, u),
+            Expr::Variable { name } => self.var_eval(code.expr_slot(id), name.0),
             Expr::TenaryIfStmt {
                 condition,
                 value,
                 else_value,
-            } => self.tenary_if_eval(condition.0, value.0, else_value.0),
-            Expr::FunctionCall { .. } => todo!(),
+            } => self.tenary_if_eval(code, *condition, *value, *else_value),
+            Expr::FunctionCall {
+                callee,
+                arguments,
+                safe,
+            } => self.call_eval(
+                code,
+                *callee,
+                arguments.as_deref().unwrap_or_default(),
+                *safe,
+            ),
+            Expr::Lambda(lambda) => Ok(self.lambda_eval(code, id, lambda)),
+            Expr::Interpolation {
+                template,
+                arguments,
+            } => {
+                let values = arguments
+                    .iter()
+                    .map(|arg| self.expr_eval(code, *arg))
+                    .collect::<RuntimeResult<Vec<_>>>()?;
+                interpolate(*template, &values)
+            }
+        }
+    }
+
+    /// Copies the locals the lambda uses out of the current frame
+    fn lambda_eval(&mut self, code: &Rc<Code>, id: ExprId, lambda: &Lambda) -> Object {
+        let layout = code.lambda(id);
+        let captures = layout
+            .captures
+            .iter()
+            .map(|(outer, slot)| (*slot, self.local_value(*outer)))
+            .collect();
+        let function = UserFunction {
+            parameters: lambda.parameters.clone().unwrap_or_default(),
+            statments: lambda.statments.clone(),
+            code: code.clone(),
+            frame_size: layout.frame_size,
+            memoize: false,
+        };
+        self.function_value(Callable::Lambda {
+            function: Rc::new(function),
+            captures,
+        })
+    }
+
+    /// A `safe` call is skipped when its receiver, the first argument, is null
+    fn call_eval(
+        &mut self,
+        code: &Rc<Code>,
+        callee: ExprId,
+        arguments: &[ExprId],
+        safe: bool,
+    ) -> RuntimeResult<Object> {
+        let callable = match &code.arena[callee].0 {
+            // Variables holding a function come first, then declared functions and natives
+            Expr::Variable { name } if self.variable_mut(code.expr_slot(callee)).is_none() => {
+                Callable::Named(name.0)
+            }
+            _ => {
+                let value = self.expr_eval(code, callee)?;
+                self.callable(value)?
+            }
+        };
+        let mut args = Vec::with_capacity(arguments.len());
+        for arg in arguments {
+            let value = self.expr_eval(code, *arg)?;
+            if safe && args.is_empty() && value == Object::NullValue {
+                return Ok(Object::NullValue);
+            }
+            args.push(value);
+        }
+        // `ref` and `out` parameters get the argument's variable on top of its value
+        let mut references = vec![];
+        let function = self.user_function(&callable);
+        // A call with the wrong number of arguments fails when it's made
+        if let Some(function) = function.filter(|f| f.parameters.len() == arguments.len()) {
+            for (param, arg) in function.parameters.iter().zip(arguments) {
+                let reference = match param.is_alias() {
+                    true => Some(self.reference_eval(code, *arg, param)?),
+                    false => None,
+                };
+                references.push(reference);
+            }
+        }
+        self.call_value_eval(callable, args, references)
+    }
+
+    /// The script function `callable` calls, if it calls one
+    fn user_function(&self, callable: &Callable) -> Option<Rc<UserFunction>> {
+        match callable {
+            Callable::Named(name) => self.functions.get(name).cloned(),
+            Callable::Lambda { function, .. } => Some(function.clone()),
+            _ => None,
         }
     }
 
+    /// Binds `param` to the variable passed as `arg`
+    fn reference_eval(
+        &mut self,
+        code: &Rc<Code>,
+        arg: ExprId,
+        param: &FuncParameter,
+    ) -> RuntimeResult<Reference> {
+        if !matches!(code.arena[arg].0, Expr::Variable { .. }) {
+            return Err(RuntimeError::new(
+                "Only variables can be passed to ref and out parameters!",
+            ));
+        }
+        let place = match code.expr_slot(arg) {
+            Slot::Global(global) => Place::Global(global),
+            Slot::Local { slot, global } => {
+                let frame = self.frames.len() - 1;
+                match self.frames[frame].variables[slot as usize] {
+                    Some(_) => Place::Local { frame, slot },
+                    None => Place::Global(global),
+                }
+            }
+            // Passing a reference on binds to the caller's variable
+            Slot::Reference { slot } => {
+                let outer = *self.reference_mut(slot);
+                if param.is_mutable() && !outer.mutable {
+                    return Err(RuntimeError::new("Variable is immutable!"));
+                }
+                outer.place
+            }
+        };
+        match *self.place_mut(place) {
+            Some((_, false)) if param.is_mutable() => {
+                Err(RuntimeError::new("Variable is immutable!"))
+            }
+            Some(_) => Ok(Reference {
+                place,
+                mutable: param.is_mutable(),
+                output: param.param_type.0 == ParamType::Output,
+                assigned: false,
+            }),
+            // Functions and natives can only be passed by value
+            None => Err(RuntimeError::new("Variable doesn't exist!")),
+        }
+    }
+
+    fn call_value_eval(
+        &mut self,
+        callable: Callable,
+        args: Vec<Object>,
+        references: Vec<Option<Reference>>,
+    ) -> RuntimeResult<Object> {
+        match callable {
+            Callable::Named(name) => self.named_call_eval(name, args, references),
+            Callable::Lambda { function, captures } => {
+                self.user_call_eval(&function, &captures, args, references)
+            }
+            Callable::Compiled { .. } => Err(RuntimeError::new(
+                "Functions compiled by the vm can't be called by the tree-walker!",
+            )),
+        }
+    }
+
+    fn named_call_eval(
+        &mut self,
+        name: u64,
+        args: Vec<Object>,
+        references: Vec<Option<Reference>>,
+    ) -> RuntimeResult<Object> {
+        // Script functions come first so they can shadow natives
+        if let Some(function) = self.functions.get(&name).cloned() {
+            if !function.memoize {
+                return self.user_call_eval(&function, &[], args, references);
+            }
+            if let Some(value) = self.memoized(name, &args) {
+                return Ok(value);
+            }
+            let value = self.user_call_eval(&function, &[], args.clone(), references)?;
+            self.memoize(name, args, value);
+            return Ok(value);
+        }
+        if let Some(native) = self.natives.get(&name).cloned() {
+            return native.call(self, &args);
+        }
+        Err(RuntimeError::new("Function doesn't exist!"))
+    }
+
+    /// Runs the body in a frame of its own, a trailing expression is the return value
+    fn user_call_eval(
+        &mut self,
+        function: &UserFunction,
+        captures: &[(u32, Option<Object>)],
+        args: Vec<Object>,
+        references: Vec<Option<Reference>>,
+    ) -> RuntimeResult<Object> {
+        if args.len() != function.parameters.len() {
+            return Err(RuntimeError::new(format!(
+                "Expected {} argument(s), found {}!",
+                function.parameters.len(),
+                args.len()
+            )));
+        }
+        let mut variables = vec![None; function.frame_size];
+        for (slot, (param, arg)) in function.parameters.iter().zip(args).enumerate() {
+            variables[slot] = Some((arg, param.is_mutable()));
+        }
+        // Lambdas get their own copy of what they captured, which they can't assign to
+        for (slot, value) in captures {
+            variables[*slot as usize] = value.map(|value| (value, false));
+        }
+        self.frames.push(Frame {
+            variables,
+            references,
+        });
+        let result = self.deferring(&function.code, |interperter| {
+            interperter.body_eval(&function.code, &function.statments)
+        });
+        let frame = self.frames.pop().expect("the frame was just pushed");
+        let result = result?;
+        if frame
+            .references
+            .iter()
+            .flatten()
+            .any(|reference| reference.output && !reference.assigned)
+        {
+            return Err(RuntimeError::new("Out parameter wasn't assigned!"));
+        }
+        Ok(result)
+    }
+
+    fn body_eval(&mut self, code: &Rc<Code>, statments: &[StmtId]) -> RuntimeResult<Object> {
+        match statments.split_last() {
+            Some((tail, rest)) => match &code.arena[*tail].0 {
+                Statement::Expression { expr } => {
+                    self.block_eval(code, rest)?;
+                    self.expr_eval(code, *expr)
+                }
+                _ => {
+                    self.block_eval(code, statments)?;
+                    Ok(Object::NullValue)
+                }
+            },
+            None => Ok(Object::NullValue),
+        }
+    }
+
+    /// Runs `body`, then the statements it deferred, the last one first. An error skips
+    /// them like it skips the rest of the body
+    fn deferring<T>(
+        &mut self,
+        code: &Rc<Code>,
+        body: impl FnOnce(&mut Self) -> RuntimeResult<T>,
+    ) -> RuntimeResult<T> {
+        self.defers.push(vec![]);
+        let result = body(self);
+        let defers = self.defers.pop().expect("the queue was just pushed");
+        let value = result?;
+        for stmt in defers.into_iter().rev() {
+            self.stmt_eval(code, stmt)?;
+        }
+        Ok(value)
+    }
+
     //----------------------------------------------------------------
     //-Stmt Functions-------------------------------------------------
     //----------------------------------------------------------------
 
-    fn block_eval(&mut self, statments: Vec<Spanned<Statement>>) -> anyhow::Result<()> {
+    fn block_eval(&mut self, code: &Rc<Code>, statments: &[StmtId]) -> RuntimeResult<()> {
         for statement in statments {
-            self.stmt_eval(statement.0)?
+            self.stmt_eval(code, *statement)?
         }
         Ok(())
     }
 
-    fn assign_eval(&mut self, assign: Assign) -> anyhow::Result<()> {
-        let val = self.expr_eval(assign.value.0)?; //Only is at top due to error about "Borrowing"
-        let var = match self.vars.get_mut(&assign.name.0) {
+    fn assign_eval(&mut self, code: &Rc<Code>, id: StmtId, assign: &Assign) -> RuntimeResult<()> {
+        let val = self.expr_eval(code, assign.value)?; //Only is at top due to error about "Borrowing"
+        let slot = code.stmt_slot(id);
+        if let Slot::Reference { slot } = slot {
+            let reference = self.reference_mut(slot);
+            if !reference.mutable {
+                return Err(RuntimeError::new("Variable is immutable!"));
+            }
+            if assign.operator.0 != AssignOp::Set {
+                reference.check_read()?;
+            }
+            reference.assigned = true;
+        }
+        let var = match self.variable_mut(slot) {
             Some(o) => o,
-            None => return Err(anyhow::anyhow!("Variable doesn't exist!")),
+            None => return Err(RuntimeError::new("Variable doesn't exist!")),
         };
         if !var.1 {
-            return Err(anyhow::anyhow!("Variable is immutable!"));
-        }
-        use crate::ast::misc::AssignOp::*;
-        match assign.operator.0 {
-            Add => var.0 = (var.0 + val)?,
-            Sub => var.0 = (var.0 - val)?,
-            Mul => var.0 = (var.0 * val)?,
-            Div => var.0 = (var.0 / val)?,
-            Set => var.0 = val,
+            return Err(RuntimeError::new("Variable is immutable!"));
         }
+        var.0 = assign_op(assign.operator.0, var.0, val)?;
         Ok(())
     }
 
     fn declar_eval(
         &mut self,
-        declaration_type: crate::ast::misc::DeclarationType,
-        name: u64,
-        _manual_type: Option<Spanned<crate::utils::valtype::Type>>,
-        value: Box<Spanned<Expr>>,
-    ) -> anyhow::Result<()> {
-        if self.vars.contains_key(&name) {
-            return Err(anyhow::anyhow!("Variable already declared!"));
+        code: &Rc<Code>,
+        id: StmtId,
+        declaration_type: DeclarationType,
+        value: ExprId,
+    ) -> RuntimeResult<()> {
+        let slot = code.stmt_slot(id);
+        if self.scope_mut(slot).is_some() {
+            return Err(RuntimeError::new("Variable already declared!"));
         }
         let mutable = match declaration_type {
-            crate::ast::misc::DeclarationType::Mutable => true,
-            crate::ast::misc::DeclarationType::Immutable => false,
+            DeclarationType::Mutable => true,
+            DeclarationType::Immutable => false,
         };
-        let val = self.expr_eval(value.0)?;
-        self.vars.insert(name, (val, mutable));
+        let val = self.expr_eval(code, value)?;
+        *self.scope_mut(slot) = Some((val, mutable));
         Ok(())
     }
 
     fn if_eval(
         &mut self,
-        condition: Expr,
-        then_branch: Statement,
-        else_branch: Option<Box<Spanned<Statement>>>,
-    ) -> anyhow::Result<()> {
-        if let Object::Boolean(b) = self.expr_eval(condition)? {
+        code: &Rc<Code>,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) -> RuntimeResult<()> {
+        if let Object::Boolean(b) = self.expr_eval(code, condition)? {
             if b {
-                self.stmt_eval(then_branch)
+                self.stmt_eval(code, then_branch)
             } else if let Some(else_then) = else_branch {
-                self.stmt_eval(else_then.0)
+                self.stmt_eval(code, else_then)
             } else {
                 Ok(())
             }
         } else {
-            Err(anyhow::anyhow!("Not a Bool!"))
+            Err(RuntimeError::new("Not a Bool!"))
         }
     }
 
-    fn match_eval(&mut self, match_stmt: MatchStmt) -> anyhow::Result<()> {
-        let p_k = self.expr_eval(match_stmt.predicate.0)?; //Possible key
+    fn match_eval(&mut self, code: &Rc<Code>, match_stmt: &MatchStmt) -> RuntimeResult<()> {
+        let p_k = self.expr_eval(code, match_stmt.predicate)?; //Possible key
         let mut found = false;
-        for key_value_pair in match_stmt.then_branches.0 {
-            if let Pattern::Literal(lp) = key_value_pair.0 {
+        for key_value_pair in &match_stmt.then_branches.0 {
+            if let Pattern::Literal(lp) = &key_value_pair.0 {
                 if lp.value.0 == p_k {
-                    self.stmt_eval(key_value_pair.1)?;
+                    self.stmt_eval(code, key_value_pair.1)?;
                     found = true;
                     break;
                 }
@@ -185,55 +809,192 @@ impl Interperter {
         Ok(())
     }
 
-    pub fn while_eval(&mut self, condition: Expr, then_branch: Statement) -> anyhow::Result<()> {
-        if let Object::Boolean(mut b) = self.expr_eval(condition.clone())? {
+    fn while_eval(
+        &mut self,
+        code: &Rc<Code>,
+        condition: ExprId,
+        then_branch: StmtId,
+    ) -> RuntimeResult<()> {
+        if let Object::Boolean(mut b) = self.expr_eval(code, condition)? {
             while b {
-                self.stmt_eval(then_branch.clone())?;
-                b = self.expr_eval(condition.to_owned())?.into();
+                self.stmt_eval(code, then_branch)?;
+                b = self.expr_eval(code, condition)?.into();
             }
             Ok(())
         } else {
-            Err(anyhow::anyhow!("Not a bool!"))
+            Err(RuntimeError::new("Not a bool!"))
+        }
+    }
+
+    /// The exit hook runs however the body ends, an error from the body wins over one from the hook
+    fn with_eval(
+        &mut self,
+        code: &Rc<Code>,
+        id: StmtId,
+        value: ExprId,
+        statments: &[StmtId],
+    ) -> RuntimeResult<()> {
+        let slot = code.stmt_slot(id);
+        if self.scope_mut(slot).is_some() {
+            return Err(RuntimeError::new("Variable already declared!"));
+        }
+        let resource = self.expr_eval(code, value)?;
+        let hooks = self.resource(producer(&code.arena, value))?;
+        if let Some(enter) = hooks.enter {
+            self.call_value_eval(Callable::Named(enter), vec![resource], vec![])?;
         }
+        *self.scope_mut(slot) = Some((resource, false));
+        let result = self.block_eval(code, statments);
+        // The resource is gone once it's exited, so the name can be bound again
+        *self.scope_mut(slot) = None;
+        let exited = self.call_value_eval(Callable::Named(hooks.exit), vec![resource], vec![]);
+        result.and(exited.map(|_| ()))
     }
 
-    pub fn stmt_eval(&mut self, stmt: Statement) -> anyhow::Result<()> {
-        match stmt {
-            Statement::Error => return Err(anyhow::anyhow!("Error statment!")),
-            Statement::Block { statments } => self.block_eval(statments)?,
-            Statement::Assign(a) => self.assign_eval(a)?,
-            Statement::Expression { .. } => todo!(),
+    fn stmt_eval(&mut self, code: &Rc<Code>, id: StmtId) -> RuntimeResult<()> {
+        match &code.arena[id].0 {
+            Statement::Error => return Err(RuntimeError::new("Error statment!")),
+            Statement::Block { statments } => self.block_eval(code, statments)?,
+            Statement::Assign(a) => self.assign_eval(code, id, a)?,
+            Statement::Expression { expr } => {
+                self.expr_eval(code, *expr)?;
+            }
             Statement::Declaration {
                 declaration_type,
-                name,
-                manual_type,
                 value,
-            } => self.declar_eval(declaration_type, name.0, manual_type, value)?,
-            Statement::FuncDeclaration { .. } => todo!(),
+                ..
+            } => self.declar_eval(code, id, *declaration_type, *value)?,
+            Statement::FuncDeclaration {
+                name,
+                parameters,
+                statments,
+                ..
+            } => {
+                let function = UserFunction {
+                    parameters: parameters.clone().unwrap_or_default(),
+                    statments: statments.clone(),
+                    code: code.clone(),
+                    frame_size: code.frame_size(id),
+                    memoize: code.arena[id].0.attribute("memoize").is_some(),
+                };
+                self.forget(name.0);
+                self.set_resource(name.0, ResourceHooks::of(code.arena[id].0.attributes()));
+                self.functions.insert(name.0, Rc::new(function));
+            }
+            Statement::Extern(declaration) => self.define_extern(declaration)?,
             Statement::IfStmt {
                 condition,
                 then_branch,
                 else_branch,
-            } => self.if_eval(condition.0, then_branch.0, else_branch)?,
-            Statement::MatchStmt(match_stmt) => self.match_eval(match_stmt)?,
+            } => self.if_eval(code, *condition, *then_branch, *else_branch)?,
+            Statement::MatchStmt(match_stmt) => self.match_eval(code, match_stmt)?,
             Statement::WhileStmt {
                 condition,
                 then_branch,
-            } => self.while_eval(condition.0, then_branch.0)?,
-            Statement::Defer { .. } => todo!(),
+            } => self.while_eval(code, *condition, *then_branch)?,
+            Statement::Defer { defered_statment } => self
+                .defers
+                .last_mut()
+                .expect("statements run in a body")
+                .push(*defered_statment),
+            Statement::With {
+                value, statments, ..
+            } => self.with_eval(code, id, *value, statments)?,
             Statement::Empty => return Ok(()),
-            Statement::Test(expr) => self.test_eval(expr)?,
+            Statement::Test(expr) => self.test_eval(code, *expr)?,
+            // Test declarations only run under the test runner
+            Statement::TestDeclaration { .. } => (),
+            // Modules run before the programs importing them
+            Statement::Use { .. } => (),
         }
         Ok(())
     }
 
-    fn test_eval(&mut self, expr: Expr) -> anyhow::Result<()> {
-        let obj = self.expr_eval(expr)?;
-        println!("Test Output:{obj}");
-        Ok(())
+    pub fn program_eval(&mut self, program: &Program) -> RuntimeResult<()> {
+        let code = Rc::new(resolve(program, &mut self.vars));
+        self.deferring(&code, |interperter| {
+            interperter.block_eval(&code, &program.statements)
+        })
     }
 
-    //----------------------------------------------------------------
-    //-Misc Functions-------------------------------------------------
-    //----------------------------------------------------------------
+    /// Like `program_eval`, but a trailing expression statement is returned
+    pub fn program_value_eval(&mut self, program: &Program) -> RuntimeResult<Object> {
+        let code = Rc::new(resolve(program, &mut self.vars));
+        self.deferring(&code, |interperter| {
+            interperter.body_eval(&code, &program.statements)
+        })
+    }
+
+    fn test_eval(&mut self, code: &Rc<Code>, expr: ExprId) -> RuntimeResult<()> {
+        let obj = self.expr_eval(code, expr)?;
+        self.write_output(&format!("Test Output:{obj}\n"))
+    }
+}
+
+//----------------------------------------------------------------
+//-Misc Functions-------------------------------------------------
+//----------------------------------------------------------------
+// Shared with the vm so both engines compute the same results
+
+pub(crate) fn unary_op(operator: UnaryOp, value: Object) -> RuntimeResult<Object> {
+    match operator {
+        UnaryOp::Neg => match value {
+            Object::Integer(i) => Ok(Object::Integer(-i)),
+            Object::Long(l) => Ok(Object::Long(-l)),
+            Object::Float(f) => Ok(Object::Float(-f)),
+            _ => Err(RuntimeError::new("Invalid value type!")),
+        },
+        UnaryOp::Not => match value {
+            Object::Boolean(b) => Ok(Object::Boolean(!b)),
+            _ => Err(RuntimeError::new("Invalid value type!")),
+        },
+    }
+}
+
+pub(crate) fn binary_op(operator: BinaryOp, lhs: Object, rhs: Object) -> RuntimeResult<Object> {
+    if operator == BinaryOp::Coalesce {
+        return Ok(match lhs {
+            Object::NullValue => rhs,
+            lhs => lhs,
+        });
+    }
+    let (lhs, rhs) = lhs.promote(rhs);
+    use BinaryOp::*;
+    match operator {
+        Add => lhs + rhs,
+        Sub => lhs - rhs,
+        Mul => lhs * rhs,
+        Div => lhs / rhs,
+        GreaterThan => Ok(Object::Boolean(lhs > rhs)),
+        GreaterThanEqual => Ok(Object::Boolean(lhs >= rhs)),
+        LessThan => Ok(Object::Boolean(lhs < rhs)),
+        LessThanEqual => Ok(Object::Boolean(lhs <= rhs)),
+        Equal => Ok(Object::Boolean(lhs == rhs)),
+        NotEqual => Ok(Object::Boolean(lhs != rhs)),
+        And => Ok(Object::Boolean(lhs.into() && rhs.into())),
+        Or => Ok(Object::Boolean(lhs.into() || rhs.into())),
+        Coalesce => unreachable!("`??` keeps its operands as they are"),
+    }
+}
+
+pub(crate) fn assign_op(
+    operator: AssignOp,
+    current: Object,
+    value: Object,
+) -> RuntimeResult<Object> {
+    match operator {
+        AssignOp::Add => current + value,
+        AssignOp::Sub => current - value,
+        AssignOp::Mul => current * value,
+        AssignOp::Div => current / value,
+        AssignOp::Set => Ok(value),
+        AssignOp::SetIfNull if current == Object::NullValue => Ok(value),
+        AssignOp::SetIfNull => Ok(current),
+    }
+}
+
+/// Fills the placeholders of an interpolated string's template with its arguments
+pub(crate) fn interpolate(template: Spur, values: &[Object]) -> RuntimeResult<Object> {
+    let formatted = format_template(INTERNER.resolve(&template), values)?;
+    Ok(Object::String(INTERNER.get_or_intern(formatted)))
 }
@@ -0,0 +1,159 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt::Debug,
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+use crate::utils::error::{RuntimeError, RuntimeResult};
+
+/// Where everything a script prints ends up
+pub trait OutputSink {
+    fn write(&mut self, text: &str) -> RuntimeResult<()>;
+
+    fn flush(&mut self) -> RuntimeResult<()> {
+        Ok(())
+    }
+}
+
+/// Where `input` reads lines from
+pub trait InputSource {
+    /// A line without its line ending, `None` once there's nothing left
+    fn read_line(&mut self) -> RuntimeResult<Option<String>>;
+}
+
+//----------------------------------------------------------------
+//-Output---------------------------------------------------------
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdoutSink;
+
+impl OutputSink for StdoutSink {
+    fn write(&mut self, text: &str) -> RuntimeResult<()> {
+        std::io::stdout()
+            .write_all(text.as_bytes())
+            .map_err(RuntimeError::new)
+    }
+
+    fn flush(&mut self) -> RuntimeResult<()> {
+        std::io::stdout().flush().map_err(RuntimeError::new)
+    }
+}
+
+/// Keeps output in memory, clones share the same buffer so the host can read
+/// what a script wrote through the copy it kept
+#[derive(Debug, Clone, Default)]
+pub struct BufferSink {
+    buffer: Rc<RefCell<String>>,
+}
+
+impl BufferSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contents(&self) -> String {
+        self.buffer.borrow().clone()
+    }
+
+    /// The contents, leaving the buffer empty
+    pub fn take(&self) -> String {
+        std::mem::take(&mut self.buffer.borrow_mut())
+    }
+}
+
+impl OutputSink for BufferSink {
+    fn write(&mut self, text: &str) -> RuntimeResult<()> {
+        self.buffer.borrow_mut().push_str(text);
+        Ok(())
+    }
+}
+
+/// Sends output to any `Write`, like a file or a host's log
+#[derive(Debug)]
+pub struct WriterSink<W: Write>(pub W);
+
+impl<W: Write> OutputSink for WriterSink<W> {
+    fn write(&mut self, text: &str) -> RuntimeResult<()> {
+        self.0.write_all(text.as_bytes()).map_err(RuntimeError::new)
+    }
+
+    fn flush(&mut self) -> RuntimeResult<()> {
+        self.0.flush().map_err(RuntimeError::new)
+    }
+}
+
+//----------------------------------------------------------------
+//-Input----------------------------------------------------------
+//----------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdinSource;
+
+impl InputSource for StdinSource {
+    fn read_line(&mut self) -> RuntimeResult<Option<String>> {
+        read_line(&mut std::io::stdin().lock())
+    }
+}
+
+/// Hands out prepared lines, then reports the end of input
+#[derive(Debug, Clone, Default)]
+pub struct BufferSource {
+    lines: VecDeque<String>,
+}
+
+impl BufferSource {
+    pub fn new(input: &str) -> Self {
+        Self {
+            lines: input.lines().map(str::to_string).collect(),
+        }
+    }
+}
+
+impl InputSource for BufferSource {
+    fn read_line(&mut self) -> RuntimeResult<Option<String>> {
+        Ok(self.lines.pop_front())
+    }
+}
+
+/// Reads lines from any `BufRead`
+#[derive(Debug)]
+pub struct ReaderSource<R: BufRead>(pub R);
+
+impl<R: BufRead> InputSource for ReaderSource<R> {
+    fn read_line(&mut self) -> RuntimeResult<Option<String>> {
+        read_line(&mut self.0)
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> RuntimeResult<Option<String>> {
+    let mut line = String::new();
+    match reader.read_line(&mut line).map_err(RuntimeError::new)? {
+        0 => Ok(None),
+        _ => Ok(Some(line.trim_end_matches(['\r', '\n']).to_string())),
+    }
+}
+
+/// The sink and source an interpreter talks to, clones of an interpreter share them
+#[derive(Clone)]
+pub struct Io {
+    pub output: Rc<RefCell<dyn OutputSink>>,
+    pub input: Rc<RefCell<dyn InputSource>>,
+}
+
+impl Default for Io {
+    fn default() -> Self {
+        Self {
+            output: Rc::new(RefCell::new(StdoutSink)),
+            input: Rc::new(RefCell::new(StdinSource)),
+        }
+    }
+}
+
+impl Debug for Io {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Io")
+    }
+}
@@ -0,0 +1,36 @@
+use std::{fmt::Debug, rc::Rc};
+
+use crate::utils::{error::RuntimeResult, object::Object};
+
+use super::interperter::Interperter;
+
+type NativeCall = dyn Fn(&mut Interperter, &[Object]) -> RuntimeResult<Object>;
+
+/// A function implemented in Rust that scripts can call like any other function
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    func: Rc<NativeCall>,
+}
+
+impl NativeFunction {
+    pub fn new(
+        name: impl Into<String>,
+        func: impl Fn(&mut Interperter, &[Object]) -> RuntimeResult<Object> + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            func: Rc::new(func),
+        }
+    }
+
+    pub fn call(&self, interperter: &mut Interperter, args: &[Object]) -> RuntimeResult<Object> {
+        (self.func)(interperter, args)
+    }
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NativeFunction({})", self.name)
+    }
+}
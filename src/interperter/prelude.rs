@@ -0,0 +1,158 @@
+use rust_decimal::Decimal;
+
+use crate::utils::{
+    error::{RuntimeError, RuntimeResult},
+    interner::INTERNER,
+    object::Object,
+};
+
+use super::{interperter::interpolate, native::NativeFunction};
+
+/// The natives every interpreter starts with, scripts can declare functions with
+/// the same names to replace them
+pub fn prelude() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new("print", |interperter, args| {
+            interperter.write_output(&join(args))?;
+            Ok(Object::NullValue)
+        }),
+        NativeFunction::new("println", |interperter, args| {
+            interperter.write_output(&format!("{}\n", join(args)))?;
+            Ok(Object::NullValue)
+        }),
+        NativeFunction::new("input", |interperter, args| {
+            interperter.write_output(&join(args))?;
+            Ok(interperter.read_line()?.map_or(Object::NullValue, string))
+        }),
+        NativeFunction::new("len", |_, args| match single(args)? {
+            Object::String(s) => Ok(Object::Integer(INTERNER.resolve(&s).chars().count() as i32)),
+            value => Err(RuntimeError::new(format!("'{value}' has no length!"))),
+        }),
+        NativeFunction::new("type_of", |_, args| {
+            let name = match single(args)? {
+                Object::String(_) => "string",
+                Object::Integer(_) => "int",
+                Object::Long(_) => "long",
+                Object::Float(_) => "float",
+                Object::Boolean(_) => "bool",
+                Object::NullValue => "null",
+                Object::Function(_) => "function",
+            };
+            Ok(string(name))
+        }),
+        NativeFunction::new("to_string", |_, args| Ok(string(single(args)?.to_string()))),
+        NativeFunction::new("format", |_, args| match args.split_first() {
+            Some((Object::String(template), values)) => interpolate(*template, values),
+            _ => Err(RuntimeError::new("format expects a template string!")),
+        }),
+        NativeFunction::new("parse_int", |_, args| {
+            let text = single(args)?.to_string();
+            Ok(text
+                .trim()
+                .parse()
+                .map_or(Object::NullValue, Object::Integer))
+        }),
+        NativeFunction::new("parse_float", |_, args| {
+            let text = single(args)?.to_string();
+            Ok(text
+                .trim()
+                .parse::<Decimal>()
+                .or_else(|_| Decimal::from_scientific(text.trim()))
+                .map_or(Object::NullValue, Object::Float))
+        }),
+        NativeFunction::new("assert", |_, args| match args {
+            [Object::Boolean(true)] | [Object::Boolean(true), _] => Ok(Object::NullValue),
+            [Object::Boolean(false)] => Err(RuntimeError::new("Assertion failed!")),
+            [Object::Boolean(false), message] => {
+                Err(RuntimeError::new(format!("Assertion failed, {message}")))
+            }
+            _ => Err(RuntimeError::new(
+                "assert expects a bool and an optional message!",
+            )),
+        }),
+        NativeFunction::new("assert_eq", |_, args| {
+            let (left, right, message) = match args {
+                [left, right] => (*left, *right, None),
+                [left, right, message] => (*left, *right, Some(message)),
+                _ => {
+                    return Err(RuntimeError::new(
+                        "assert_eq expects two values and an optional message!",
+                    ))
+                }
+            };
+            let (promoted_left, promoted_right) = left.promote(right);
+            if promoted_left == promoted_right {
+                return Ok(Object::NullValue);
+            }
+            let header = match message {
+                Some(message) => format!("Assertion failed, {message}"),
+                None => "Assertion failed, left != right".to_string(),
+            };
+            Err(RuntimeError::new(format!(
+                "{header}\n{}",
+                diff(&left.to_string(), &right.to_string())
+            )))
+        }),
+        NativeFunction::new("panic", |_, args| {
+            Err(RuntimeError::new(match args {
+                [] => "Panicked!".to_string(),
+                args => join(args),
+            }))
+        }),
+    ]
+}
+
+/// Both values on their own line, or a line by line diff when they span several lines
+fn diff(left: &str, right: &str) -> String {
+    if !left.contains('\n') && !right.contains('\n') {
+        return format!("  left: {left}\n right: {right}");
+    }
+    let left: Vec<_> = left.lines().collect();
+    let right: Vec<_> = right.lines().collect();
+    // Longest common subsequence of lines, filled from the back so the walk can go forwards
+    let mut lengths = vec![vec![0; right.len() + 1]; left.len() + 1];
+    for i in (0..left.len()).rev() {
+        for j in (0..right.len()).rev() {
+            lengths[i][j] = match left[i] == right[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+    let mut lines = vec!["--- left".to_string(), "+++ right".to_string()];
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() || j < right.len() {
+        if i < left.len() && j < right.len() && left[i] == right[j] {
+            lines.push(format!(" {}", left[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if j == right.len() || (i < left.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            lines.push(format!("-{}", left[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+{}", right[j]));
+            j += 1;
+        }
+    }
+    lines.join("\n")
+}
+
+fn string(text: impl AsRef<str>) -> Object {
+    Object::String(INTERNER.get_or_intern(text))
+}
+
+fn join(args: &[Object]) -> String {
+    args.iter()
+        .map(Object::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn single(args: &[Object]) -> RuntimeResult<Object> {
+    match args {
+        [value] => Ok(*value),
+        _ => Err(RuntimeError::new(format!(
+            "Expected 1 argument(s), found {}!",
+            args.len()
+        ))),
+    }
+}
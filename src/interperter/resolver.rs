@@ -0,0 +1,357 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::{
+        arena::{Arena, ExprId, StmtId},
+        expr::{Expr, Lambda},
+        misc::FuncParameter,
+        stmt::{Program, Statement},
+    },
+    checker::names::{self, Declaration, Environment, Owner, Resolution},
+};
+
+use super::interperter::Globals;
+
+/// Where a variable lives, worked out before the program runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Slot {
+    Global(u32),
+    /// Functions fall back to the global while the local hasn't been declared yet
+    Local {
+        slot: u32,
+        global: u32,
+    },
+    /// A `ref` or `out` parameter, which stands for a variable of the caller
+    Reference {
+        slot: u32,
+    },
+}
+
+/// A parsed program along with the slots of every variable in it
+#[derive(Debug)]
+pub(crate) struct Code {
+    pub arena: Rc<Arena>,
+    /// Indexed by `ExprId`, set for variables
+    exprs: Vec<Option<Slot>>,
+    /// Indexed by `StmtId`, set for assignments and declarations
+    stmts: Vec<Option<Slot>>,
+    /// How many locals each function declaration needs
+    frames: HashMap<StmtId, usize>,
+    lambdas: HashMap<ExprId, Layout>,
+}
+
+/// The frame of a lambda, captured locals come after its parameters and declarations
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Layout {
+    pub frame_size: usize,
+    /// The slot of each captured local in the enclosing frame and the slot it's copied into
+    pub captures: Vec<(u32, u32)>,
+}
+
+impl Code {
+    pub fn expr_slot(&self, id: ExprId) -> Slot {
+        self.exprs[id.index()].expect("variables are resolved")
+    }
+
+    pub fn stmt_slot(&self, id: StmtId) -> Slot {
+        self.stmts[id.index()].expect("assignments and declarations are resolved")
+    }
+
+    pub fn frame_size(&self, id: StmtId) -> usize {
+        self.frames[&id]
+    }
+
+    pub fn lambda(&self, id: ExprId) -> &Layout {
+        &self.lambdas[&id]
+    }
+}
+
+/// Gives every variable in `program` a slot, globals get theirs from `globals`. Locals are
+/// the declarations name resolution binds each use to, anything it can't bind is global
+pub(crate) fn resolve(program: &Program, globals: &mut Globals) -> Code {
+    let arena = &program.arena;
+    let resolution = names::bind(program, &Environment::new());
+    let mut resolver = Resolver {
+        arena,
+        resolution: &resolution,
+        globals,
+        functions: vec![],
+        code: Code {
+            arena: arena.clone(),
+            exprs: vec![None; arena.expr_count()],
+            stmts: vec![None; arena.stmt_count()],
+            frames: HashMap::new(),
+            lambdas: HashMap::new(),
+        },
+    };
+    resolver.statements(&program.statements);
+    resolver.code
+}
+
+/// The locals of a function being resolved
+struct Frame {
+    locals: HashMap<Declaration, u32>,
+    slots: u32,
+    /// The slots of the `ref` and `out` parameters
+    references: Vec<u32>,
+    /// `Some` for lambdas, which copy in the locals they use from the frame around them
+    captures: Option<Vec<(u32, u32)>>,
+}
+
+struct Resolver<'a> {
+    arena: &'a Arena,
+    resolution: &'a Resolution,
+    globals: &'a mut Globals,
+    /// The functions being resolved, innermost last, empty at the top level where everything is global
+    functions: Vec<Frame>,
+    code: Code,
+}
+
+impl Resolver<'_> {
+    fn slot(&mut self, name: u64, declaration: Option<Declaration>) -> Slot {
+        let global = self.globals.slot(name);
+        let depth = self.functions.len().checked_sub(1);
+        match depth.zip(declaration) {
+            Some((depth, declaration)) => match self.local(depth, declaration) {
+                Some(slot) if self.functions[depth].references.contains(&slot) => {
+                    Slot::Reference { slot }
+                }
+                Some(slot) => Slot::Local { slot, global },
+                None => Slot::Global(global),
+            },
+            None => Slot::Global(global),
+        }
+    }
+
+    /// The slot `declaration` has in the function at `depth`, capturing it from the functions
+    /// around lambdas the first time it's used
+    fn local(&mut self, depth: usize, declaration: Declaration) -> Option<u32> {
+        let frame = &self.functions[depth];
+        if let Some(slot) = frame.locals.get(&declaration) {
+            return Some(*slot);
+        }
+        if frame.captures.is_none() || depth == 0 {
+            return None;
+        }
+        let outer = self.local(depth - 1, declaration)?;
+        let frame = &mut self.functions[depth];
+        let slot = frame.slots;
+        frame.slots += 1;
+        frame.locals.insert(declaration, slot);
+        frame.captures.as_mut()?.push((outer, slot));
+        Some(slot)
+    }
+
+    fn statements(&mut self, statements: &[StmtId]) {
+        for stmt in statements {
+            self.statement(*stmt);
+        }
+    }
+
+    fn statement(&mut self, id: StmtId) {
+        let arena = self.arena;
+        match &arena[id].0 {
+            Statement::Error | Statement::Empty | Statement::Use { .. } | Statement::Extern(_) => {}
+            Statement::Test(expr) | Statement::Expression { expr } => self.expr(*expr),
+            // Test bodies run at the top level under the test runner
+            Statement::TestDeclaration { statments, .. } | Statement::Block { statments } => {
+                self.statements(statments)
+            }
+            Statement::Assign(assign) => {
+                self.expr(assign.value);
+                self.code.stmts[id.index()] =
+                    Some(self.slot(assign.name.0, self.resolution.stmt(id)));
+            }
+            Statement::Declaration { name, value, .. } => {
+                self.expr(*value);
+                self.code.stmts[id.index()] =
+                    Some(self.slot(name.0, Some(Declaration::Variable(id))));
+            }
+            Statement::FuncDeclaration {
+                parameters,
+                statments,
+                ..
+            } => self.function(id, parameters.as_deref().unwrap_or_default(), statments),
+            Statement::IfStmt {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(*condition);
+                self.statement(*then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(*else_branch);
+                }
+            }
+            Statement::MatchStmt(match_stmt) => {
+                self.expr(match_stmt.predicate);
+                for (_, arm) in &match_stmt.then_branches.0 {
+                    self.statement(*arm);
+                }
+            }
+            Statement::WhileStmt {
+                condition,
+                then_branch,
+            } => {
+                self.expr(*condition);
+                self.statement(*then_branch);
+            }
+            Statement::Defer { defered_statment } => self.statement(*defered_statment),
+            Statement::With {
+                name,
+                value,
+                statments,
+            } => {
+                self.expr(*value);
+                self.code.stmts[id.index()] =
+                    Some(self.slot(name.0, Some(Declaration::Variable(id))));
+                self.statements(statments);
+            }
+        }
+    }
+
+    fn function(&mut self, id: StmtId, parameters: &[FuncParameter], statments: &[StmtId]) {
+        let frame = self.frame(Owner::Function(id), parameters, statments, None);
+        self.code.frames.insert(id, frame.slots as usize);
+    }
+
+    fn lambda(&mut self, id: ExprId, lambda: &Lambda) {
+        let parameters = lambda.parameters.as_deref().unwrap_or_default();
+        let frame = self.frame(
+            Owner::Lambda(id),
+            parameters,
+            &lambda.statments,
+            Some(vec![]),
+        );
+        let layout = Layout {
+            frame_size: frame.slots as usize,
+            captures: frame.captures.unwrap_or_default(),
+        };
+        self.code.lambdas.insert(id, layout);
+    }
+
+    /// Resolves a function body in a frame of its own
+    fn frame(
+        &mut self,
+        owner: Owner,
+        parameters: &[FuncParameter],
+        statments: &[StmtId],
+        captures: Option<Vec<(u32, u32)>>,
+    ) -> Frame {
+        let (locals, slots) = locals(self.arena, owner, parameters, statments);
+        let references = (0..parameters.len() as u32)
+            .filter(|slot| parameters[*slot as usize].is_alias())
+            .collect();
+        self.functions.push(Frame {
+            locals,
+            slots: slots as u32,
+            references,
+            captures,
+        });
+        self.statements(statments);
+        self.functions.pop().expect("the frame was just pushed")
+    }
+
+    fn expr(&mut self, id: ExprId) {
+        let arena = self.arena;
+        match &arena[id].0 {
+            Expr::Literal(_) => (),
+            Expr::Variable { name } => {
+                self.code.exprs[id.index()] = Some(self.slot(name.0, self.resolution.expr(id)))
+            }
+            Expr::Unary(unary) => self.expr(unary.right),
+            Expr::Binary(binary) => {
+                self.expr(binary.left);
+                self.expr(binary.right);
+            }
+            Expr::TenaryIfStmt {
+                condition,
+                value,
+                else_value,
+            } => {
+                for e in [condition, value, else_value] {
+                    self.expr(*e);
+                }
+            }
+            Expr::FunctionCall {
+                callee, arguments, ..
+            } => {
+                self.expr(*callee);
+                for arg in arguments.iter().flatten() {
+                    self.expr(*arg);
+                }
+            }
+            Expr::Interpolation { arguments, .. } => {
+                for arg in arguments {
+                    self.expr(*arg);
+                }
+            }
+            Expr::Lambda(lambda) => self.lambda(id, lambda),
+        }
+    }
+}
+
+/// The slots of a function's locals and how many there are. Parameters take the first
+/// slots and declarations repeating a name share its slot
+fn locals(
+    arena: &Arena,
+    owner: Owner,
+    parameters: &[FuncParameter],
+    statments: &[StmtId],
+) -> (HashMap<Declaration, u32>, usize) {
+    let mut slots: HashMap<u64, u32> = HashMap::new();
+    let mut locals = HashMap::new();
+    let mut local = |name: u64, declaration: Declaration| {
+        let count = slots.len() as u32;
+        locals.insert(declaration, *slots.entry(name).or_insert(count));
+    };
+    for (index, param) in parameters.iter().enumerate() {
+        local(
+            param.param_value_name.0,
+            Declaration::Parameter { owner, index },
+        );
+    }
+    declarations(arena, statments, &mut |name, id| {
+        local(name, Declaration::Variable(id))
+    });
+    (locals, slots.len())
+}
+
+/// Calls `declare` with every name declared in a function body, blocks don't open scopes
+/// of their own
+fn declarations(arena: &Arena, statements: &[StmtId], declare: &mut impl FnMut(u64, StmtId)) {
+    for stmt in statements {
+        declaration(arena, *stmt, declare);
+    }
+}
+
+fn declaration(arena: &Arena, id: StmtId, declare: &mut impl FnMut(u64, StmtId)) {
+    match &arena[id].0 {
+        Statement::Declaration { name, .. } => declare(name.0, id),
+        Statement::With {
+            name, statments, ..
+        } => {
+            declare(name.0, id);
+            declarations(arena, statments, declare);
+        }
+        Statement::Block { statments } => declarations(arena, statments, declare),
+        Statement::IfStmt {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            declaration(arena, *then_branch, declare);
+            if let Some(else_branch) = else_branch {
+                declaration(arena, *else_branch, declare);
+            }
+        }
+        Statement::MatchStmt(match_stmt) => match_stmt
+            .then_branches
+            .0
+            .iter()
+            .for_each(|(_, arm)| declaration(arena, *arm, declare)),
+        Statement::WhileStmt { then_branch, .. } => declaration(arena, *then_branch, declare),
+        Statement::Defer { defered_statment } => declaration(arena, *defered_statment, declare),
+        _ => (),
+    }
+}
@@ -0,0 +1,171 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    engine::{engine::Engine, error::Error},
+    utils::error::{RuntimeError, RuntimeResult},
+};
+
+use super::{
+    interperter::Interperter,
+    io::{BufferSink, BufferSource, OutputSink, ReaderSource, WriterSink},
+};
+
+fn run(source: &str) -> Result<String, Error> {
+    let mut engine = Engine::new();
+    engine.capture_output();
+    engine.run_source(source)?;
+    Ok(engine.take_output())
+}
+
+#[test]
+fn prelude_output() {
+    assert_eq!(
+        "a 1\nb\n",
+        run("println(\"a\", 1); print(\"b\"); println();").unwrap()
+    );
+}
+
+#[test]
+fn prelude_conversions() {
+    let mut engine = Engine::new();
+    assert_eq!(3, engine.eval::<i32>("len(\"abc\")").unwrap());
+    assert_eq!("float", engine.eval::<String>("type_of(1.5)").unwrap());
+    assert_eq!("null", engine.eval::<String>("type_of(null)").unwrap());
+    assert_eq!("42", engine.eval::<String>("to_string(42)").unwrap());
+    assert_eq!(
+        Some(-7),
+        engine.eval::<Option<i32>>("parse_int(\" -7 \")").unwrap()
+    );
+    assert_eq!(
+        None,
+        engine.eval::<Option<i32>>("parse_int(\"x\")").unwrap()
+    );
+    assert_eq!(2.5, engine.eval::<f64>("parse_float(\"2.5\")").unwrap());
+}
+
+#[test]
+fn prelude_failures() {
+    assert_eq!(Ok(String::new()), run("assert(1 == 1);"));
+    assert_eq!(
+        Err(Error::Runtime(RuntimeError::new("Assertion failed, math"))),
+        run("assert(1 == 2, \"math\");")
+    );
+    assert_eq!(
+        Err(Error::Runtime(RuntimeError::new("Oh no 3"))),
+        run("panic(\"Oh no\", 3);")
+    );
+}
+
+#[test]
+fn functions_shadow_prelude() {
+    let source = "fn len(val s: string), int {\n99\n}\nfn add(val a: int, val b: int), int {\na + b\n}\nprintln(len(\"abc\"), add(1, 2));";
+    assert_eq!("99 3\n", run(source).unwrap());
+    assert_eq!(
+        Err(Error::Runtime(RuntimeError::new(
+            "Expected 2 argument(s), found 1!"
+        ))),
+        run("fn add(val a: int, val b: int) {\na + b\n}\nadd(1);")
+    );
+}
+
+#[test]
+fn output_sinks() {
+    let mut interperter = Interperter::default();
+    let buffer = BufferSink::new();
+    interperter.set_output(buffer.clone());
+    interperter.set_input(BufferSource::new("Ada\n"));
+    let program = Engine::new()
+        .compile("let name = input(\"Name? \"); println(\"Hi\", name); println(input());")
+        .unwrap();
+    interperter.program_eval(&program).unwrap();
+    assert_eq!("Name? Hi Ada\nnull\n", buffer.contents());
+
+    let mut engine = Engine::new();
+    engine.set_output(WriterSink(Vec::new()));
+    engine.run_source("println(1);").unwrap();
+    assert_eq!("", engine.take_output());
+}
+
+#[test]
+fn custom_output_sink() {
+    struct Lines(Rc<RefCell<Vec<String>>>);
+    impl OutputSink for Lines {
+        fn write(&mut self, text: &str) -> RuntimeResult<()> {
+            self.0.borrow_mut().extend(text.lines().map(str::to_string));
+            Ok(())
+        }
+    }
+
+    let lines = Rc::new(RefCell::new(vec![]));
+    let mut engine = Engine::new();
+    engine
+        .set_output(Lines(lines.clone()))
+        .set_input(ReaderSource("first\r\nsecond\n".as_bytes()));
+    engine
+        .run_source("println(input()); test input();")
+        .unwrap();
+    assert_eq!(vec!["first", "Test Output:second"], *lines.borrow());
+}
+
+#[test]
+fn locals_fall_back_to_globals() {
+    // `x` is a local of `f` but reads the global until the declaration runs
+    let source = "let x = 1;\nfn f(), int {\nlet y = x;\nlet x = y + 1;\nx\n}\nprintln(f(), x);";
+    assert_eq!("2 1\n", run(source).unwrap());
+    let mut engine = Engine::new();
+    engine.run_source("var total = 0;").unwrap();
+    engine
+        .run_source("fn bump(val n: int) {\ntotal += n;\n}\nbump(2);\nbump(3);")
+        .unwrap();
+    assert_eq!(Some(5), engine.get_global::<i32>("total").unwrap());
+    // Declaring a local twice is caught before the program runs
+    assert!(matches!(
+        run("fn f() {\nlet a = 1;\nlet a = 2;\n}\nf();"),
+        Err(Error::Check(_))
+    ));
+}
+
+#[test]
+fn closures_capture_locals() {
+    let source = "fn make_adder(val n: int), function {\nfn(val x: int) -> x + n\n}\nlet adders = make_adder(1);\nprintln(adders(2), make_adder(10)(5));";
+    assert_eq!("3 15\n", run(source).unwrap());
+    let mut engine = Engine::new();
+    assert_eq!(
+        "function",
+        engine
+            .eval::<String>("let f = fn(val x: int) -> x; type_of(f)")
+            .unwrap()
+    );
+    assert_eq!(
+        Err(Error::Runtime(RuntimeError::new("Not a function!"))),
+        run("let x = 1;\nx();")
+    );
+}
+
+#[test]
+fn parameter_modes() {
+    let source = "fn swap(ref a: mut int, ref b: mut int) {\nlet t = a;\na = b;\nb = t;\n}\nfn split(val n: int, out tens: int, out ones: int) {\ntens = n / 10;\nones = n - tens * 10;\n}\nvar x = 1;\nvar y = 2;\nswap(x, y);\nsplit(42, x, y);\nprintln(x, y);";
+    assert_eq!("4 2\n", run(source).unwrap());
+    let failure = |source: &str| match run(source) {
+        Err(Error::Runtime(error)) => error,
+        outcome => panic!("expected a runtime error, got {outcome:?}"),
+    };
+    // Calls through function values are only checked when they run
+    assert_eq!(
+        RuntimeError::new("Only variables can be passed to ref and out parameters!"),
+        failure("fn f(ref a: mut int) {\na = 1;\n}\nlet g = f;\ng(3);")
+    );
+    assert_eq!(
+        RuntimeError::new("Variable is immutable!"),
+        failure("let x = 1;\nfn f(ref a: mut int) {\na = 2;\n}\nlet g = f;\ng(x);")
+    );
+    // Direct calls, and out parameters that aren't assigned, are caught before the
+    // program runs
+    for source in [
+        "fn f(ref a: mut int) {\na = 1;\n}\nf(3);",
+        "let x = 1;\nfn f(ref a: mut int) {\na = 2;\n}\nf(x);",
+        "var x = 1;\nfn f(out a: int) {\nprintln();\n}\nf(x);",
+    ] {
+        assert!(matches!(run(source), Err(Error::Check(_))));
+    }
+}
@@ -1,36 +1,507 @@
 use std::{
     hash::{DefaultHasher, Hash, Hasher},
     num::ParseIntError,
+    str::FromStr,
 };
 
-use chumsky::span::SimpleSpan;
-use logos::Logos;
+use ariadne::{Color, Label, ReportKind};
+use logos::{FilterResult, Lexer, Logos};
+use rust_decimal::{
+    prelude::{FromPrimitive, ToPrimitive},
+    Decimal,
+};
+
+use crate::utils::{
+    format::FormatSpec,
+    source_map::{self, SourceMap},
+    spanned::{FileId, Span},
+};
 
-use super::token::TokenType;
+use super::token::{StringSegment, TokenType};
 
-pub fn scan<'a>(input: &'a str) -> anyhow::Result<Vec<(TokenType, SimpleSpan)>> {
-    let token_lexer = TokenType::lexer(input);
-    let mut tokens: Vec<(TokenType, SimpleSpan)> = vec![];
-    for token_res in token_lexer.spanned() {
-        if token_res.0.is_err() {
-            return Err(anyhow::anyhow!(LexingErrorKind::Other));
-        } else {
-            tokens.push((token_res.0.unwrap_or(TokenType::EOF), token_res.1.into()));
+pub fn scan(input: &str) -> Result<Vec<(TokenType, Span)>, LexingError> {
+    scan_file(input, FileId::default())
+}
+
+/// Like `scan`, with spans that point into `file`
+pub fn scan_file(input: &str, file: FileId) -> Result<Vec<(TokenType, Span)>, LexingError> {
+    let token_lexer = TokenType::lexer_with_extras(input, file);
+    let mut tokens: Vec<(TokenType, Span)> = vec![];
+    for (token_res, span) in token_lexer.spanned() {
+        match token_res {
+            Ok(token) => tokens.push((token, Span::new(file, span))),
+            Err(kind) => {
+                return Err(LexingError {
+                    kind,
+                    span: Span::new(file, span),
+                })
+            }
         }
     }
+    keep_symbols(&mut tokens, input);
+    drop_stray_docs(&mut tokens);
     Ok(tokens)
 }
 
+/// Doc comments only mean something where there's a declaration to document, anywhere
+/// else they're left out like any other comment. `///` goes before a declaration and
+/// `//!` at the start of the file or of a function's body
+fn drop_stray_docs(tokens: &mut Vec<(TokenType, Span)>) {
+    let declaration = |i| declaration_at(tokens, i);
+    let mut keep = Vec::with_capacity(tokens.len());
+    // Whether the last token kept was the `{` of a function declaration, or nothing at all
+    let mut body_start = true;
+    let mut in_signature = false;
+    for (i, (token, _)) in tokens.iter().enumerate() {
+        let kept = match token {
+            TokenType::DocComment(_) => {
+                let next = (i + 1..tokens.len())
+                    .find(|next| !matches!(tokens[*next].0, TokenType::DocComment(_)));
+                next.is_some_and(declaration)
+            }
+            TokenType::InnerDocComment(_) => body_start,
+            _ => true,
+        };
+        keep.push(kept);
+        match token {
+            TokenType::InnerDocComment(_) => {}
+            _ if !kept => {}
+            TokenType::Function => {
+                in_signature = declaration(i);
+                body_start = false;
+            }
+            TokenType::LeftBracket => {
+                body_start = in_signature;
+                in_signature = false;
+            }
+            _ => body_start = false,
+        }
+    }
+    let mut keep = keep.into_iter();
+    tokens.retain(|_| keep.next().unwrap_or(true));
+}
+
+/// Whether a declaration that takes docs starts at `i`, attributes are skipped over
+fn declaration_at(tokens: &[(TokenType, Span)], mut i: usize) -> bool {
+    while let Some((TokenType::AttributeStart, _)) = tokens.get(i) {
+        i += 2;
+        if let Some((TokenType::LeftParenthesis, _)) = tokens.get(i) {
+            i += tokens[i..]
+                .iter()
+                .position(|(token, _)| *token == TokenType::RightParenthesis)
+                .map_or(tokens.len(), |close| close + 1);
+        }
+    }
+    match tokens.get(i).map(|token| &token.0) {
+        Some(TokenType::Let | TokenType::Var | TokenType::Pub | TokenType::Extern) => true,
+        Some(TokenType::Function) => {
+            matches!(tokens.get(i + 1), Some((TokenType::Identifier(_), _)))
+        }
+        _ => false,
+    }
+}
+
+/// Identifiers are only kept as hashes, but an `extern` function needs the text of its name
+/// to be looked up by and an attribute needs its name to be checked and queried. The name
+/// of an attribute can be a keyword, `@test` is one
+fn keep_symbols(tokens: &mut [(TokenType, Span)], input: &str) {
+    let mut in_extern = false;
+    for i in 0..tokens.len() {
+        let text = &input[tokens[i].1.into_range()];
+        match &tokens[i].0 {
+            TokenType::Extern => in_extern = true,
+            TokenType::SemiColon => in_extern = false,
+            TokenType::Identifier(_) if in_extern && tokens[i - 1].0 == TokenType::Function => {
+                tokens[i].0 = TokenType::Symbol(text.to_string());
+                in_extern = false;
+            }
+            _ if i > 0
+                && tokens[i - 1].0 == TokenType::AttributeStart
+                && text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+            {
+                tokens[i].0 = TokenType::Symbol(text.to_string());
+            }
+            _ => (),
+        }
+    }
+}
+
 pub fn convert_to_hash<T: Hash>(t: &T) -> u64 {
     let mut hasher = DefaultHasher::default();
     t.hash(&mut hasher);
     hasher.finish()
 }
 
+/// The name `a::b::c` is stored under, a single segment keeps the hash of the identifier
+pub fn path_hash(segments: &[u64]) -> u64 {
+    match segments {
+        [name] => *name,
+        _ => convert_to_hash(&segments),
+    }
+}
+
+//----------------------------------------------------------------
+//-Comment Callbacks----------------------------------------------
+//----------------------------------------------------------------
+
+/// Skips a `/* */` comment, which may contain other block comments
+pub(super) fn skip_block_comment(lex: &mut Lexer<TokenType>) -> FilterResult<(), LexingErrorKind> {
+    let rest = lex.remainder();
+    let mut depth = 1;
+    let mut pos = 0;
+    while pos < rest.len() {
+        match &rest.as_bytes()[pos..] {
+            [b'/', b'*', ..] => depth += 1,
+            [b'*', b'/', ..] => depth -= 1,
+            _ => {
+                pos += 1;
+                continue;
+            }
+        }
+        pos += 2;
+        if depth == 0 {
+            lex.bump(pos);
+            return FilterResult::Skip;
+        }
+    }
+    lex.bump(rest.len());
+    FilterResult::Error(LexingErrorKind::UnterminatedComment)
+}
+
+/// Drops the `///` or `//!` marker and the space following it
+pub(super) fn lex_doc_comment(lex: &mut Lexer<TokenType>) -> String {
+    let text = &lex.slice()[3..];
+    text.strip_prefix(' ')
+        .unwrap_or(text)
+        .trim_end()
+        .to_string()
+}
+
+//----------------------------------------------------------------
+//-Number Callbacks-----------------------------------------------
+//----------------------------------------------------------------
+
+/// Strips the `_` separators, radix prefix and type suffix of an integer literal
+fn int_digits<'a>(lex: &Lexer<'a, TokenType>, suffix: &str) -> (String, u32) {
+    let slice = lex.slice();
+    let slice = slice.strip_suffix(suffix).unwrap_or(slice);
+    let (digits, radix) = match slice.get(..2) {
+        Some("0x") => (&slice[2..], 16),
+        Some("0o") => (&slice[2..], 8),
+        Some("0b") => (&slice[2..], 2),
+        _ => (slice, 10),
+    };
+    (digits.replace('_', ""), radix)
+}
+
+pub(super) fn lex_i32(lex: &mut Lexer<TokenType>) -> Result<i32, LexingErrorKind> {
+    let (digits, radix) = int_digits(lex, "i32");
+    Ok(i32::from_str_radix(&digits, radix)?)
+}
+
+pub(super) fn lex_i64(lex: &mut Lexer<TokenType>) -> Result<i64, LexingErrorKind> {
+    let (digits, radix) = int_digits(lex, "i64");
+    Ok(i64::from_str_radix(&digits, radix)?)
+}
+
+/// Parses a float literal into a `Decimal`, which is what every float is at runtime
+fn float_decimal(lex: &Lexer<TokenType>, suffix: &str) -> Result<Decimal, LexingErrorKind> {
+    let slice = lex.slice();
+    let digits = slice.strip_suffix(suffix).unwrap_or(slice).replace('_', "");
+    let value = if digits.contains(['e', 'E']) {
+        Decimal::from_scientific(&digits)
+    } else {
+        Decimal::from_str(&digits)
+    };
+    value.map_err(|_| LexingErrorKind::FloatError)
+}
+
+pub(super) fn lex_f32(lex: &mut Lexer<TokenType>) -> Result<f32, LexingErrorKind> {
+    float_decimal(lex, "f32")?
+        .to_f32()
+        .filter(|f| Decimal::from_f32(*f).is_some())
+        .ok_or(LexingErrorKind::FloatError)
+}
+
+pub(super) fn lex_f64(lex: &mut Lexer<TokenType>) -> Result<f64, LexingErrorKind> {
+    float_decimal(lex, "f64")?
+        .to_f64()
+        .filter(|f| Decimal::from_f64(*f).is_some())
+        .ok_or(LexingErrorKind::FloatError)
+}
+
+pub(super) fn lex_decimal(lex: &mut Lexer<TokenType>) -> Result<Decimal, LexingErrorKind> {
+    float_decimal(lex, "d")
+}
+
+//----------------------------------------------------------------
+//-String Callbacks-----------------------------------------------
+//----------------------------------------------------------------
+
+pub(super) fn lex_string(lex: &mut Lexer<TokenType>) -> Result<String, LexingErrorKind> {
+    let slice = lex.slice();
+    unescape(
+        &slice[1..slice.len() - 1]
+            .replace("{{", "{")
+            .replace("}}", "}"),
+    )
+}
+
+/// The regex only matches up to the first interpolation, so the whole string is rescanned
+/// from its opening quote and the lexer is bumped to the real closing quote
+pub(super) fn lex_interpolated_string(
+    lex: &mut Lexer<TokenType>,
+) -> Result<Vec<StringSegment>, LexingErrorKind> {
+    let offset = lex.span().start + 1;
+    let rest = &lex.source()[offset..];
+    let matched = lex.span().end - offset;
+    let mut segments = vec![];
+    let mut literal = String::new();
+    let mut pos = 0;
+    while let Some(c) = rest[pos..].chars().next() {
+        let next = rest[pos + c.len_utf8()..].chars().next();
+        match (c, next) {
+            ('"', _) => {
+                if !literal.is_empty() {
+                    segments.push(StringSegment::Literal(unescape(&literal)?));
+                }
+                lex.bump(pos + 1 - matched);
+                return Ok(segments);
+            }
+            ('\\', Some(escaped)) => {
+                let mut len = 1 + escaped.len_utf8();
+                if escaped == 'u' && rest[pos + len..].starts_with('{') {
+                    len += rest[pos + len..].find(['}', '"']).map_or(0, |i| i + 1);
+                }
+                literal.push_str(&rest[pos..pos + len]);
+                pos += len;
+            }
+            ('{', Some('{')) | ('}', Some('}')) => {
+                literal.push(c);
+                pos += 2;
+            }
+            ('}', _) => return Err(LexingErrorKind::UnmatchedBrace),
+            ('{', _) => {
+                if !literal.is_empty() {
+                    segments.push(StringSegment::Literal(unescape(&literal)?));
+                    literal.clear();
+                }
+                let inner_start = pos + 1;
+                let inner_len = interpolation_len(&rest[inner_start..])?;
+                let inner = &rest[inner_start..inner_start + inner_len];
+                segments.push(lex_interpolation(inner, offset + inner_start, lex.extras)?);
+                pos = inner_start + inner_len + 1;
+            }
+            _ => {
+                literal.push(c);
+                pos += c.len_utf8();
+            }
+        }
+    }
+    lex.bump(rest.len() - matched);
+    Err(LexingErrorKind::UnterminatedString)
+}
+
+/// Finds the `}` closing an interpolation, skipping over nested braces, strings and chars
+fn interpolation_len(input: &str) -> Result<usize, LexingErrorKind> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '{') => depth += 1,
+            (None, '}') if depth == 0 => return Ok(i),
+            (None, '}') => depth -= 1,
+            _ => (),
+        }
+    }
+    Err(LexingErrorKind::UnterminatedString)
+}
+
+/// Lexes the expression of an interpolation, with spans relative to the whole input
+fn lex_interpolation(
+    inner: &str,
+    offset: usize,
+    file: FileId,
+) -> Result<StringSegment, LexingErrorKind> {
+    let (expr, spec) = match inner.rsplit_once(':') {
+        Some((expr, spec)) if !expr.trim().is_empty() => match FormatSpec::parse(spec) {
+            Some(_) => (expr, Some(spec.to_string())),
+            None => (inner, None),
+        },
+        _ => (inner, None),
+    };
+    if expr.trim().is_empty() {
+        return Err(LexingErrorKind::EmptyInterpolation);
+    }
+    let mut tokens = vec![];
+    for (token_res, span) in TokenType::lexer_with_extras(expr, file).spanned() {
+        let span = (span.start + offset)..(span.end + offset);
+        tokens.push((token_res?, Span::new(file, span)));
+    }
+    Ok(StringSegment::Interpolation {
+        tokens,
+        spec,
+        span: Span::new(file, offset..offset + expr.len()),
+    })
+}
+
+pub(super) fn lex_raw_string(lex: &mut Lexer<TokenType>) -> String {
+    let slice = lex.slice();
+    slice[2..slice.len() - 1].to_string()
+}
+
+pub(super) fn lex_multiline_string(lex: &mut Lexer<TokenType>) -> Result<String, LexingErrorKind> {
+    let Some(end) = lex.remainder().find(r#"""""#) else {
+        lex.bump(lex.remainder().len());
+        return Err(LexingErrorKind::UnterminatedString);
+    };
+    let content = strip_indentation(&lex.remainder()[..end]);
+    lex.bump(end + 3);
+    unescape(&content)
+}
+
+pub(super) fn lex_char(lex: &mut Lexer<TokenType>) -> Result<char, LexingErrorKind> {
+    let slice = lex.slice();
+    let value = unescape(&slice[1..slice.len() - 1])?;
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(LexingErrorKind::InvalidCharLiteral),
+    }
+}
+
+/// Resolves `\n \r \t \0 \\ \" \' \u{..}` escapes inside of a string or char literal
+fn unescape(input: &str) -> Result<String, LexingErrorKind> {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => output.push('\n'),
+            Some('r') => output.push('\r'),
+            Some('t') => output.push('\t'),
+            Some('0') => output.push('\0'),
+            Some('\\') => output.push('\\'),
+            Some('"') => output.push('"'),
+            Some('\'') => output.push('\''),
+            Some('u') => output.push(unescape_unicode(&mut chars)?),
+            Some(other) => return Err(LexingErrorKind::InvalidEscape(other)),
+            None => return Err(LexingErrorKind::InvalidEscape('\\')),
+        }
+    }
+    Ok(output)
+}
+
+fn unescape_unicode(chars: &mut std::str::Chars) -> Result<char, LexingErrorKind> {
+    if chars.next() != Some('{') {
+        return Err(LexingErrorKind::InvalidUnicodeEscape(String::new()));
+    }
+    let mut digits = String::new();
+    for c in chars.by_ref() {
+        if c == '}' {
+            return u32::from_str_radix(&digits, 16)
+                .ok()
+                .filter(|_| (1..=6).contains(&digits.len()))
+                .and_then(char::from_u32)
+                .ok_or(LexingErrorKind::InvalidUnicodeEscape(digits));
+        }
+        digits.push(c);
+    }
+    Err(LexingErrorKind::InvalidUnicodeEscape(digits))
+}
+
+/// Removes the line break after the opening `"""`, the line holding the closing `"""`
+/// and the indentation shared by every non blank line
+fn strip_indentation(content: &str) -> String {
+    let content = content
+        .strip_prefix("\r\n")
+        .or_else(|| content.strip_prefix('\n'))
+        .unwrap_or(content);
+    let mut lines: Vec<&str> = content.split('\n').collect();
+    let closing_indent = match lines.last() {
+        Some(last) if lines.len() > 1 && last.trim().is_empty() => lines.pop().map(str::len),
+        _ => None,
+    };
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .chain(closing_indent)
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            // Only whitespace is cut, a line indented with other characters keeps them
+            let cut = line
+                .char_indices()
+                .take_while(|(i, c)| c.is_whitespace() && i + c.len_utf8() <= indent)
+                .last()
+                .map_or(0, |(i, c)| i + c.len_utf8());
+            &line[cut..]
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Debug, PartialEq, Clone, thiserror::Error)]
+#[error("{kind}")]
+pub struct LexingError {
+    pub kind: LexingErrorKind,
+    pub span: Span,
+}
+
+impl LexingError {
+    pub fn report(&self, sources: &SourceMap) {
+        let _ = self.write(sources, std::io::stderr());
+    }
+
+    /// Renders the report `report` prints to `writer`
+    pub fn write(&self, sources: &SourceMap, writer: impl std::io::Write) -> std::io::Result<()> {
+        source_map::report(ReportKind::Error, self.span)
+            .with_message("Failed to lex input")
+            .with_label(
+                Label::new(self.span)
+                    .with_message(&self.kind)
+                    .with_color(Color::Red),
+            )
+            .finish()
+            .write(sources.cache(), writer)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Default, thiserror::Error)]
 pub enum LexingErrorKind {
-    #[error("Int parsing error")]
+    #[error("Invalid integer literal, {0}")]
     IntError(#[from] ParseIntError),
+    #[error("Float literal can't be represented")]
+    FloatError,
+    #[error("Invalid escape sequence '\\{0}'")]
+    InvalidEscape(char),
+    #[error("Invalid unicode escape '\\u{{{0}}}'")]
+    InvalidUnicodeEscape(String),
+    #[error("Char literals must contain exactly one character")]
+    InvalidCharLiteral,
+    #[error("Unterminated string literal")]
+    UnterminatedString,
+    #[error("Unterminated block comment")]
+    UnterminatedComment,
+    #[error("Unmatched '}}' in string literal, use '}}}}' for a literal brace")]
+    UnmatchedBrace,
+    #[error("Empty interpolation in string literal")]
+    EmptyInterpolation,
     #[error("Unknown or not implemented yet error!")]
     #[default]
     Other,
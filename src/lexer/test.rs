@@ -1,6 +1,12 @@
 use logos::Logos;
+use rust_decimal::Decimal;
 
-use super::token::TokenType;
+use crate::utils::source_map::SourceMap;
+
+use super::{
+    lexer::{convert_to_hash, scan, LexingErrorKind},
+    token::{StringSegment, TokenType},
+};
 
 #[test]
 fn lex_number() {
@@ -8,17 +14,138 @@ fn lex_number() {
     assert_eq!(Some(Ok(TokenType::Number(123))), tokens.next());
 }
 
+#[test]
+fn lex_number_literals() {
+    let tokens: Vec<_> =
+        TokenType::lexer("0xFF 0o17 0b1010 1_000_000 10i64 2.5f64 1.0d 1.5e3 2e-1").collect();
+    assert_eq!(
+        vec![
+            Ok(TokenType::Number(255)),
+            Ok(TokenType::Number(15)),
+            Ok(TokenType::Number(10)),
+            Ok(TokenType::Number(1_000_000)),
+            Ok(TokenType::LongNumber(10)),
+            Ok(TokenType::DoubleNumber(2.5)),
+            Ok(TokenType::DecimalNumber(Decimal::new(10, 1))),
+            Ok(TokenType::FloatingNumber(1500.0)),
+            Ok(TokenType::FloatingNumber(0.2)),
+        ],
+        tokens
+    );
+}
+
+#[test]
+fn lex_number_out_of_range() {
+    let err = scan("let x = 99999999999;").unwrap_err();
+    assert!(matches!(err.kind, LexingErrorKind::IntError(_)));
+    assert_eq!(8..19, err.span.into_range());
+    assert!(scan("99999999999i64").is_ok());
+}
+
 #[test]
 fn lex_string() {
     let mut tokens = TokenType::lexer("\"Hello World!\"");
     assert_eq!(
-        Some(Ok(TokenType::StringLiteral("\"Hello World!\"".into()))),
+        Some(Ok(TokenType::StringLiteral("Hello World!".into()))),
         tokens.next()
     );
-    assert_ne!(
-        Some(Ok(TokenType::StringLiteral("Hello World!".into()))),
+    assert_eq!(None, tokens.next());
+}
+
+#[test]
+fn lex_string_escapes() {
+    let mut tokens = TokenType::lexer(r#""a\n\t\\\"\u{1F600}" '\n' '\'' r"C:\path""#);
+    assert_eq!(
+        Some(Ok(TokenType::StringLiteral("a\n\t\\\"\u{1F600}".into()))),
+        tokens.next()
+    );
+    assert_eq!(Some(Ok(TokenType::CharLiteral('\n'))), tokens.next());
+    assert_eq!(Some(Ok(TokenType::CharLiteral('\''))), tokens.next());
+    assert_eq!(
+        Some(Ok(TokenType::StringLiteral(r"C:\path".into()))),
+        tokens.next()
+    );
+}
+
+#[test]
+fn lex_invalid_escape() {
+    let mut tokens = TokenType::lexer(r#""\q" "\u{110000}""#);
+    assert_eq!(
+        Some(Err(LexingErrorKind::InvalidEscape('q'))),
+        tokens.next()
+    );
+    assert_eq!(
+        Some(Err(LexingErrorKind::InvalidUnicodeEscape("110000".into()))),
+        tokens.next()
+    );
+}
+
+#[test]
+fn lex_multiline_string() {
+    let source = "\"\"\"\n    first\n      second\n\n    third\\t\n    \"\"\"";
+    let mut tokens = TokenType::lexer(source);
+    assert_eq!(
+        Some(Ok(TokenType::StringLiteral(
+            "first\n  second\n\nthird\t".into()
+        ))),
+        tokens.next()
+    );
+    assert_eq!(None, tokens.next());
+}
+
+#[test]
+fn lex_multiline_string_keeps_unevenly_indented_lines() {
+    let source = "\"\"\"\n\u{3000}first\n  second\n  \"\"\"";
+    let mut tokens = TokenType::lexer(source);
+    assert_eq!(
+        Some(Ok(TokenType::StringLiteral("\u{3000}first\nsecond".into()))),
+        tokens.next()
+    );
+}
+
+#[test]
+fn lex_interpolated_string() {
+    let mut tokens = TokenType::lexer(r#""hi {name}, {x:.2} {{}}""#);
+    assert_eq!(
+        Some(Ok(TokenType::InterpolatedString(vec![
+            StringSegment::Literal("hi ".into()),
+            StringSegment::Interpolation {
+                tokens: vec![(
+                    TokenType::Identifier(convert_to_hash(&"name")),
+                    (5..9).into()
+                )],
+                spec: None,
+                span: (5..9).into(),
+            },
+            StringSegment::Literal(", ".into()),
+            StringSegment::Interpolation {
+                tokens: vec![(
+                    TokenType::Identifier(convert_to_hash(&"x")),
+                    (13..14).into()
+                )],
+                spec: Some(".2".into()),
+                span: (13..14).into(),
+            },
+            StringSegment::Literal(" {}".into()),
+        ]))),
         tokens.next()
     );
+    assert_eq!(None, tokens.next());
+}
+
+#[test]
+fn lex_comments() {
+    let source = "/* a /* nested */ comment */ 1 // line\n/// outer\n//! inner\n/* open";
+    let tokens: Vec<_> = TokenType::lexer(source).collect();
+    assert_eq!(
+        vec![
+            Ok(TokenType::Number(1)),
+            Ok(TokenType::DocComment("outer".into())),
+            Ok(TokenType::InnerDocComment("inner".into())),
+            Err(LexingErrorKind::UnterminatedComment),
+        ],
+        tokens
+    );
 }
 
 #[test]
@@ -27,3 +154,27 @@ fn lex_bool() {
     assert_eq!(Some(Ok(TokenType::True)), tokens.next());
     assert_eq!(Some(Ok(TokenType::False)), tokens.next());
 }
+
+#[test]
+fn lex_keywords() {
+    for keyword in TokenType::KEYWORDS {
+        let tokens = scan(keyword).unwrap();
+        assert_eq!(1, tokens.len(), "{keyword}");
+        assert!(
+            !matches!(tokens[0].0, TokenType::Identifier(_)),
+            "{keyword} lexed as an identifier"
+        );
+    }
+}
+
+#[test]
+fn report_points_past_multibyte_text() {
+    let source = "let s = \"日本語\"; let c = 'ab';";
+    let err = scan(source).unwrap_err();
+    let mut rendered = vec![];
+    err.write(&SourceMap::single("main.sn", source), &mut rendered)
+        .unwrap();
+    let rendered = String::from_utf8(rendered).unwrap();
+    // The column ariadne prints counts chars, the 'ab' literal starts at the 24th
+    assert!(rendered.contains("main.sn:1:24"), "{rendered}");
+}
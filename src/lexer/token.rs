@@ -1,9 +1,23 @@
 use logos::Logos;
+use rust_decimal::Decimal;
+
+use crate::utils::spanned::{FileId, Span};
 
 use super::lexer::*;
 
+/// A piece of an interpolated string, `"a {b} c"` is lexed into `[Literal, Interpolation, Literal]`
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringSegment {
+    Literal(String),
+    Interpolation {
+        tokens: Vec<(TokenType, Span)>,
+        spec: Option<String>,
+        span: Span,
+    },
+}
+
 #[derive(Logos, Debug, Clone, PartialEq)]
-#[logos(skip r"[\r\n\t ]+", skip r"//[^\n]*", error = LexingErrorKind)]
+#[logos(skip r"[\r\n\t ]+", skip r"//[^\n]*", error = LexingErrorKind, extras = FileId)]
 pub enum TokenType {
     #[token("test")]
     Test,
@@ -73,6 +87,8 @@ pub enum TokenType {
     Extern,
     #[token("use")]
     Use,
+    #[token("pub")]
+    Pub,
     #[token("with")]
     With,
     #[token("+=")]
@@ -119,6 +135,8 @@ pub enum TokenType {
     RightSquareBracket,
     #[token(";")]
     SemiColon,
+    #[token("::")]
+    DoubleColon,
     #[token(":")]
     Colon,
     #[token("->")]
@@ -127,24 +145,60 @@ pub enum TokenType {
     FatArrow,
     #[token(",")]
     Comma,
+    #[token("...")]
+    Ellipsis,
     #[token("?=")]
     IsNull,
+    #[token("??")]
+    NullCoalesce,
+    #[token("?.")]
+    SafeNavigation,
     #[token("?")]
     NullChecker,
     #[token("@")]
     AttributeStart,
     #[token("_")]
     WildCard,
-    #[regex(r"([a-zA-Z])?[a-zA-Z0-9_]*", |lex| lex.slice().parse().ok().map(|s: String| convert_to_hash(&s)))]
+    #[regex(r"[a-zA-Z][a-zA-Z0-9_]*|_[a-zA-Z0-9_]+", |lex| lex.slice().parse().ok().map(|s: String| convert_to_hash(&s)))]
     Identifier(u64),
-    #[regex(r"[0-9]+", |lex| lex.slice().parse().ok())]
+    /// A name that keeps its text, for `extern` functions to be looked up by and for
+    /// attributes. `scan` turns the name after `extern ... fn` or `@` into this
+    Symbol(String),
+    #[regex(r"[0-9][0-9_]*(i32)?", lex_i32)]
+    #[regex(r"0x[0-9a-fA-F_]+(i32)?", lex_i32)]
+    #[regex(r"0o[0-7_]+(i32)?", lex_i32)]
+    #[regex(r"0b[01_]+(i32)?", lex_i32)]
     Number(i32),
-    #[regex(r"[0-9]+\.[0-9]+", |lex| lex.slice().parse().ok())]
+    #[regex(r"[0-9][0-9_]*i64", lex_i64)]
+    #[regex(r"0x[0-9a-fA-F_]+i64", lex_i64)]
+    #[regex(r"0o[0-7_]+i64", lex_i64)]
+    #[regex(r"0b[01_]+i64", lex_i64)]
+    LongNumber(i64),
+    #[regex(r"[0-9][0-9_]*\.[0-9][0-9_]*([eE][+-]?[0-9_]+)?(f32)?", lex_f32)]
+    #[regex(r"[0-9][0-9_]*[eE][+-]?[0-9_]+(f32)?", lex_f32)]
+    #[regex(r"[0-9][0-9_]*f32", lex_f32)]
     FloatingNumber(f32),
-    #[regex(r#""[^"]*""#, |lex| lex.slice().parse().ok())]
+    #[regex(r"[0-9][0-9_]*(\.[0-9][0-9_]*)?([eE][+-]?[0-9_]+)?f64", lex_f64)]
+    DoubleNumber(f64),
+    #[regex(r"[0-9][0-9_]*(\.[0-9][0-9_]*)?([eE][+-]?[0-9_]+)?d", lex_decimal)]
+    DecimalNumber(Decimal),
+    #[regex(r#""([^"\\{}]|\\u\{[^"}]*\}|\\.|\{\{|\}\})*""#, lex_string)]
+    #[regex(r#"r"[^"]*""#, lex_raw_string)]
+    #[token(r#"""""#, lex_multiline_string)]
     StringLiteral(String),
-    #[regex("'[^']'", |lex| lex.slice().chars().nth(1))]
+    #[regex(
+        r#""([^"\\{}]|\\u\{[^"}]*\}|\\.|\{\{|\}\})*\{"#,
+        lex_interpolated_string
+    )]
+    InterpolatedString(Vec<StringSegment>),
+    #[regex(r"'([^'\\]|\\'|\\[^']+)'", lex_char)]
     CharLiteral(char),
+    #[regex(r"///[^\n]*", lex_doc_comment)]
+    DocComment(String),
+    #[regex(r"//![^\n]*", lex_doc_comment)]
+    InnerDocComment(String),
+    #[token("/*", skip_block_comment)]
+    BlockComment,
     #[token("true")]
     True,
     #[token("false")]
@@ -154,3 +208,13 @@ pub enum TokenType {
     #[end]
     EOF,
 }
+
+impl TokenType {
+    /// The source text of every keyword token
+    pub const KEYWORDS: &'static [&'static str] = &[
+        "test", "struct", "class", "trait", "enum", "impl", "defer", "case", "bitset", "fn", "def",
+        "if", "elif", "else", "match", "and", "or", "not", "for", "while", "loop", "skip", "stop",
+        "var", "let", "scope", "val", "ref", "in", "out", "const", "mut", "extern", "use", "pub",
+        "with", "true", "false", "null",
+    ];
+}
@@ -0,0 +1,21 @@
+#![feature(trait_alias)]
+
+//! The scorpionest language, embed it through [`Engine`]
+
+pub mod ast;
+pub mod checker;
+pub mod cli;
+pub mod cst;
+pub mod engine;
+pub mod formatter;
+pub mod interperter;
+pub mod lexer;
+pub mod loader;
+pub mod lsp;
+pub mod parser;
+pub mod runner;
+pub mod utils;
+pub mod vm;
+
+pub use engine::{engine::Engine, error::Error};
+pub use utils::{error::RuntimeError, object::Object};
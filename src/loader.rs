@@ -0,0 +1,3 @@
+pub mod loader;
+#[cfg(test)]
+mod test;
@@ -0,0 +1,340 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    ast::{
+        arena::{Arena, StmtId},
+        expr::Expr,
+        misc::Attribute,
+        stmt::{ExternDeclaration, Module, Program, Statement},
+    },
+    checker::{
+        checker::{check, nested, Diagnostic},
+        names::{resolve, Declaration, Environment},
+    },
+    engine::error::Error,
+    lexer::lexer::{convert_to_hash, path_hash, scan_file},
+    parser::parser::{get_stream, try_parse},
+    utils::{
+        interner::INTERNER,
+        object::Object,
+        source_map::SourceMap,
+        spanned::{concat_span, FileId, Spanned},
+    },
+};
+
+/// A global a module declares at its top level
+#[derive(Debug, Clone, Copy)]
+struct Export {
+    /// The name it's stored under once the module is linked
+    global: u64,
+    public: bool,
+}
+
+/// Loads the modules programs import, `use a::b;` is the file `a/b.sn` next to the importing
+/// file or in one of the search path's directories
+pub struct Loader<'a> {
+    search_path: &'a [PathBuf],
+    /// What modules can use without importing it
+    natives: &'a Environment,
+    /// Where the text of every module loaded goes, which gives them their `FileId`s
+    sources: &'a mut SourceMap,
+    /// Each after the modules it imports
+    modules: Vec<Module>,
+    /// The top level of every module loaded so far, by canonical path
+    exports: HashMap<PathBuf, HashMap<u64, Export>>,
+    /// The files being loaded, the one that imports the next first
+    loading: Vec<PathBuf>,
+}
+
+impl<'a> Loader<'a> {
+    pub fn new(
+        search_path: &'a [PathBuf],
+        natives: &'a Environment,
+        sources: &'a mut SourceMap,
+    ) -> Self {
+        Loader {
+            search_path,
+            natives,
+            sources,
+            modules: vec![],
+            exports: HashMap::new(),
+            loading: vec![],
+        }
+    }
+
+    /// Marks the file a program was compiled from as being loaded, so modules can't import it
+    pub fn enter(&mut self, path: &Path) {
+        if let Ok(path) = path.canonicalize() {
+            self.loading.push(path);
+        }
+    }
+
+    /// The modules loaded so far, each after the ones it imports
+    pub fn finish(self) -> Vec<Module> {
+        self.modules
+    }
+
+    /// Loads the modules the `use` statements of `program` name and adds what they export to
+    /// `environment`. `dir` is where the program's file is, `use` statements that can't be
+    /// loaded are returned as errors and modules that don't compile fail the whole load
+    pub fn imports(
+        &mut self,
+        input: &str,
+        program: &Program,
+        dir: &Path,
+        environment: &mut Environment,
+    ) -> Result<Vec<Diagnostic>, Error> {
+        let mut diagnostics = vec![];
+        for stmt in &program.statements {
+            let Statement::Use { path, items } = &program.arena[*stmt].0 else {
+                continue;
+            };
+            let span = concat_span(path[0].1, path[path.len() - 1].1);
+            let segments: Vec<&str> = path
+                .iter()
+                .map(|segment| &input[segment.1.into_range()])
+                .collect();
+            let name = segments.join("::");
+            let relative = PathBuf::from(segments.join("/")).with_extension("sn");
+            let found = std::iter::once(dir)
+                .chain(self.search_path.iter().map(PathBuf::as_path))
+                .map(|dir| dir.join(&relative))
+                .find(|path| path.is_file())
+                .and_then(|path| path.canonicalize().ok());
+            let Some(file) = found else {
+                diagnostics.push(Diagnostic::error(
+                    format!("Can't find module `{name}`"),
+                    span,
+                ));
+                continue;
+            };
+            if let Some(start) = self.loading.iter().position(|loading| *loading == file) {
+                let cycle: Vec<_> = self.loading[start..]
+                    .iter()
+                    .chain([&file])
+                    .map(|path| path.file_stem().unwrap_or_default().to_string_lossy())
+                    .collect();
+                diagnostics.push(Diagnostic::error(
+                    format!(
+                        "Modules can't import each other in a cycle, {}",
+                        cycle.join(" -> ")
+                    ),
+                    span,
+                ));
+                continue;
+            }
+            let exports = match self.load(file) {
+                Ok(exports) => exports,
+                Err(Some(error)) => return Err(error),
+                Err(None) => {
+                    diagnostics.push(Diagnostic::error(
+                        format!("Can't read module `{name}`"),
+                        span,
+                    ));
+                    continue;
+                }
+            };
+            let module = path[path.len() - 1].0;
+            let Some(items) = items else {
+                // Only qualified, `b::name`
+                for (name, export) in exports.iter().filter(|(_, export)| export.public) {
+                    environment.import(path_hash(&[module, *name]), export.global);
+                }
+                continue;
+            };
+            for item in items {
+                let text = &input[item.1.into_range()];
+                match exports.get(&item.0) {
+                    Some(export) if export.public => {
+                        environment.import(item.0, export.global);
+                    }
+                    Some(_) => diagnostics.push(Diagnostic::error(
+                        format!("`{text}` is private to `{name}`, declare it with `pub`"),
+                        item.1,
+                    )),
+                    None => diagnostics.push(Diagnostic::error(
+                        format!("`{name}` has no `{text}`"),
+                        item.1,
+                    )),
+                }
+            }
+        }
+        Ok(diagnostics)
+    }
+
+    /// The top level of the module at `path`, loading it the first time. `Err(None)` if the
+    /// file couldn't be read
+    fn load(&mut self, path: PathBuf) -> Result<HashMap<u64, Export>, Option<Error>> {
+        if let Some(exports) = self.exports.get(&path) {
+            return Ok(exports.clone());
+        }
+        let input = std::fs::read_to_string(&path).map_err(|_| None)?;
+        let file = self.sources.add(&path, input.as_str());
+        self.loading.push(path.clone());
+        let compiled = self.compile(&path, &input, file);
+        self.loading.pop();
+        let (program, exports) = compiled.map_err(|error| match error {
+            // Reported against the module it happened in
+            Error::Module { .. } => Some(error),
+            error => Some(Error::Module {
+                file,
+                path: path.clone(),
+                input,
+                error: Box::new(error),
+            }),
+        })?;
+        self.modules.push(Module {
+            path: path.clone(),
+            program,
+        });
+        self.exports.insert(path, exports.clone());
+        Ok(exports)
+    }
+
+    fn compile(
+        &mut self,
+        path: &Path,
+        input: &str,
+        file: FileId,
+    ) -> Result<(Program, HashMap<u64, Export>), Error> {
+        let tokens = scan_file(input, file)?;
+        let mut program = try_parse(get_stream((tokens, input)))?;
+        let mut environment = self.natives.clone();
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut diagnostics = self.imports(input, &program, dir, &mut environment)?;
+        diagnostics.extend(check(input, &program, &environment));
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+            return Err(Error::Check(diagnostics));
+        }
+        let exports = exports(&program, path);
+        link(&mut program, input, &environment, Some(path));
+        Ok((program, exports))
+    }
+}
+
+/// The name a module's global is stored under, so it can't clash with the globals of other files
+fn mangle(module: &Path, name: u64) -> u64 {
+    convert_to_hash(&mangled(module, name))
+}
+
+/// The text `mangle` hashes, what `@enter` and `@exit` in a module are renamed to so they
+/// still name its functions
+fn mangled(module: &Path, name: u64) -> String {
+    format!("{}::{name:x}", module.display())
+}
+
+/// What a module declares at its top level, by name, the first declaration of a name wins
+fn exports(program: &Program, module: &Path) -> HashMap<u64, Export> {
+    let arena = &program.arena;
+    let mut exports = HashMap::new();
+    for stmt in globals(arena, &program.statements) {
+        let (name, public) = match &arena[stmt].0 {
+            Statement::Declaration { name, public, .. }
+            | Statement::FuncDeclaration { name, public, .. } => (name.0, *public),
+            Statement::Extern(declaration) => (declaration.name.0, declaration.public),
+            _ => continue,
+        };
+        exports.entry(name).or_insert(Export {
+            global: mangle(module, name),
+            public,
+        });
+    }
+    exports
+}
+
+/// The declarations at the top level of `statements`, leaving out the bodies of functions
+fn globals(arena: &Arena, statements: &[StmtId]) -> Vec<StmtId> {
+    let mut found = vec![];
+    for stmt in statements {
+        match &arena[*stmt].0 {
+            Statement::Declaration { .. }
+            | Statement::FuncDeclaration { .. }
+            | Statement::Extern(_) => found.push(*stmt),
+            stmt => found.extend(globals(arena, &nested(stmt))),
+        }
+    }
+    found
+}
+
+/// Renames what `program` imports to the globals the modules store it under. For a module,
+/// `module` is its path and its own globals are renamed too
+pub fn link(program: &mut Program, input: &str, environment: &Environment, module: Option<&Path>) {
+    let (resolution, _) = resolve(input, program, environment);
+    let top: HashSet<StmtId> = globals(&program.arena, &program.statements)
+        .into_iter()
+        .collect();
+    let arena = Rc::get_mut(&mut program.arena).expect("a program isn't shared before it's linked");
+    // Functions are global wherever they're declared
+    let functions: HashSet<u64> = arena
+        .stmt_ids()
+        .filter_map(|id| match &arena[id].0 {
+            Statement::FuncDeclaration { name, .. }
+            | Statement::Extern(ExternDeclaration { name, .. }) => Some(name.0),
+            _ => None,
+        })
+        .collect();
+    let renamed = |declaration: Option<Declaration>, name: u64| match (declaration, module) {
+        (Some(Declaration::Imported { global }), _) => Some(global),
+        (Some(Declaration::Function(_)), Some(module)) => Some(mangle(module, name)),
+        (Some(Declaration::Variable(stmt)), Some(module)) if top.contains(&stmt) => {
+            Some(mangle(module, name))
+        }
+        _ => None,
+    };
+    for id in arena.expr_ids().collect::<Vec<_>>() {
+        if let Expr::Variable { name } = &mut arena[id].0 {
+            if let Some(global) = renamed(resolution.expr(id), name.0) {
+                name.0 = global;
+            }
+        }
+    }
+    for id in arena.stmt_ids().collect::<Vec<_>>() {
+        match &mut arena[id].0 {
+            Statement::Assign(assign) => {
+                if let Some(global) = renamed(resolution.stmt(id), assign.name.0) {
+                    assign.name.0 = global;
+                }
+            }
+            Statement::Declaration { name, .. } => {
+                if let Some(global) = renamed(Some(Declaration::Variable(id)), name.0) {
+                    name.0 = global;
+                }
+            }
+            Statement::FuncDeclaration {
+                name, attributes, ..
+            }
+            | Statement::Extern(ExternDeclaration {
+                name, attributes, ..
+            }) => {
+                if let Some(global) = renamed(Some(Declaration::Function(id)), name.0) {
+                    name.0 = global;
+                }
+                if let Some(module) = module {
+                    hooks(attributes, module, &functions);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Renames the functions a module's `@enter` and `@exit` name to the globals they're
+/// stored under
+fn hooks(attributes: &mut [Attribute], module: &Path, functions: &HashSet<u64>) {
+    let hooks = attributes
+        .iter_mut()
+        .filter(|attribute| matches!(attribute.name.0.as_str(), "enter" | "exit"));
+    for attribute in hooks {
+        if let Some(Spanned(Object::String(hook), _)) = attribute.arguments.first_mut() {
+            let name = convert_to_hash(&INTERNER.resolve(hook));
+            if functions.contains(&name) {
+                *hook = INTERNER.get_or_intern(mangled(module, name));
+            }
+        }
+    }
+}
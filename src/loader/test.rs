@@ -0,0 +1,200 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    engine::{
+        engine::{Backend, Engine},
+        error::Error,
+    },
+    utils::spanned::FileId,
+};
+
+/// Writes `files` into a fresh directory named after the test and the process, so test
+/// runs going at the same time don't share it
+fn write_modules(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("scorpionest_loader_{name}_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    for (file, source) in files {
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, source).unwrap();
+    }
+    dir
+}
+
+fn run(path: &Path, backend: Backend) -> Result<String, Error> {
+    let mut engine = Engine::new();
+    engine.set_backend(backend).capture_output();
+    let source = std::fs::read_to_string(path).unwrap();
+    let program = engine.compile_file(path, &source)?;
+    engine.run(&program)?;
+    Ok(engine.take_output())
+}
+
+fn check_errors(path: &Path) -> Vec<String> {
+    let source = std::fs::read_to_string(path).unwrap();
+    match Engine::new().compile_file(path, &source) {
+        Err(Error::Check(diagnostics)) => diagnostics.into_iter().map(|d| d.message).collect(),
+        result => panic!("expected check errors, got {result:?}"),
+    }
+}
+
+#[test]
+fn qualified_and_selective_imports() {
+    let dir = write_modules(
+        "imports",
+        &[
+            (
+                "lib/math.sn",
+                "pub let pi = 3;
+                let secret = 7;
+                fn helper(val x: int), int { x * secret }
+                pub fn triple(val x: int), int { helper(x) / secret * 3 }",
+            ),
+            (
+                "util.sn",
+                "use lib::math::{triple};
+                pub fn nine(), int { triple(3) }
+                println(\"util loaded\");",
+            ),
+            (
+                "main.sn",
+                "use lib::math;
+                use util::{nine};
+                use util;
+                fn helper(), int { 100 }
+                println(math::triple(2), math::pi, nine(), util::nine(), helper());",
+            ),
+        ],
+    );
+    for backend in [Backend::Tree, Backend::Vm] {
+        assert_eq!(
+            Ok("util loaded\n6 3 9 9 100\n".to_string()),
+            run(&dir.join("main.sn"), backend).map_err(|e| e.to_string())
+        );
+    }
+}
+
+#[test]
+fn search_path_is_used_after_the_importing_dir() {
+    let dir = write_modules(
+        "search_path",
+        &[
+            ("lib/greet.sn", "pub fn hello(), string { \"hi\" }"),
+            ("app/main.sn", "use greet::{hello}; println(hello());"),
+        ],
+    );
+    let path = dir.join("app/main.sn");
+    let source = std::fs::read_to_string(&path).unwrap();
+    assert!(Engine::new().compile_file(&path, &source).is_err());
+    let mut engine = Engine::new();
+    engine.add_search_path(dir.join("lib")).capture_output();
+    let program = engine.compile_file(&path, &source).unwrap();
+    engine.run(&program).unwrap();
+    assert_eq!("hi\n", engine.take_output());
+}
+
+#[test]
+fn items_are_private_by_default() {
+    let dir = write_modules(
+        "private",
+        &[
+            ("shapes.sn", "let sides = 4; pub fn square(), int { sides }"),
+            ("main.sn", "use shapes::{sides, circle, square}; square();"),
+        ],
+    );
+    assert_eq!(
+        vec![
+            "`sides` is private to `shapes`, declare it with `pub`",
+            "`shapes` has no `circle`",
+        ],
+        check_errors(&dir.join("main.sn"))
+    );
+}
+
+#[test]
+fn missing_modules_and_cycles() {
+    let dir = write_modules(
+        "cycle",
+        &[
+            ("a.sn", "use b; pub fn f(), int { 1 }"),
+            ("b.sn", "use a; pub fn g(), int { 2 }"),
+            ("lost.sn", "use nowhere::near;"),
+            ("main.sn", "use a;"),
+        ],
+    );
+    assert_eq!(
+        vec!["Can't find module `nowhere::near`"],
+        check_errors(&dir.join("lost.sn"))
+    );
+    let source = std::fs::read_to_string(dir.join("main.sn")).unwrap();
+    let error = Engine::new()
+        .compile_file(&dir.join("main.sn"), &source)
+        .unwrap_err();
+    let Error::Module { path, error, .. } = error else {
+        panic!("expected a module error, got {error:?}");
+    };
+    assert_eq!(dir.join("b.sn").canonicalize().unwrap(), path);
+    let Error::Check(diagnostics) = *error else {
+        panic!("expected check errors");
+    };
+    assert_eq!(
+        "Modules can't import each other in a cycle, a -> b -> a",
+        diagnostics[0].message
+    );
+}
+
+#[test]
+fn modules_that_dont_compile_name_their_file() {
+    let dir = write_modules(
+        "broken",
+        &[
+            ("broken.sn", "pub fn f(), int { missing }"),
+            ("main.sn", "use broken;"),
+        ],
+    );
+    let source = std::fs::read_to_string(dir.join("main.sn")).unwrap();
+    let error = Engine::new()
+        .compile_file(&dir.join("main.sn"), &source)
+        .unwrap_err();
+    assert!(error.to_string().contains("broken.sn"));
+    let Error::Module { file, error, .. } = error else {
+        panic!("expected a module error, got {error:?}");
+    };
+    let Error::Check(diagnostics) = *error else {
+        panic!("expected check errors");
+    };
+    // Spans point into the module, not the file importing it
+    assert_ne!(FileId::default(), file);
+    assert_eq!(file, diagnostics[0].span.file);
+}
+
+#[test]
+fn resources_declared_in_modules() {
+    let dir = write_modules(
+        "resources",
+        &[
+            (
+                "res.sn",
+                "fn begin(val x: int) { println(\"in\", x); }
+                fn finish(val x: int) { println(\"out\", x); }
+                @enter(\"begin\")
+                @exit(\"finish\")
+                pub fn open(), int { 7 }",
+            ),
+            (
+                "main.sn",
+                "use res::{open};
+                use res;
+                with f = open() { println(f); }
+                with g = res::open() { println(g + 1); }",
+            ),
+        ],
+    );
+    for backend in [Backend::Tree, Backend::Vm] {
+        assert_eq!(
+            Ok("in 7\n7\nout 7\nin 7\n8\nout 7\n".to_string()),
+            run(&dir.join("main.sn"), backend).map_err(|e| e.to_string())
+        );
+    }
+}
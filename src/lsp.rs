@@ -0,0 +1,4 @@
+pub mod analysis;
+pub mod lsp;
+#[cfg(test)]
+mod test;
@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{
+        arena::{Arena, ExprId, StmtId},
+        expr::Expr,
+        misc::{Attribute, DeclarationType, FuncParameter},
+        stmt::{Program, Statement},
+    },
+    checker::names::{Declaration, Owner, Resolution},
+    utils::spanned::Span,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Function,
+    Parameter,
+}
+
+/// A name introduced by a declaration, along with every place it's used
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub definition: Span,
+    /// The part of the file the symbol can be used in
+    pub scope: Span,
+    /// How the symbol was declared, e.g. `let x: int` or `fn add(val a: int), int`
+    pub detail: String,
+    pub docs: Option<String>,
+    /// The `@` attributes on the declaration, `detail` starts with them as they were written
+    pub attributes: Vec<Attribute>,
+    pub references: Vec<Span>,
+}
+
+/// Symbols of a single file, references are the uses `resolution` binds to their declaration
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    pub symbols: Vec<Symbol>,
+}
+
+impl Analysis {
+    pub fn new(input: &str, program: &Program, resolution: &Resolution) -> Self {
+        let mut resolver = Resolver {
+            input,
+            arena: &program.arena,
+            resolution,
+            symbols: vec![],
+            declarations: HashMap::new(),
+        };
+        resolver.statements(&program.statements, (0..input.len()).into());
+        Analysis {
+            symbols: resolver.symbols,
+        }
+    }
+
+    /// The symbol whose declaration or one of its uses covers `offset`
+    pub fn symbol_at(&self, offset: usize) -> Option<&Symbol> {
+        let covers = |span: &Span| span.start <= offset && offset <= span.end;
+        self.symbols
+            .iter()
+            .find(|symbol| covers(&symbol.definition) || symbol.references.iter().any(covers))
+    }
+
+    /// Symbols that can be named at `offset`, innermost first
+    pub fn visible_at(&self, offset: usize) -> Vec<&Symbol> {
+        let mut visible: Vec<&Symbol> = self
+            .symbols
+            .iter()
+            .filter(|symbol| symbol.scope.start <= offset && offset <= symbol.scope.end)
+            .collect();
+        visible.sort_by_key(|symbol| std::cmp::Reverse(symbol.scope.start));
+        let mut seen = vec![];
+        visible.retain(|symbol| {
+            let first = !seen.contains(&&symbol.name);
+            seen.push(&symbol.name);
+            first
+        });
+        visible
+    }
+}
+
+struct Resolver<'a> {
+    input: &'a str,
+    arena: &'a Arena,
+    resolution: &'a Resolution,
+    symbols: Vec<Symbol>,
+    /// The symbol of each declaration, as indices into `symbols`
+    declarations: HashMap<Declaration, usize>,
+}
+
+impl<'a> Resolver<'a> {
+    fn text(&self, span: Span) -> &'a str {
+        &self.input[span.into_range()]
+    }
+
+    fn define(&mut self, declaration: Declaration, symbol: Symbol) {
+        self.symbols.push(symbol);
+        self.declarations
+            .insert(declaration, self.symbols.len() - 1);
+    }
+
+    fn reference(&mut self, declaration: Option<Declaration>, span: Span) {
+        let found = declaration.and_then(|declaration| self.declarations.get(&declaration));
+        if let Some(index) = found {
+            self.symbols[*index].references.push(span);
+        }
+    }
+
+    /// Functions can be called anywhere in `scope`, even before they're declared
+    fn statements(&mut self, statements: &[StmtId], scope: Span) {
+        let arena = self.arena;
+        for id in statements {
+            let stmt = &arena[*id];
+            let (name, detail) = match &stmt.0 {
+                Statement::FuncDeclaration {
+                    name,
+                    parameters,
+                    return_type,
+                    ..
+                } => {
+                    let params = parameters
+                        .iter()
+                        .flatten()
+                        .map(|param| {
+                            self.text(
+                                (param.param_type.1.start..param.param_value_type.1.end).into(),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let mut detail = format!("fn {}({params})", self.text(name.1));
+                    if let Some(return_type) = return_type {
+                        detail.push_str(&format!(", {}", self.text(return_type.1)));
+                    }
+                    (name, detail)
+                }
+                Statement::Extern(declaration) => {
+                    let params = declaration
+                        .parameters
+                        .iter()
+                        .map(|(name, c_type)| format!("{}: {}", self.text(name.1), c_type.0.name()))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let mut detail = format!("extern \"C\" fn {}({params})", declaration.symbol);
+                    if let Some(return_type) = declaration.return_type {
+                        detail.push_str(&format!(" -> {}", return_type.0.name()));
+                    }
+                    (&declaration.name, detail)
+                }
+                _ => continue,
+            };
+            let attributes = stmt.0.attributes().to_vec();
+            let written = attributes.iter().map(|attribute| {
+                let arguments = attribute
+                    .arguments
+                    .iter()
+                    .map(|argument| self.text(argument.1))
+                    .collect::<Vec<_>>();
+                match arguments.is_empty() {
+                    true => format!("@{}\n", attribute.name.0),
+                    false => format!("@{}({})\n", attribute.name.0, arguments.join(", ")),
+                }
+            });
+            self.define(
+                Declaration::Function(*id),
+                Symbol {
+                    name: self.text(name.1).to_string(),
+                    kind: SymbolKind::Function,
+                    definition: name.1,
+                    scope,
+                    detail: written.chain([detail]).collect(),
+                    docs: stmt.0.docs().map(|docs| docs.0),
+                    attributes,
+                    references: vec![],
+                },
+            );
+        }
+        for id in statements {
+            self.statement(*id, scope.end);
+        }
+    }
+
+    /// `end` is where the enclosing scope ends, which is how long a declaration stays visible
+    fn statement(&mut self, id: StmtId, end: usize) {
+        let arena = self.arena;
+        let (stmt, span) = (&arena[id].0, arena[id].1);
+        match stmt {
+            Statement::Error | Statement::Empty => (),
+            Statement::Test(expr) => self.expr(*expr),
+            Statement::TestDeclaration { statments, .. } => self.statements(statments, span),
+            Statement::Assign(assign) => {
+                self.expr(assign.value);
+                self.reference(self.resolution.stmt(id), assign.name.1);
+            }
+            Statement::Block { statments } => self.statements(statments, span),
+            Statement::Expression { expr } => self.expr(*expr),
+            Statement::Declaration {
+                docs,
+                declaration_type,
+                public: _,
+                name,
+                manual_type,
+                value,
+            } => {
+                self.expr(*value);
+                let keyword = match declaration_type {
+                    DeclarationType::Mutable => "var",
+                    DeclarationType::Immutable => "let",
+                };
+                let mut detail = format!("{keyword} {}", self.text(name.1));
+                if let Some(manual_type) = manual_type {
+                    detail.push_str(&format!(": {}", self.text(manual_type.1)));
+                }
+                self.define(
+                    Declaration::Variable(id),
+                    Symbol {
+                        name: self.text(name.1).to_string(),
+                        kind: SymbolKind::Variable,
+                        definition: name.1,
+                        scope: (name.1.start..end).into(),
+                        detail,
+                        docs: docs.as_ref().map(|docs| docs.0.clone()),
+                        attributes: vec![],
+                        references: vec![],
+                    },
+                );
+            }
+            Statement::With {
+                name,
+                value,
+                statments,
+            } => {
+                self.expr(*value);
+                self.define(
+                    Declaration::Variable(id),
+                    Symbol {
+                        name: self.text(name.1).to_string(),
+                        kind: SymbolKind::Variable,
+                        definition: name.1,
+                        scope: (name.1.start..span.end).into(),
+                        detail: format!("with {}", self.text(name.1)),
+                        docs: None,
+                        attributes: vec![],
+                        references: vec![],
+                    },
+                );
+                self.statements(statments, span);
+            }
+            Statement::FuncDeclaration {
+                parameters,
+                statments,
+                ..
+            } => {
+                self.parameters(Owner::Function(id), parameters.iter().flatten(), span);
+                self.statements(statments, span);
+            }
+            Statement::IfStmt {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(*condition);
+                self.branch(*then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.branch(*else_branch);
+                }
+            }
+            Statement::MatchStmt(match_stmt) => {
+                self.expr(match_stmt.predicate);
+                for (_, arm) in &match_stmt.then_branches.0 {
+                    self.branch(*arm);
+                }
+            }
+            Statement::WhileStmt {
+                condition,
+                then_branch,
+            } => {
+                self.expr(*condition);
+                self.branch(*then_branch);
+            }
+            Statement::Defer { defered_statment } => self.statement(*defered_statment, end),
+            Statement::Extern(_) | Statement::Use { .. } => (),
+        }
+    }
+
+    /// Parameters can be used until the end of the function at `span`
+    fn parameters<'p>(
+        &mut self,
+        owner: Owner,
+        parameters: impl Iterator<Item = &'p FuncParameter>,
+        span: Span,
+    ) {
+        for (index, param) in parameters.enumerate() {
+            let name = param.param_value_name.1;
+            self.define(
+                Declaration::Parameter { owner, index },
+                Symbol {
+                    name: self.text(name).to_string(),
+                    kind: SymbolKind::Parameter,
+                    definition: name,
+                    scope: (name.start..span.end).into(),
+                    detail: self
+                        .text((param.param_type.1.start..param.param_value_type.1.end).into())
+                        .to_string(),
+                    docs: None,
+                    attributes: vec![],
+                    references: vec![],
+                },
+            );
+        }
+    }
+
+    /// The body of an `if`, `while` or match arm is a scope of its own
+    fn branch(&mut self, id: StmtId) {
+        self.statement(id, self.arena[id].1.end);
+    }
+
+    fn expr(&mut self, id: ExprId) {
+        let arena = self.arena;
+        match &arena[id].0 {
+            Expr::Binary(binary) => {
+                self.expr(binary.left);
+                self.expr(binary.right);
+            }
+            Expr::Unary(unary) => self.expr(unary.right),
+            Expr::Literal(_) => (),
+            Expr::Variable { name } => self.reference(self.resolution.expr(id), name.1),
+            Expr::TenaryIfStmt {
+                condition,
+                value,
+                else_value,
+            } => {
+                for e in [condition, value, else_value] {
+                    self.expr(*e);
+                }
+            }
+            Expr::FunctionCall {
+                callee, arguments, ..
+            } => {
+                self.expr(*callee);
+                for arg in arguments.iter().flatten() {
+                    self.expr(*arg);
+                }
+            }
+            Expr::Interpolation { arguments, .. } => {
+                for arg in arguments {
+                    self.expr(*arg);
+                }
+            }
+            Expr::Lambda(lambda) => {
+                let span = arena[id].1;
+                self.parameters(Owner::Lambda(id), lambda.parameters.iter().flatten(), span);
+                self.statements(&lambda.statments, span);
+            }
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    ast::stmt::{Program, Statement},
+    checker::{
+        checker::{check, Diagnostic, Severity},
+        names::{self, Environment},
+    },
+    lexer::{lexer::scan, token::TokenType},
+    loader::loader::Loader,
+    parser::parser::{get_stream, try_parse},
+    utils::{
+        source_map::{SourceFile, SourceMap},
+        spanned::Span,
+    },
+};
+
+use super::analysis::{Analysis, Symbol, SymbolKind};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// The diagnostics and symbols of a parsed document, the modules it imports are loaded when
+/// it's a file. Modules are looked for next to it and in `search_path`
+fn analyse(
+    uri: &str,
+    text: &str,
+    program: &Program,
+    search_path: &[PathBuf],
+) -> (Vec<Diagnostic>, Analysis) {
+    let natives = Environment::prelude();
+    let mut environment = natives.clone();
+    let path = uri.strip_prefix("file://").map(Path::new);
+    let mut sources = SourceMap::single(path.unwrap_or(Path::new(uri)), text);
+    let mut loader = Loader::new(search_path, &natives, &mut sources);
+    if let Some(path) = path {
+        loader.enter(path);
+    }
+    let dir = path.and_then(Path::parent).unwrap_or(Path::new("."));
+    let mut diagnostics = match loader.imports(text, program, dir, &mut environment) {
+        Ok(diagnostics) => diagnostics,
+        // A module that doesn't compile is reported on the first `use`
+        Err(error) => {
+            let span = program
+                .statements
+                .iter()
+                .map(|stmt| &program.arena[*stmt])
+                .find(|stmt| matches!(stmt.0, Statement::Use { .. }))
+                .map_or(Span::default(), |stmt| stmt.1);
+            vec![Diagnostic::error(error.to_string(), span)]
+        }
+    };
+    diagnostics.extend(check(text, program, &environment));
+    let (resolution, _) = names::resolve(text, program, &environment);
+    (diagnostics, Analysis::new(text, program, &resolution))
+}
+
+/// Serves LSP requests read from `reader` until the client sends `exit` or closes the stream,
+/// imports are looked for in `search_path` like the CLI does
+pub fn run(
+    mut reader: impl BufRead,
+    writer: impl Write,
+    search_path: Vec<PathBuf>,
+) -> std::io::Result<()> {
+    let mut server = Server {
+        writer,
+        documents: HashMap::new(),
+        search_path,
+    };
+    while let Some(message) = read_message(&mut reader)? {
+        if message["method"] == "exit" {
+            break;
+        }
+        server.handle(message)?;
+    }
+    Ok(())
+}
+
+//----------------------------------------------------------------
+//-Transport------------------------------------------------------
+//----------------------------------------------------------------
+
+/// Reads one `Content-Length` framed message, `None` once the stream is closed
+pub fn read_message(reader: &mut impl BufRead) -> std::io::Result<Option<Value>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+            );
+        }
+    }
+    let length = length.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Message without a Content-Length header!",
+        )
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> std::io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()?;
+    Ok(())
+}
+
+//----------------------------------------------------------------
+//-Positions------------------------------------------------------
+//----------------------------------------------------------------
+
+/// LSP positions count lines and UTF-16 code units
+fn to_position(source: &SourceFile, offset: usize) -> Value {
+    let location = source.location(offset);
+    json!({ "line": location.line, "character": location.utf16_column })
+}
+
+fn to_offset(source: &SourceFile, position: &Value) -> Option<usize> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    source.offset(line, character)
+}
+
+fn to_range(source: &SourceFile, span: Span) -> Value {
+    json!({ "start": to_position(source, span.start), "end": to_position(source, span.end) })
+}
+
+//----------------------------------------------------------------
+//-Server---------------------------------------------------------
+//----------------------------------------------------------------
+
+struct Document {
+    source: SourceFile,
+    /// Kept from the last time the document parsed, so features keep working while typing
+    analysis: Analysis,
+}
+
+struct Server<W: Write> {
+    writer: W,
+    documents: HashMap<String, Document>,
+    search_path: Vec<PathBuf>,
+}
+
+impl<W: Write> Server<W> {
+    fn handle(&mut self, message: Value) -> std::io::Result<()> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let Some(id) = message.get("id") else {
+            return self.notification(method, params);
+        };
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                    "renameProvider": true,
+                },
+                "serverInfo": { "name": "scorpionest" },
+            })),
+            "shutdown" => Ok(Value::Null),
+            "textDocument/definition" => Ok(self.definition(params)),
+            "textDocument/references" => Ok(self.references(params)),
+            "textDocument/hover" => Ok(self.hover(params)),
+            "textDocument/completion" => Ok(self.completion(params)),
+            "textDocument/rename" => self.rename(params),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {method}"))),
+        };
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        write_message(&mut self.writer, &response)
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> std::io::Result<()> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.update(uri, text.to_string())
+            }
+            "textDocument/didChange" => {
+                // Only full document sync is advertised, so the last change holds the whole text
+                let changes = params["contentChanges"].as_array();
+                match changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    Some(text) => self.update(uri, text.to_string()),
+                    None => Ok(()),
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Re-analyses a document and publishes its diagnostics
+    fn update(&mut self, uri: &str, text: String) -> std::io::Result<()> {
+        let mut diagnostics = vec![];
+        let mut analysis = None;
+        match scan(&text) {
+            Err(e) => diagnostics.push((e.span, e.kind.to_string(), Severity::Error)),
+            Ok(tokens) => match try_parse(get_stream((tokens, &text))) {
+                Ok(program) => {
+                    let (found, symbols) = analyse(uri, &text, &program, &self.search_path);
+                    diagnostics.extend(found.into_iter().map(|d| (d.span, d.message, d.severity)));
+                    analysis = Some(symbols);
+                }
+                Err(errors) => diagnostics.extend(
+                    errors
+                        .into_iter()
+                        .map(|e| (e.span, e.message, Severity::Error)),
+                ),
+            },
+        }
+        let analysis = analysis
+            .or_else(|| self.documents.remove(uri).map(|doc| doc.analysis))
+            .unwrap_or_default();
+        let source = SourceFile::new(uri, text);
+        let diagnostics: Vec<_> = diagnostics
+            .into_iter()
+            .map(|(span, message, severity)| {
+                json!({
+                    "range": to_range(&source, span),
+                    "severity": match severity {
+                        Severity::Error => 1,
+                        Severity::Warning => 2,
+                    },
+                    "source": "scorpionest",
+                    "message": message,
+                })
+            })
+            .collect();
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        });
+        self.documents
+            .insert(uri.to_string(), Document { source, analysis });
+        write_message(&mut self.writer, &notification)
+    }
+
+    /// The document and the symbol under the cursor of a positional request
+    fn symbol_at<'a>(&'a self, params: &'a Value) -> Option<(&'a str, &'a Document, &'a Symbol)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let document = self.documents.get(uri)?;
+        let offset = to_offset(&document.source, &params["position"])?;
+        let symbol = document.analysis.symbol_at(offset)?;
+        Some((uri, document, symbol))
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        match self.symbol_at(params) {
+            Some((uri, document, symbol)) => json!({
+                "uri": uri,
+                "range": to_range(&document.source, symbol.definition),
+            }),
+            None => Value::Null,
+        }
+    }
+
+    fn references(&self, params: &Value) -> Value {
+        let Some((uri, document, symbol)) = self.symbol_at(params) else {
+            return Value::Null;
+        };
+        let include_declaration = params["context"]["includeDeclaration"]
+            .as_bool()
+            .unwrap_or(true);
+        let declaration = include_declaration.then_some(symbol.definition);
+        let locations: Vec<_> = declaration
+            .iter()
+            .chain(&symbol.references)
+            .map(|span| json!({ "uri": uri, "range": to_range(&document.source, *span) }))
+            .collect();
+        json!(locations)
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let Some((_, _, symbol)) = self.symbol_at(params) else {
+            return Value::Null;
+        };
+        let mut contents = format!("```scorpionest\n{}\n```", symbol.detail);
+        if let Some(docs) = &symbol.docs {
+            contents.push_str(&format!("\n\n{docs}"));
+        }
+        json!({ "contents": { "kind": "markdown", "value": contents } })
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        const FUNCTION: u8 = 3;
+        const VARIABLE: u8 = 6;
+        const KEYWORD: u8 = 14;
+        const DEPRECATED: u8 = 1;
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let names = self.documents.get(uri).map(|document| {
+            let offset = to_offset(&document.source, &params["position"]).unwrap_or_default();
+            document.analysis.visible_at(offset)
+        });
+        let symbols = names.into_iter().flatten().map(|symbol| {
+            let kind = match symbol.kind {
+                SymbolKind::Function => FUNCTION,
+                SymbolKind::Variable | SymbolKind::Parameter => VARIABLE,
+            };
+            let mut item = json!({ "label": symbol.name, "kind": kind, "detail": symbol.detail });
+            if symbol
+                .attributes
+                .iter()
+                .any(|attribute| attribute.name.0 == "deprecated")
+            {
+                item["tags"] = json!([DEPRECATED]);
+            }
+            item
+        });
+        let keywords = TokenType::KEYWORDS
+            .iter()
+            .map(|keyword| json!({ "label": keyword, "kind": KEYWORD }));
+        json!(symbols.chain(keywords).collect::<Vec<_>>())
+    }
+
+    fn rename(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let new_name = params["newName"].as_str().unwrap_or_default();
+        let is_identifier = matches!(
+            scan(new_name).as_deref(),
+            Ok([(TokenType::Identifier(_), span)]) if span.into_range() == (0..new_name.len())
+        );
+        if !is_identifier {
+            return Err((INVALID_PARAMS, format!("'{new_name}' isn't a valid name")));
+        }
+        let Some((uri, document, symbol)) = self.symbol_at(params) else {
+            return Ok(Value::Null);
+        };
+        let edits: Vec<_> = std::iter::once(&symbol.definition)
+            .chain(&symbol.references)
+            .map(|span| json!({ "range": to_range(&document.source, *span), "newText": new_name }))
+            .collect();
+        Ok(json!({ "changes": { uri: edits } }))
+    }
+}
@@ -0,0 +1,162 @@
+use std::{io::Cursor, path::PathBuf};
+
+use serde_json::{json, Value};
+
+use super::lsp::{read_message, run, write_message};
+
+const URI: &str = "file:///main.sn";
+const SOURCE: &str =
+    "/// How many\nvar count: int = 1;\nfn bump(val by: int) {\ncount += by\n}\nbump(count);\n";
+
+/// Runs the server over a script of messages, returning everything it sent back
+fn session(messages: &[Value]) -> Vec<Value> {
+    session_with(messages, vec![])
+}
+
+fn session_with(messages: &[Value], search_path: Vec<PathBuf>) -> Vec<Value> {
+    let mut input = vec![];
+    for message in messages {
+        write_message(&mut input, message).unwrap();
+    }
+    let mut output = vec![];
+    run(Cursor::new(input), &mut output, search_path).unwrap();
+    let mut reader = Cursor::new(output);
+    std::iter::from_fn(|| read_message(&mut reader).unwrap()).collect()
+}
+
+fn open(text: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": { "textDocument": { "uri": URI, "languageId": "scorpionest", "version": 1, "text": text } },
+    })
+}
+
+fn request(id: u64, method: &str, line: u64, character: u64, extra: Value) -> Value {
+    let mut params = json!({
+        "textDocument": { "uri": URI },
+        "position": { "line": line, "character": character },
+    });
+    params
+        .as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().cloned().unwrap_or_default());
+    json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+}
+
+fn response(responses: &[Value], id: u64) -> &Value {
+    &responses.iter().find(|r| r["id"] == id).unwrap()["result"]
+}
+
+#[test]
+fn publishes_diagnostics() {
+    let responses = session(&[
+        open("let x = ;"),
+        json!({ "jsonrpc": "2.0", "method": "exit" }),
+    ]);
+    let params = &responses[0]["params"];
+    assert_eq!("textDocument/publishDiagnostics", responses[0]["method"]);
+    assert_eq!(1, params["diagnostics"].as_array().unwrap().len());
+    assert_eq!(
+        json!({ "line": 0, "character": 8 }),
+        params["diagnostics"][0]["range"]["start"]
+    );
+
+    let responses = session(&[open(SOURCE)]);
+    assert_eq!(json!([]), responses[0]["params"]["diagnostics"]);
+
+    let responses = session(&[open("fn f(out s: int) {\nprintln();\n}\nvar x = 0;\nf(x);")]);
+    let diagnostic = &responses[0]["params"]["diagnostics"][0];
+    assert_eq!(
+        "`out` parameter `s` is never assigned",
+        diagnostic["message"]
+    );
+    assert_eq!(
+        json!({ "line": 0, "character": 9 }),
+        diagnostic["range"]["start"]
+    );
+}
+
+#[test]
+fn definition_references_and_hover() {
+    let responses = session(&[
+        open(SOURCE),
+        request(1, "textDocument/definition", 5, 6, json!({})),
+        request(
+            2,
+            "textDocument/references",
+            1,
+            4,
+            json!({ "context": { "includeDeclaration": true } }),
+        ),
+        request(3, "textDocument/hover", 3, 0, json!({})),
+        request(4, "textDocument/hover", 5, 1, json!({})),
+    ]);
+    assert_eq!(
+        json!({ "start": { "line": 1, "character": 4 }, "end": { "line": 1, "character": 9 } }),
+        response(&responses, 1)["range"]
+    );
+    let lines: Vec<_> = response(&responses, 2)
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|location| location["range"]["start"]["line"].clone())
+        .collect();
+    assert_eq!(vec![json!(1), json!(3), json!(5)], lines);
+    assert_eq!(
+        "```scorpionest\nvar count: int\n```\n\nHow many",
+        response(&responses, 3)["contents"]["value"]
+    );
+    assert_eq!(
+        "```scorpionest\nfn bump(val by: int)\n```",
+        response(&responses, 4)["contents"]["value"]
+    );
+}
+
+#[test]
+fn completion_and_rename() {
+    let responses = session(&[
+        open(SOURCE),
+        request(1, "textDocument/completion", 3, 0, json!({})),
+        request(
+            2,
+            "textDocument/rename",
+            3,
+            10,
+            json!({ "newName": "step" }),
+        ),
+        request(3, "textDocument/rename", 3, 10, json!({ "newName": "1st" })),
+    ]);
+    let labels: Vec<_> = response(&responses, 1)
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap().to_string())
+        .collect();
+    for label in ["by", "bump", "count", "while", "let"] {
+        assert!(labels.contains(&label.to_string()), "{label}");
+    }
+
+    let edits = response(&responses, 2)["changes"][URI].as_array().unwrap();
+    assert_eq!(2, edits.len());
+    assert!(edits.iter().all(|edit| edit["newText"] == "step"));
+    assert!(responses
+        .iter()
+        .any(|r| r["id"] == 3 && r["error"]["code"] == -32602));
+}
+
+#[test]
+fn imports_from_the_search_path() {
+    let lib = std::env::temp_dir().join(format!("scorpionest_lsp_lib_{}", std::process::id()));
+    std::fs::create_dir_all(&lib).unwrap();
+    std::fs::write(lib.join("greet.sn"), "pub fn hello(), string { \"hi\" }").unwrap();
+    let text = "use greet::{hello};\nprintln(hello());";
+    let responses = session_with(&[open(text)], vec![lib.clone()]);
+    let missing = session(&[open(text)]);
+    std::fs::remove_dir_all(&lib).unwrap();
+    assert_eq!(json!([]), responses[0]["params"]["diagnostics"]);
+    assert!(!missing[0]["params"]["diagnostics"]
+        .as_array()
+        .unwrap()
+        .is_empty());
+}
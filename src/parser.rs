@@ -1 +1,3 @@
 pub mod parser;
+#[cfg(test)]
+mod test;
@@ -1,22 +1,29 @@
-use std::collections::HashMap;
+use std::rc::Rc;
 
-use chumsky::prelude::*;
+use ariadne::{Color, Label, ReportKind};
+use chumsky::{input::MapExtra, prelude::*};
 use rust_decimal::{prelude::FromPrimitive, Decimal};
 
 use crate::{
     ast::{
-        expr::{Binary, Expr, Literal, Unary},
+        arena::{Arena, ExprId, StmtId},
+        expr::{Binary, Expr, Lambda, Literal, Unary},
         misc::{
-            AssignOp, BinaryOp, DeclarationType, FuncParameter, ParamRestrictor, ParamType, UnaryOp,
+            AssignOp, Attribute, BinaryOp, CType, DeclarationType, FuncParameter, ParamRestrictor,
+            ParamType, UnaryOp,
         },
         pattern::Pattern,
-        stmt::{Assign, MatchStmt, Statement},
+        stmt::{Assign, ExternDeclaration, MatchStmt, Program, Statement},
+    },
+    lexer::{
+        lexer::{convert_to_hash, path_hash},
+        token::{StringSegment, TokenType},
     },
-    lexer::token::TokenType,
     utils::{
         interner::INTERNER,
         object::Object,
-        spanned::{concat_span, Spanned},
+        source_map::{self, SourceMap},
+        spanned::{concat_span, FileId, Span, Spanned},
         valtype::Type,
     },
 };
@@ -24,55 +31,123 @@ use crate::{
 ///----------------------------------------------------------------
 ///-Common Traits--------------------------------------------------
 ///----------------------------------------------------------------
-pub type TokenParserExtra<'a> = extra::Full<Rich<'a, TokenType>, (), ()>;
-pub trait TokenInput<'a> = chumsky::input::ValueInput<'a, Token = TokenType, Span = SimpleSpan>;
+/// Nodes are allocated into the `Arena` state as they're parsed
+pub type TokenParserExtra<'a> = extra::Full<Rich<'a, TokenType, Span>, Arena, ()>;
+pub trait TokenInput<'a> = chumsky::input::ValueInput<'a, Token = TokenType, Span = Span>;
 pub trait TokenParser<'a, I: TokenInput<'a>, O> = Parser<'a, I, O, TokenParserExtra<'a>> + Clone;
 
+type Extra<'a, 'b, I> = MapExtra<'a, 'b, I, TokenParserExtra<'a>>;
+
+fn alloc_expr<'a, I: TokenInput<'a>>(expr: Expr, e: &mut Extra<'a, '_, I>) -> ExprId {
+    let span = e.span();
+    e.state().alloc_expr(expr, span)
+}
+
+fn alloc_stmt<'a, I: TokenInput<'a>>(stmt: Statement, e: &mut Extra<'a, '_, I>) -> StmtId {
+    let span = e.span();
+    e.state().alloc_stmt(stmt, span)
+}
+
 //----------------------------------------------------------------
 //-Expression Parsing---------------------------------------------
 //----------------------------------------------------------------
 
-fn recursive_expr_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Expr> {
+fn recursive_expr_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, ExprId> {
+    expr_parser(stmt_parser())
+}
+
+/// Expressions hold statements in the bodies of lambdas, which `stmt_parser` parses
+fn expr_parser<'a, SP, I: TokenInput<'a>>(stmt_parser: SP) -> impl TokenParser<'a, I, ExprId>
+where
+    SP: TokenParser<'a, I, StmtId> + 'a,
+{
     recursive(|f| {
         choice((
-            func_call_parser(f.clone()),
-            tenary_if_parser(f.clone()),
-            binary_parser(f),
+            tenary_if_parser(f.clone()).map_with(alloc_expr),
+            binary_parser(f, stmt_parser),
         ))
     })
     .boxed()
 }
 
-fn func_call_parser<'a, RP, I: TokenInput<'a>>(
+/// What follows a callee, `(args)` or `?.name(args)`
+#[derive(Clone)]
+enum Postfix {
+    Call(Spanned<Option<Vec<ExprId>>>),
+    Safe(Spanned<u64>, Spanned<Option<Vec<ExprId>>>),
+}
+
+/// Calls are postfix, so anything that evaluates to a function can be called like `make_adder(1)(2)`.
+/// There are no fields, so `a?.f(b)` navigates by calling `f(a, b)`, unless `a` is null
+fn func_call_parser<'a, RP, SP, I: TokenInput<'a>>(
     recursive_parser: RP,
-) -> impl TokenParser<'a, I, Expr>
+    stmt_parser: SP,
+) -> impl TokenParser<'a, I, ExprId>
 where
-    RP: TokenParser<'a, I, Expr>,
+    RP: TokenParser<'a, I, ExprId>,
+    SP: TokenParser<'a, I, StmtId> + 'a,
 {
-    var_ident()
-        .then(
-            recursive_parser
-                .separated_by(just(TokenType::Comma))
-                .collect::<Vec<_>>()
-                .or_not()
-                .delimited_by(
-                    just(TokenType::LeftParenthesis),
-                    just(TokenType::RightParenthesis),
-                ),
+    let arguments = recursive_parser
+        .clone()
+        .separated_by(just(TokenType::Comma))
+        .collect::<Vec<_>>()
+        .or_not()
+        .delimited_by(
+            just(TokenType::LeftParenthesis),
+            just(TokenType::RightParenthesis),
         )
-        .map(|(name, args)| Expr::FunctionCall {
-            func_name: Box::new(name),
-            arguments: args,
-        })
+        .map_with(|args, e| Spanned(args, e.span()));
+    let postfix = choice((
+        arguments.clone().map(Postfix::Call),
+        just(TokenType::SafeNavigation)
+            .ignore_then(var_ident())
+            .then(arguments)
+            .map(|(name, arguments)| Postfix::Safe(name, arguments)),
+    ));
+    atom_parser(recursive_parser, stmt_parser).foldl_with(
+        postfix.repeated(),
+        |target, postfix, e| match postfix {
+            Postfix::Call(arguments) => {
+                let span = concat_span(e.state()[target].1, arguments.1);
+                e.state().alloc_expr(
+                    Expr::FunctionCall {
+                        callee: target,
+                        arguments: arguments.0,
+                        safe: false,
+                    },
+                    span,
+                )
+            }
+            Postfix::Safe(name, arguments) => {
+                let span = concat_span(e.state()[target].1, arguments.1);
+                let callee = e.state().alloc_expr(Expr::Variable { name }, name.1);
+                let arguments = std::iter::once(target)
+                    .chain(arguments.0.into_iter().flatten())
+                    .collect();
+                e.state().alloc_expr(
+                    Expr::FunctionCall {
+                        callee,
+                        arguments: Some(arguments),
+                        safe: true,
+                    },
+                    span,
+                )
+            }
+        },
+    )
 }
 
-fn binary_parser<'a, RP, I: TokenInput<'a>>(recursive_parser: RP) -> impl TokenParser<'a, I, Expr>
+fn binary_parser<'a, RP, SP, I: TokenInput<'a>>(
+    recursive_parser: RP,
+    stmt_parser: SP,
+) -> impl TokenParser<'a, I, ExprId>
 where
-    RP: TokenParser<'a, I, Expr>,
+    RP: TokenParser<'a, I, ExprId>,
+    SP: TokenParser<'a, I, StmtId> + 'a,
 {
-    let unary = unary_parser(recursive_parser).map_with(|ident, e| Spanned(ident, e.span()));
+    let unary = unary_parser(recursive_parser, stmt_parser);
 
-    let product = unary.clone().foldl(
+    let product = unary.clone().foldl_with(
         choice((
             just(TokenType::Times).to(BinaryOp::Mul),
             just(TokenType::Div).to(BinaryOp::Div),
@@ -80,20 +155,10 @@ where
         .map_with(|ident, e| Spanned(ident, e.span()))
         .then(unary)
         .repeated(),
-        |lhs, (op, rhs)| {
-            let span: SimpleSpan = (lhs.1.start()..rhs.1.end()).into();
-            Spanned(
-                Expr::Binary(Binary {
-                    left: Box::new(lhs),
-                    operator: op,
-                    right: Box::new(rhs),
-                }),
-                span,
-            )
-        },
+        |lhs, (op, rhs), e| binary_node(e.state(), lhs, op, rhs),
     );
 
-    let sum = product.clone().foldl(
+    let sum = product.clone().foldl_with(
         choice((
             just(TokenType::Plus).to(BinaryOp::Add),
             just(TokenType::Minus).to(BinaryOp::Sub),
@@ -101,20 +166,20 @@ where
         .map_with(|ident, e| Spanned(ident, e.span()))
         .then(product)
         .repeated(),
-        |lhs, (op, rhs)| {
-            let span: SimpleSpan = concat_span(lhs.1, rhs.1);
-            Spanned(
-                Expr::Binary(Binary {
-                    left: Box::new(lhs),
-                    operator: op,
-                    right: Box::new(rhs),
-                }),
-                span,
-            )
-        },
+        |lhs, (op, rhs), e| binary_node(e.state(), lhs, op, rhs),
+    );
+
+    // Binds looser than arithmetic so `x ?? 0 + 1` defaults to `0 + 1`
+    let coalesce = sum.clone().foldl_with(
+        just(TokenType::NullCoalesce)
+            .to(BinaryOp::Coalesce)
+            .map_with(|ident, e| Spanned(ident, e.span()))
+            .then(sum)
+            .repeated(),
+        |lhs, (op, rhs), e| binary_node(e.state(), lhs, op, rhs),
     );
 
-    let cond = sum.clone().foldl(
+    coalesce.clone().foldl_with(
         choice((
             just(TokenType::GreaterThan).to(BinaryOp::GreaterThan),
             just(TokenType::GreaterThanEqual).to(BinaryOp::GreaterThanEqual),
@@ -126,26 +191,36 @@ where
             just(TokenType::Or).to(BinaryOp::Or),
         ))
         .map_with(|ident, e| Spanned(ident, e.span()))
-        .then(sum)
+        .then(coalesce)
         .repeated(),
-        |lhs, (op, rhs)| {
-            let span: SimpleSpan = concat_span(lhs.1, rhs.1);
-            Spanned(
-                Expr::Binary(Binary {
-                    left: Box::new(lhs),
-                    operator: op,
-                    right: Box::new(rhs),
-                }),
-                span,
-            )
-        },
-    );
-    cond.map(|spanned_expr| spanned_expr.0)
+        |lhs, (op, rhs), e| binary_node(e.state(), lhs, op, rhs),
+    )
+}
+
+fn binary_node(
+    arena: &mut Arena,
+    left: ExprId,
+    operator: Spanned<BinaryOp>,
+    right: ExprId,
+) -> ExprId {
+    let span = concat_span(arena[left].1, arena[right].1);
+    arena.alloc_expr(
+        Expr::Binary(Binary {
+            left,
+            operator,
+            right,
+        }),
+        span,
+    )
 }
 
-fn unary_parser<'a, EP, I: TokenInput<'a>>(expr_parser: EP) -> impl TokenParser<'a, I, Expr>
+fn unary_parser<'a, EP, SP, I: TokenInput<'a>>(
+    expr_parser: EP,
+    stmt_parser: SP,
+) -> impl TokenParser<'a, I, ExprId>
 where
-    EP: TokenParser<'a, I, Expr>,
+    EP: TokenParser<'a, I, ExprId>,
+    SP: TokenParser<'a, I, StmtId> + 'a,
 {
     choice((
         just(TokenType::Not).to(UnaryOp::Not),
@@ -153,108 +228,242 @@ where
     ))
     .map_with(|ident, e| Spanned(ident, e.span()))
     .repeated()
-    .foldr(
-        atom_parser(expr_parser).map_with(|ident, e| Spanned(ident, e.span())),
-        |op, literal| {
-            let span: SimpleSpan = concat_span(op.1, literal.1);
-            Spanned(
+    .foldr_with(
+        func_call_parser(expr_parser, stmt_parser),
+        |op, right, e| {
+            let span = concat_span(op.1, e.state()[right].1);
+            e.state().alloc_expr(
                 Expr::Unary(Unary {
                     operator: op,
-                    right: Box::new(literal),
+                    right,
                 }),
                 span,
             )
         },
     )
-    .map(|spanned_expr| spanned_expr.0)
 }
 
-fn atom_parser<'a, EP, I: TokenInput<'a>>(expr_parser: EP) -> impl TokenParser<'a, I, Expr>
+fn atom_parser<'a, EP, SP, I: TokenInput<'a>>(
+    expr_parser: EP,
+    stmt_parser: SP,
+) -> impl TokenParser<'a, I, ExprId>
 where
-    EP: TokenParser<'a, I, Expr>,
+    EP: TokenParser<'a, I, ExprId>,
+    SP: TokenParser<'a, I, StmtId> + 'a,
 {
     choice((
-        select! {
-               TokenType::True = e => Expr::Literal(Literal { value: Spanned(Object::Boolean(true), e.span()) }),
-               TokenType::False = e => Expr::Literal(Literal { value: Spanned(Object::Boolean(false),e.span()) }),
-               TokenType::Null = e =>Expr::Literal( Literal { value: Spanned(Object::NullValue,e.span()) }),
-               TokenType::StringLiteral(s) = e =>Expr::Literal( Literal {
-                value: Spanned(Object::String(INTERNER.get_or_intern(s)),e.span())
-                }),
-               TokenType::CharLiteral(c) = e => Expr::Literal(Literal { value: Spanned(Object::Integer(c as i32),e.span()) }),
-               TokenType::Number(i) = e => Expr::Literal(Literal { value: Spanned(Object::Integer(i),e.span()) }),
-               TokenType::FloatingNumber(f) = e => Expr::Literal(Literal { value: Spanned(Object::Float(Decimal::from_f32(f).unwrap()),e.span()) },),
-               TokenType::Identifier(i) = e => Expr::Variable { name: Spanned(i, e.span()) }
-        },
-        expr_parser.clone().delimited_by(
-            just(TokenType::LeftParenthesis),
-            just(TokenType::RightParenthesis),
-        ),
+        literal_parser()
+            .map(|value| Expr::Literal(Literal { value }))
+            .map_with(alloc_expr),
+        path_parser()
+            .map_with(|path, e| Expr::Variable {
+                name: Spanned(path_hash(&path), e.span()),
+            })
+            .map_with(alloc_expr),
+        interpolated_string_parser().map_with(alloc_expr),
+        lambda_parser(expr_parser.clone(), stmt_parser).map_with(alloc_expr),
+        expr_parser
+            .delimited_by(
+                just(TokenType::LeftParenthesis),
+                just(TokenType::RightParenthesis),
+            )
+            .map_with(|id, e: &mut Extra<'a, '_, I>| {
+                let span = e.span();
+                e.state().respan(id, span);
+                id
+            }),
     ))
 }
 
-fn tenary_if_parser<'a, EP, I: TokenInput<'a>>(expr_parser: EP) -> impl TokenParser<'a, I, Expr>
+/// `fn(val x: int) -> x * 2`, or a body in braces like the one of a declared function
+fn lambda_parser<'a, EP, SP, I: TokenInput<'a>>(
+    expr_parser: EP,
+    stmt_parser: SP,
+) -> impl TokenParser<'a, I, Expr>
 where
-    EP: TokenParser<'a, I, Expr>,
+    EP: TokenParser<'a, I, ExprId>,
+    SP: TokenParser<'a, I, StmtId> + 'a,
 {
-    just(TokenType::If)
+    just(TokenType::Function)
         .ignore_then(
-            expr_parser
-                .clone()
-                .map_with(|ident, e| Spanned(ident, e.span())),
+            func_params_parser()
+                .separated_by(just(TokenType::Comma))
+                .collect::<Vec<_>>()
+                .or_not()
+                .delimited_by(
+                    just(TokenType::LeftParenthesis),
+                    just(TokenType::RightParenthesis),
+                ),
         )
+        .then(just(TokenType::Comma).ignore_then(type_ident()).or_not())
+        .then(choice((
+            just(TokenType::SkinnyArrow)
+                .ignore_then(expr_parser)
+                .map_with(|expr, e: &mut Extra<'a, '_, I>| {
+                    let span = e.state()[expr].1;
+                    let body = e.state().alloc_stmt(Statement::Expression { expr }, span);
+                    (vec![body], true)
+                }),
+            stmt_parser
+                .repeated()
+                .at_least(1)
+                .collect::<Vec<_>>()
+                .delimited_by(just(TokenType::LeftBracket), just(TokenType::RightBracket))
+                .map(|statments| (statments, false)),
+        )))
+        .map(|((parameters, return_type), (statments, arrow))| {
+            Expr::Lambda(Lambda {
+                parameters,
+                return_type,
+                statments,
+                arrow,
+            })
+        })
+}
+
+/// Lowers `"a {b:>4} c"` into the template `"a {:>4} c"` and the argument `b`
+fn interpolated_string_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Expr> {
+    select! {
+        TokenType::InterpolatedString(segments) => segments
+    }
+    .try_map_with(|segments: Vec<StringSegment>, e: &mut Extra<'a, '_, I>| {
+        let mut template = String::new();
+        let mut arguments = vec![];
+        for segment in segments {
+            match segment {
+                StringSegment::Literal(s) => {
+                    template.push_str(&s.replace('{', "{{").replace('}', "}}"))
+                }
+                StringSegment::Interpolation { tokens, spec, span } => {
+                    template.push('{');
+                    if let Some(spec) = spec {
+                        template.push(':');
+                        template.push_str(&spec);
+                    }
+                    template.push('}');
+                    let stream = chumsky::input::Stream::from_iter(tokens)
+                        .spanned::<_, Span>(span.with_range(span.end..span.end));
+                    let expr = recursive_expr_parser()
+                        .then_ignore(end())
+                        .parse_with_state(stream, e.state())
+                        .into_result()
+                        .map_err(|errs| {
+                            let message = errs
+                                .into_iter()
+                                .map(|err| format!("{err:?}"))
+                                .collect::<Vec<_>>()
+                                .join(", ");
+                            Rich::custom(span, message)
+                        })?;
+                    arguments.push(expr);
+                }
+            }
+        }
+        Ok(Expr::Interpolation {
+            template: INTERNER.get_or_intern(template),
+            arguments,
+        })
+    })
+}
+
+fn tenary_if_parser<'a, EP, I: TokenInput<'a>>(expr_parser: EP) -> impl TokenParser<'a, I, Expr>
+where
+    EP: TokenParser<'a, I, ExprId>,
+{
+    just(TokenType::If)
+        .ignore_then(expr_parser.clone())
         .then_ignore(just(TokenType::Colon))
-        .then(
-            expr_parser
-                .clone()
-                .map_with(|ident, e| Spanned(ident, e.span())),
-        )
+        .then(expr_parser.clone())
         .then_ignore(just(TokenType::Else))
-        .then(expr_parser.map_with(|ident, e| Spanned(ident, e.span())))
-        .map(|(cond_and_val, else_val)| Expr::TenaryIfStmt {
-            condition: Box::new(cond_and_val.0),
-            value: Box::new(cond_and_val.1),
-            else_value: Box::new(else_val),
+        .then(expr_parser)
+        .map(|((condition, value), else_value)| Expr::TenaryIfStmt {
+            condition,
+            value,
+            else_value,
         })
 }
 
+/// Literal values, which are all attributes can take as arguments
+fn literal_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Spanned<Object>> {
+    select! {
+        TokenType::True = e => Spanned(Object::Boolean(true), e.span()),
+        TokenType::False = e => Spanned(Object::Boolean(false), e.span()),
+        TokenType::Null = e => Spanned(Object::NullValue, e.span()),
+        TokenType::StringLiteral(s) = e => Spanned(Object::String(INTERNER.get_or_intern(s)), e.span()),
+        TokenType::CharLiteral(c) = e => Spanned(Object::Integer(c as i32), e.span()),
+        TokenType::Number(i) = e => Spanned(Object::Integer(i), e.span()),
+        TokenType::FloatingNumber(f) = e => Spanned(Object::Float(Decimal::from_f32(f).unwrap()), e.span()),
+        TokenType::LongNumber(l) = e => Spanned(Object::Long(l), e.span()),
+        TokenType::DoubleNumber(f) = e => Spanned(Object::Float(Decimal::from_f64(f).unwrap()), e.span()),
+        TokenType::DecimalNumber(d) = e => Spanned(Object::Float(d), e.span()),
+    }
+}
+
 //----------------------------------------------------------------
 //-Statment Parsing-----------------------------------------------
 //----------------------------------------------------------------
 
-fn stmt_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Statement> {
+fn stmt_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, StmtId> {
     recursive(|f| {
-        let expr = recursive_expr_parser();
+        let expr = expr_parser(f.clone());
         choice((
+            test_declaration_parser(f.clone()),
             test_parser(expr.clone()),
             assign_parser(expr.clone()),
             defer_stmt_parser(f.clone()),
             statment_expr_parser(expr.clone()),
             match_parser(f.clone(), expr.clone()),
             while_parser(f.clone(), expr.clone()),
+            with_parser(f.clone(), expr.clone()),
+            extern_parser(),
             func_parser(f.clone()),
             block_parser(f.clone()),
             var_declaration_parser(expr.clone()),
             if_parser(f, expr),
             empty_stmt_parser(),
         ))
+        .map_with(alloc_stmt)
     })
     .boxed()
 }
 
 fn test_parser<'a, EP, I: TokenInput<'a>>(expr_parser: EP) -> impl TokenParser<'a, I, Statement>
 where
-    EP: TokenParser<'a, I, Expr>,
+    EP: TokenParser<'a, I, ExprId>,
 {
     just(TokenType::Test)
         .ignore_then(expr_parser)
         .then_ignore(just(TokenType::SemiColon))
-        .map(|expr| Statement::Test(expr))
+        .map(Statement::Test)
+}
+
+fn test_declaration_parser<'a, RP, I: TokenInput<'a>>(
+    stmt_parser: RP,
+) -> impl TokenParser<'a, I, Statement>
+where
+    RP: TokenParser<'a, I, StmtId>,
+{
+    attributes_parser()
+        .then_ignore(just(TokenType::Test))
+        .then(select! {
+            TokenType::StringLiteral(s) = e => Spanned(s, e.span())
+        })
+        .then(
+            stmt_parser
+                .repeated()
+                .collect::<Vec<_>>()
+                .delimited_by(just(TokenType::LeftBracket), just(TokenType::RightBracket)),
+        )
+        .map(|((attributes, name), stmts)| Statement::TestDeclaration {
+            name,
+            attributes,
+            statments: stmts,
+        })
 }
 
 fn assign_parser<'a, EP, I: TokenInput<'a>>(expr_parser: EP) -> impl TokenParser<'a, I, Statement>
 where
-    EP: TokenParser<'a, I, Expr>,
+    EP: TokenParser<'a, I, ExprId>,
 {
     group((
         var_ident(),
@@ -264,15 +473,16 @@ where
             just(TokenType::MinusAssign).to(AssignOp::Sub),
             just(TokenType::TimesAssign).to(AssignOp::Mul),
             just(TokenType::DivAssign).to(AssignOp::Div),
+            just(TokenType::IsNull).to(AssignOp::SetIfNull),
         ))
         .map_with(|ident, e| Spanned(ident, e.span())),
-        expr_parser.map_with(|ident, e| Spanned(ident, e.span())),
+        expr_parser,
     ))
     .map(|(var, op, expr)| {
         Statement::Assign(Assign {
             name: var,
             operator: op,
-            value: Box::new(expr),
+            value: expr,
         })
     })
 }
@@ -281,12 +491,12 @@ fn defer_stmt_parser<'a, RP, I: TokenInput<'a>>(
     stmt_parser: RP,
 ) -> impl TokenParser<'a, I, Statement>
 where
-    RP: TokenParser<'a, I, Statement>,
+    RP: TokenParser<'a, I, StmtId>,
 {
     just(TokenType::Defer)
-        .ignore_then(stmt_parser.map_with(|ident, e| Spanned(ident, e.span())))
+        .ignore_then(stmt_parser)
         .map(|stmt| Statement::Defer {
-            defered_statment: Box::new(stmt),
+            defered_statment: stmt,
         })
 }
 
@@ -298,10 +508,9 @@ fn empty_stmt_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Stateme
 
 fn block_parser<'a, RP, I: TokenInput<'a>>(stmt_parser: RP) -> impl TokenParser<'a, I, Statement>
 where
-    RP: TokenParser<'a, I, Statement>,
+    RP: TokenParser<'a, I, StmtId>,
 {
     stmt_parser
-        .map_with(|ident, e| Spanned(ident, e.span()))
         .repeated()
         .at_least(1)
         .collect::<Vec<_>>()
@@ -313,35 +522,62 @@ fn statment_expr_parser<'a, EP, I: TokenInput<'a>>(
     expr_parser: EP,
 ) -> impl TokenParser<'a, I, Statement>
 where
-    EP: TokenParser<'a, I, Expr>,
+    EP: TokenParser<'a, I, ExprId>,
 {
-    expr_parser
-        .map_with(|ident, e| Spanned(ident, e.span()))
-        .map(|e| Statement::Expression { expr: Box::new(e) })
+    expr_parser.map(|e| Statement::Expression { expr: e })
 }
 
 fn var_declaration_parser<'a, EP, I: TokenInput<'a>>(
     expr_parser: EP,
 ) -> impl TokenParser<'a, I, Statement>
 where
-    EP: TokenParser<'a, I, Expr>,
+    EP: TokenParser<'a, I, ExprId>,
 {
     group((
+        doc_comments_parser(),
+        pub_parser(),
         choice((
             just(TokenType::Let).to(DeclarationType::Immutable),
             just(TokenType::Var).to(DeclarationType::Mutable),
         )),
         var_ident(),
-        just(TokenType::Colon).ignore_then(type_ident()).or_not(),
-        just(TokenType::Assign)
-            .ignore_then(expr_parser.map_with(|ident, e| Spanned(ident, e.span()))),
+        choice((
+            // `int?=` is lexed as `int` followed by `?=`
+            just(TokenType::Colon)
+                .ignore_then(
+                    select! { TokenType::Identifier(i) => i }
+                        .then_ignore(just(TokenType::IsNull))
+                        .map_with(|name, e| {
+                            // The type ends at the `?`, not the `=` after it
+                            let span: Span = e.span();
+                            Some(Spanned(
+                                Type {
+                                    name,
+                                    nullable: true,
+                                },
+                                Span {
+                                    end: span.end - 1,
+                                    ..span
+                                },
+                            ))
+                        }),
+                )
+                .then(expr_parser.clone()),
+            just(TokenType::Colon)
+                .ignore_then(type_ident())
+                .or_not()
+                .then_ignore(just(TokenType::Assign))
+                .then(expr_parser),
+        )),
     ))
     .map(
-        |(declaration_type, name, manual_type, expr)| Statement::Declaration {
+        |(docs, public, declaration_type, name, (manual_type, expr))| Statement::Declaration {
+            docs,
+            public,
             declaration_type,
             name,
             manual_type,
-            value: Box::new(expr),
+            value: expr,
         },
     )
     .then_ignore(just(TokenType::SemiColon))
@@ -352,27 +588,20 @@ fn if_parser<'a, RP, EP, I: TokenInput<'a>>(
     expr_parser: EP,
 ) -> impl TokenParser<'a, I, Statement>
 where
-    RP: TokenParser<'a, I, Statement>,
-    EP: TokenParser<'a, I, Expr>,
+    RP: TokenParser<'a, I, StmtId>,
+    EP: TokenParser<'a, I, ExprId>,
 {
     just(TokenType::If)
         .ignore_then(group((
-            expr_parser.map_with(|ident, e| Spanned(ident, e.span())),
+            expr_parser,
             stmt_parser
                 .clone()
-                .map_with(|ident, e| Spanned(ident, e.span()))
                 .delimited_by(just(TokenType::LeftBracket), just(TokenType::RightBracket)),
-            (just(TokenType::Else)
-                .ignore_then(
-                    stmt_parser
-                        .map_with(|ident, e| Spanned(ident, e.span()))
-                        .map(Box::new),
-                )
-                .or_not()),
+            (just(TokenType::Else).ignore_then(stmt_parser).or_not()),
         )))
         .map(|(expr, then_stmt, else_stmt)| Statement::IfStmt {
-            condition: Box::new(expr),
-            then_branch: Box::new(then_stmt),
+            condition: expr,
+            then_branch: then_stmt,
             else_branch: else_stmt,
         })
 }
@@ -382,11 +611,11 @@ fn match_parser<'a, RP, EP, I: TokenInput<'a>>(
     expr_parser: EP,
 ) -> impl TokenParser<'a, I, Statement>
 where
-    RP: TokenParser<'a, I, Statement>,
-    EP: TokenParser<'a, I, Expr>,
+    RP: TokenParser<'a, I, StmtId>,
+    EP: TokenParser<'a, I, ExprId>,
 {
     just(TokenType::Match)
-        .ignore_then(expr_parser.map_with(|ident, e| Spanned(ident, e.span())))
+        .ignore_then(expr_parser)
         .then(
             group((
                 recursive_pat_parser().then_ignore(just(TokenType::FatArrow)),
@@ -400,7 +629,7 @@ where
         )
         .map(|(pred, thens)| {
             Statement::MatchStmt(MatchStmt {
-                predicate: Box::new(pred),
+                predicate: pred,
                 then_branches: thens,
             })
         })
@@ -411,20 +640,43 @@ fn while_parser<'a, RP, EP, I: TokenInput<'a>>(
     expr_parser: EP,
 ) -> impl TokenParser<'a, I, Statement>
 where
-    RP: TokenParser<'a, I, Statement>,
-    EP: TokenParser<'a, I, Expr>,
+    RP: TokenParser<'a, I, StmtId>,
+    EP: TokenParser<'a, I, ExprId>,
 {
     just(TokenType::While)
         .ignore_then(group((
-            expr_parser.map_with(|ident, e| Spanned(ident, e.span())),
+            expr_parser,
             stmt_parser
                 .clone()
-                .map_with(|ident, e| Spanned(ident, e.span()))
                 .delimited_by(just(TokenType::LeftBracket), just(TokenType::RightBracket)),
         )))
         .map(|(expr, then_stmt)| Statement::WhileStmt {
-            condition: Box::new(expr),
-            then_branch: Box::new(then_stmt),
+            condition: expr,
+            then_branch: then_stmt,
+        })
+}
+
+fn with_parser<'a, RP, EP, I: TokenInput<'a>>(
+    stmt_parser: RP,
+    expr_parser: EP,
+) -> impl TokenParser<'a, I, Statement>
+where
+    RP: TokenParser<'a, I, StmtId>,
+    EP: TokenParser<'a, I, ExprId>,
+{
+    just(TokenType::With)
+        .ignore_then(group((
+            var_ident().then_ignore(just(TokenType::Assign)),
+            expr_parser,
+            stmt_parser
+                .repeated()
+                .collect::<Vec<_>>()
+                .delimited_by(just(TokenType::LeftBracket), just(TokenType::RightBracket)),
+        )))
+        .map(|(name, value, statments)| Statement::With {
+            name,
+            value,
+            statments,
         })
 }
 
@@ -455,14 +707,33 @@ fn func_params_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, FuncPa
             param_value_type: Box::new(pm_type_name),
         },
     )
+    .validate(|param, _, emitter| {
+        // `in` parameters are read-only and `out` parameters have to be assigned
+        let conflict = match (param.param_type.0, param.param_restrictor) {
+            (ParamType::Input, Some(Spanned(ParamRestrictor::Mutable, span))) => {
+                Some(("`in` parameters can't be `mut`", span))
+            }
+            (ParamType::Output, Some(Spanned(ParamRestrictor::Constant, span))) => {
+                Some(("`out` parameters can't be `const`", span))
+            }
+            _ => None,
+        };
+        if let Some((message, span)) = conflict {
+            emitter.emit(Rich::custom(span, message));
+        }
+        param
+    })
 }
 
 fn func_parser<'a, RP, I: TokenInput<'a>>(stmt_parser: RP) -> impl TokenParser<'a, I, Statement>
 where
-    RP: TokenParser<'a, I, Statement>,
+    RP: TokenParser<'a, I, StmtId>,
 {
-    just(TokenType::Function)
-        .ignore_then(var_ident())
+    doc_comments_parser()
+        .then(attributes_parser())
+        .then(pub_parser())
+        .then_ignore(just(TokenType::Function))
+        .then(var_ident())
         .then(
             func_params_parser()
                 .separated_by(just(TokenType::Comma))
@@ -475,23 +746,226 @@ where
         )
         .then(just(TokenType::Comma).ignore_then(type_ident()).or_not())
         .then(
-            stmt_parser
-                .map_with(|ident, e| Spanned(ident, e.span()))
-                .repeated()
-                .at_least(1)
-                .collect::<Vec<_>>()
+            inner_doc_comments_parser()
+                .then(stmt_parser.repeated().at_least(1).collect::<Vec<_>>())
                 .delimited_by(just(TokenType::LeftBracket), just(TokenType::RightBracket)),
         )
         .map(
-            |(((fn_name, fn_pms), fn_type), fn_stmts)| Statement::FuncDeclaration {
-                name: fn_name,
-                parameters: fn_pms,
-                return_type: fn_type,
-                statments: fn_stmts,
+            |(
+                (((((outer_docs, attributes), public), fn_name), fn_pms), fn_type),
+                (inner_docs, fn_stmts),
+            )| {
+                Statement::FuncDeclaration {
+                    docs: outer_docs,
+                    inner_docs,
+                    attributes,
+                    public,
+                    name: fn_name,
+                    parameters: fn_pms,
+                    return_type: fn_type,
+                    statments: fn_stmts,
+                }
+            },
+        )
+}
+
+/// `extern "C" fn strlen(s: cstr) -> long;`, `in "libm.so.6"` after the ABI names the
+/// library to look the function up in. Variadic functions and more parameters than there
+/// are registers for are rejected here, calls can't pass them
+fn extern_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Statement> {
+    let string = select! {
+        TokenType::StringLiteral(s) = e => Spanned(s, e.span())
+    };
+    let parameter = var_ident()
+        .then_ignore(just(TokenType::Colon))
+        .then(c_type_parser());
+    doc_comments_parser()
+        .then(attributes_parser())
+        .then(pub_parser())
+        .then_ignore(just(TokenType::Extern))
+        .then_ignore(string.validate(|abi, _, emitter| {
+            if abi.0 != "C" {
+                emitter.emit(Rich::custom(abi.1, "Only the \"C\" ABI is supported"));
+            }
+        }))
+        .then(just(TokenType::In).ignore_then(string).or_not())
+        .then_ignore(just(TokenType::Function))
+        .then(select! {
+            TokenType::Symbol(s) = e => (Spanned(convert_to_hash(&s), e.span()), s)
+        })
+        .then(
+            parameter
+                .map(Some)
+                .or(just(TokenType::Ellipsis).validate(|_, e, emitter| {
+                    emitter.emit(Rich::custom(
+                        e.span(),
+                        "Variadic extern functions aren't supported",
+                    ));
+                    None
+                }))
+                .separated_by(just(TokenType::Comma))
+                .allow_trailing()
+                .collect::<Vec<_>>()
+                .delimited_by(
+                    just(TokenType::LeftParenthesis),
+                    just(TokenType::RightParenthesis),
+                )
+                .map(|parameters| parameters.into_iter().flatten().collect::<Vec<_>>())
+                .validate(|parameters: Vec<(Spanned<u64>, Spanned<CType>)>, e, emitter| {
+                    let floats = parameters
+                        .iter()
+                        .filter(|(_, c_type)| c_type.0 == CType::Float)
+                        .count();
+                    if parameters.len() - floats > ExternDeclaration::INT_PARAMETERS
+                        || floats > ExternDeclaration::FLOAT_PARAMETERS
+                    {
+                        emitter.emit(Rich::custom(
+                            e.span(),
+                            format!(
+                                "Extern functions take at most {} non-float and {} float parameters",
+                                ExternDeclaration::INT_PARAMETERS,
+                                ExternDeclaration::FLOAT_PARAMETERS
+                            ),
+                        ));
+                    }
+                    parameters
+                }),
+        )
+        .then(
+            just(TokenType::SkinnyArrow)
+                .ignore_then(c_type_parser())
+                .or_not(),
+        )
+        .then_ignore(just(TokenType::SemiColon))
+        .map(
+            |(
+                (((((docs, attributes), public), library), (name, symbol)), parameters),
+                return_type,
+            )| {
+                Statement::Extern(ExternDeclaration {
+                    docs,
+                    attributes,
+                    public,
+                    library,
+                    name,
+                    symbol,
+                    parameters,
+                    return_type,
+                })
             },
         )
 }
 
+fn c_type_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Spanned<CType>> {
+    var_ident().try_map(|name, span| {
+        CType::NAMES
+            .iter()
+            .find(|c_type| convert_to_hash(*c_type) == name.0)
+            .and_then(|c_type| CType::from_name(c_type))
+            .map(|c_type| Spanned(c_type, span))
+            .ok_or_else(|| {
+                Rich::custom(
+                    span,
+                    format!(
+                        "Unknown C type, expected one of {}",
+                        CType::NAMES.join(", ")
+                    ),
+                )
+            })
+    })
+}
+
+fn pub_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, bool> {
+    just(TokenType::Pub).or_not().map(|public| public.is_some())
+}
+
+fn use_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Statement> {
+    just(TokenType::Use)
+        .ignore_then(
+            var_ident()
+                .separated_by(just(TokenType::DoubleColon))
+                .at_least(1)
+                .collect(),
+        )
+        .then(
+            just(TokenType::DoubleColon)
+                .ignore_then(
+                    var_ident()
+                        .separated_by(just(TokenType::Comma))
+                        .allow_trailing()
+                        .at_least(1)
+                        .collect()
+                        .delimited_by(just(TokenType::LeftBracket), just(TokenType::RightBracket)),
+                )
+                .or_not(),
+        )
+        .then_ignore(just(TokenType::SemiColon))
+        .map(|(path, items)| Statement::Use { path, items })
+}
+
+//----------------------------------------------------------------
+//-Attribute Parsing----------------------------------------------
+//----------------------------------------------------------------
+
+/// `@inline` or `@deprecated("use g")` on lines of their own before a declaration
+fn attributes_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Vec<Attribute>> {
+    just(TokenType::AttributeStart)
+        .ignore_then(select! {
+            TokenType::Symbol(s) = e => Spanned(s, e.span())
+        })
+        .then(
+            literal_parser()
+                .separated_by(just(TokenType::Comma))
+                .allow_trailing()
+                .collect::<Vec<_>>()
+                .delimited_by(
+                    just(TokenType::LeftParenthesis),
+                    just(TokenType::RightParenthesis),
+                )
+                .or_not(),
+        )
+        .map(|(name, arguments)| Attribute {
+            name,
+            arguments: arguments.unwrap_or_default(),
+        })
+        .repeated()
+        .collect()
+}
+
+//----------------------------------------------------------------
+//-Doc Comment Parsing--------------------------------------------
+//----------------------------------------------------------------
+
+fn doc_comments_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Option<Spanned<String>>>
+{
+    select! {
+        TokenType::DocComment(s) = e => Spanned(s, e.span())
+    }
+    .repeated()
+    .collect::<Vec<_>>()
+    .map(join_docs)
+}
+
+fn inner_doc_comments_parser<'a, I: TokenInput<'a>>(
+) -> impl TokenParser<'a, I, Option<Spanned<String>>> {
+    select! {
+        TokenType::InnerDocComment(s) = e => Spanned(s, e.span())
+    }
+    .repeated()
+    .collect::<Vec<_>>()
+    .map(join_docs)
+}
+
+fn join_docs(lines: Vec<Spanned<String>>) -> Option<Spanned<String>> {
+    let span = concat_span(lines.first()?.1, lines.last()?.1);
+    let text = lines
+        .into_iter()
+        .map(|line| line.0)
+        .collect::<Vec<_>>()
+        .join("\n");
+    Some(Spanned(text, span))
+}
+
 //----------------------------------------------------------------
 //-Pattern Parsing------------------------------------------------
 //----------------------------------------------------------------
@@ -504,7 +978,8 @@ fn atom_pattern_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Patte
     select! {
         TokenType::WildCard => Pattern::WildCard,
         TokenType::Number(i) = e => Pattern::Literal(Literal{ value: Spanned(Object::Integer(i), e.span())}),
-        TokenType::Identifier(i) => Pattern::TypeName(Type(i)),
+        TokenType::LongNumber(l) = e => Pattern::Literal(Literal{ value: Spanned(Object::Long(l), e.span())}),
+        TokenType::Identifier(i) => Pattern::TypeName(Type::new(i)),
     }
 }
 
@@ -512,12 +987,14 @@ fn atom_pattern_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Patte
 //-Main Functions-------------------------------------------------
 //----------------------------------------------------------------
 
-pub fn get_stream<'a>(
-    tokens_and_input: (Vec<(TokenType, SimpleSpan)>, &str),
-) -> impl TokenInput<'a> {
+pub fn get_stream<'a>(tokens_and_input: (Vec<(TokenType, Span)>, &str)) -> impl TokenInput<'a> {
     let (tokens, input) = tokens_and_input;
+    // Every token is from the same file
+    let file = tokens
+        .first()
+        .map_or(FileId::default(), |(_, span)| span.file);
     chumsky::input::Stream::from_iter(tokens)
-        .spanned::<_, chumsky::span::SimpleSpan>((input.len()..input.len()).into())
+        .spanned::<_, Span>(Span::new(file, input.len()..input.len()))
 }
 
 fn var_ident<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Spanned<u64>> {
@@ -526,19 +1003,103 @@ fn var_ident<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Spanned<u64>> {
     }
 }
 
+/// `a::b::c`, just `a` for a name that isn't qualified
+fn path_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Vec<u64>> {
+    select! {
+        TokenType::Identifier(i) => i
+    }
+    .separated_by(just(TokenType::DoubleColon))
+    .at_least(1)
+    .collect()
+}
+
+/// `int`, or `int?` for one that can be null
 fn type_ident<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Spanned<Type>> {
     select! {
-        TokenType::Identifier(i) = e => Spanned(Type(i), e.span())
+        TokenType::Identifier(i) => i
     }
+    .then(just(TokenType::NullChecker).or_not())
+    .map_with(|(name, nullable), e| {
+        Spanned(
+            Type {
+                name,
+                nullable: nullable.is_some(),
+            },
+            e.span(),
+        )
+    })
 }
 
-pub fn parse<'a>(stream: impl TokenInput<'a>) -> Statement {
-    let res = stmt_parser().parse(stream).into_result();
-    match res {
-        Ok(stmt) => stmt,
-        Err(e) => {
-            println!("{e:?}");
-            Statement::Error
-        }
+fn program_parser<'a, I: TokenInput<'a>>() -> impl TokenParser<'a, I, Program> {
+    inner_doc_comments_parser()
+        .then(
+            choice((use_parser().map_with(alloc_stmt), stmt_parser()))
+                .repeated()
+                .collect::<Vec<_>>(),
+        )
+        .then_ignore(end())
+        .map_with(|(docs, statements), e: &mut Extra<'a, '_, I>| Program {
+            docs,
+            statements,
+            arena: Rc::new(std::mem::take(e.state())),
+            file: e.span().file,
+            modules: vec![],
+        })
+}
+
+/// A syntax error, `message` is the `Rich` error rendered as text
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl ParseError {
+    pub fn report(&self, sources: &SourceMap) {
+        let _ = self.write(sources, std::io::stderr());
+    }
+
+    /// Renders the report `report` prints to `writer`
+    pub fn write(&self, sources: &SourceMap, writer: impl std::io::Write) -> std::io::Result<()> {
+        source_map::report(ReportKind::Error, self.span)
+            .with_message("Failed to parse input")
+            .with_label(
+                Label::new(self.span)
+                    .with_message(&self.message)
+                    .with_color(Color::Red),
+            )
+            .finish()
+            .write(sources.cache(), writer)
     }
 }
+
+fn error_message(err: &Rich<TokenType, Span>) -> String {
+    if let chumsky::error::RichReason::Custom(message) = err.reason() {
+        return message.clone();
+    }
+    let found = err
+        .found()
+        .map_or("end of input".to_string(), |token| format!("{token:?}"));
+    let expected = err
+        .expected()
+        .map(|pattern| format!("{pattern:?}"))
+        .collect::<Vec<_>>();
+    match expected.is_empty() {
+        true => format!("Unexpected {found}"),
+        false => format!("Found {found}, expected {}", expected.join(", ")),
+    }
+}
+
+pub fn try_parse<'a>(stream: impl TokenInput<'a>) -> Result<Program, Vec<ParseError>> {
+    program_parser()
+        .parse_with_state(stream, &mut Arena::new())
+        .into_result()
+        .map_err(|errs| {
+            errs.into_iter()
+                .map(|err| ParseError {
+                    message: error_message(&err),
+                    span: *err.span(),
+                })
+                .collect()
+        })
+}
@@ -0,0 +1,188 @@
+use crate::{
+    ast::{
+        expr::Expr,
+        misc::{AssignOp, BinaryOp},
+        stmt::{Program, Statement},
+    },
+    lexer::lexer::scan,
+};
+
+use super::parser::{get_stream, try_parse};
+
+fn parse_source(source: &str) -> Program {
+    let tokens = scan(source).unwrap();
+    try_parse(get_stream((tokens, source))).unwrap()
+}
+
+#[test]
+fn parse_doc_comments() {
+    let program = parse_source(
+        "//! Module docs\n/// The answer\n/// to everything\nlet x = 42;\nfn f() {\n//! Inner\nx\n}",
+    );
+    assert_eq!(
+        Some("Module docs"),
+        program.docs.as_ref().map(|d| d.0.as_str())
+    );
+    let docs: Vec<_> = program
+        .statements
+        .iter()
+        .map(|stmt| program.arena[*stmt].0.docs().map(|d| d.0.clone()))
+        .collect();
+    assert_eq!(
+        vec![
+            Some("The answer\nto everything".into()),
+            Some("Inner".into())
+        ],
+        docs
+    );
+    assert!(matches!(
+        program.arena[program.statements[0]].0,
+        Statement::Declaration { .. }
+    ));
+}
+
+#[test]
+fn parse_calls_on_any_callee() {
+    let program = parse_source("make_adder(1)(2) + f(x);");
+    let Statement::Expression { expr } = program.arena[program.statements[0]].0 else {
+        panic!("expected an expression statement");
+    };
+    let Expr::Binary(binary) = &program.arena[expr].0 else {
+        panic!("expected calls to be operands");
+    };
+    let Expr::FunctionCall { callee, .. } = &program.arena[binary.left].0 else {
+        panic!("expected a call");
+    };
+    assert!(matches!(
+        program.arena[*callee].0,
+        Expr::FunctionCall { .. }
+    ));
+    let program = parse_source("let f = fn(val x: int) -> x * 2;");
+    let Statement::Declaration { value, .. } = program.arena[program.statements[0]].0 else {
+        panic!("expected a declaration");
+    };
+    assert!(matches!(&program.arena[value].0, Expr::Lambda(lambda) if lambda.arrow));
+}
+
+#[test]
+fn parse_parameter_modes() {
+    let errors = |source: &str| {
+        let tokens = scan(source).unwrap();
+        try_parse(get_stream((tokens, source)))
+            .err()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|error| error.message)
+            .collect::<Vec<_>>()
+    };
+    assert!(
+        errors("fn f(ref a: mut int, in b: const int, out c: int) {\nc = a + b;\n}").is_empty()
+    );
+    assert_eq!(
+        vec!["`in` parameters can't be `mut`"],
+        errors("fn f(in a: mut int) {\na\n}")
+    );
+    assert_eq!(
+        vec!["`out` parameters can't be `const`"],
+        errors("fn f(out a: const int) {\na = 1;\n}")
+    );
+}
+
+#[test]
+fn parse_extern_signatures() {
+    let errors = |source: &str| {
+        let tokens = scan(source).unwrap();
+        try_parse(get_stream((tokens, source)))
+            .err()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|error| error.message)
+            .collect::<Vec<_>>()
+    };
+    assert!(errors("extern \"C\" fn pow(x: float, y: float) -> float;").is_empty());
+    assert_eq!(
+        vec!["Variadic extern functions aren't supported"],
+        errors("extern \"C\" fn printf(format: cstr, ...) -> int;")
+    );
+    assert_eq!(
+        vec!["Extern functions take at most 6 non-float and 8 float parameters"],
+        errors("extern \"C\" fn f(a: int, b: int, c: int, d: int, e: int, f: ptr, g: long);")
+    );
+}
+
+#[test]
+fn parse_attributes() {
+    let program = parse_source(
+        "/// Docs\n@deprecated(\"use g\", 2, true)\n@test\npub fn f() { 1; }\n@ignore test \"t\" {}",
+    );
+    let attributes: Vec<Vec<_>> = program
+        .statements
+        .iter()
+        .map(|stmt| {
+            program.arena[*stmt]
+                .0
+                .attributes()
+                .iter()
+                .map(|attribute| (attribute.name.0.clone(), attribute.arguments.len()))
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            vec![("deprecated".to_string(), 3), ("test".to_string(), 0)],
+            vec![("ignore".to_string(), 0)],
+        ],
+        attributes
+    );
+    let Statement::FuncDeclaration { docs, public, .. } = &program.arena[program.statements[0]].0
+    else {
+        panic!("expected a function");
+    };
+    assert_eq!(Some("Docs"), docs.as_ref().map(|d| d.0.as_str()));
+    assert!(public);
+}
+
+#[test]
+fn parse_null_safety() {
+    let program = parse_source("var a: int? = b ?? c?.f(1) + 2;\na ?= 3;");
+    let Statement::Declaration {
+        manual_type: Some(manual_type),
+        value,
+        ..
+    } = &program.arena[program.statements[0]].0
+    else {
+        panic!("expected a typed declaration");
+    };
+    assert!(manual_type.0.nullable);
+    // `??` binds looser than `+`
+    let Expr::Binary(coalesce) = &program.arena[*value].0 else {
+        panic!("expected `??`");
+    };
+    assert_eq!(BinaryOp::Coalesce, coalesce.operator.0);
+    let Expr::Binary(sum) = &program.arena[coalesce.right].0 else {
+        panic!("expected `+`");
+    };
+    let Expr::FunctionCall {
+        arguments, safe, ..
+    } = &program.arena[sum.left].0
+    else {
+        panic!("expected a safe call");
+    };
+    assert!(safe);
+    assert_eq!(2, arguments.as_ref().unwrap().len());
+    let Statement::Assign(assign) = &program.arena[program.statements[1]].0 else {
+        panic!("expected an assignment");
+    };
+    assert_eq!(AssignOp::SetIfNull, assign.operator.0);
+}
+
+#[test]
+fn parse_stray_doc_comments() {
+    let source = "/// note\nprintln(1);\nlet x = 1; /// after\n/// before a test\n@ignore\ntest \"t\" {}\nif x > 0 {\n//! not a function\nprintln(x)\n}\n/// last";
+    let program = try_parse(get_stream((scan(source).unwrap(), source))).unwrap();
+    assert_eq!(5, program.statements.len());
+    assert!(program
+        .statements
+        .iter()
+        .all(|stmt| program.arena[*stmt].0.docs().is_none()));
+}
@@ -0,0 +1,4 @@
+pub mod report;
+pub mod runner;
+#[cfg(test)]
+mod test;
@@ -0,0 +1,189 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use serde_json::{json, Value};
+
+use super::runner::{Outcome, TestResult};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// Readable progress and a summary
+    #[default]
+    Human,
+    /// JUnit XML, one test suite per file
+    Junit,
+    Json,
+}
+
+/// `results` rendered in `format`
+pub fn report(results: &[TestResult], format: ReportFormat) -> String {
+    match format {
+        ReportFormat::Human => human(results),
+        ReportFormat::Junit => junit(results),
+        ReportFormat::Json => format!("{:#}\n", json(results)),
+    }
+}
+
+fn count(results: &[TestResult], outcome: fn(&Outcome) -> bool) -> usize {
+    results.iter().filter(|r| outcome(&r.outcome)).count()
+}
+
+fn failed(outcome: &Outcome) -> bool {
+    matches!(outcome, Outcome::Failed(_))
+}
+
+fn ignored(outcome: &Outcome) -> bool {
+    matches!(outcome, Outcome::Ignored)
+}
+
+fn passed(outcome: &Outcome) -> bool {
+    matches!(outcome, Outcome::Passed)
+}
+
+//----------------------------------------------------------------
+//-Human----------------------------------------------------------
+//----------------------------------------------------------------
+
+fn human(results: &[TestResult]) -> String {
+    let mut text = format!("running {} test(s)\n", results.len());
+    for result in results {
+        let status = match result.outcome {
+            Outcome::Passed => "ok",
+            Outcome::Failed(_) => "FAILED",
+            Outcome::Ignored => "ignored",
+        };
+        text.push_str(&format!(
+            "test {}::{} ... {status}\n",
+            result.file.display(),
+            result.name
+        ));
+    }
+    let failures: Vec<_> = results.iter().filter(|r| failed(&r.outcome)).collect();
+    if !failures.is_empty() {
+        text.push_str("\nfailures:\n");
+    }
+    for result in &failures {
+        let Outcome::Failed(message) = &result.outcome else {
+            continue;
+        };
+        text.push_str(&format!(
+            "\n---- {}::{} ----\n{message}\n",
+            result.file.display(),
+            result.name
+        ));
+        if !result.output.is_empty() {
+            text.push_str(&format!("output:\n{}", result.output));
+            if !result.output.ends_with('\n') {
+                text.push('\n');
+            }
+        }
+    }
+    let summary = match failures.is_empty() {
+        true => "ok",
+        false => "FAILED",
+    };
+    text.push_str(&format!(
+        "\ntest result: {summary}. {} passed; {} failed; {} ignored\n",
+        count(results, passed),
+        failures.len(),
+        count(results, ignored)
+    ));
+    text
+}
+
+//----------------------------------------------------------------
+//-JUnit----------------------------------------------------------
+//----------------------------------------------------------------
+
+fn junit(results: &[TestResult]) -> String {
+    let mut files: BTreeMap<&PathBuf, Vec<&TestResult>> = BTreeMap::new();
+    for result in results {
+        files.entry(&result.file).or_default().push(result);
+    }
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"scorpionest\" tests=\"{}\" failures=\"{}\" skipped=\"{}\">\n",
+        results.len(),
+        count(results, failed),
+        count(results, ignored)
+    ));
+    for (file, tests) in files {
+        let suite = escape(&file.display().to_string());
+        let time: f64 = tests.iter().map(|t| t.duration.as_secs_f64()).sum();
+        xml.push_str(&format!(
+            "  <testsuite name=\"{suite}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{time:.3}\">\n",
+            tests.len(),
+            tests.iter().filter(|t| failed(&t.outcome)).count(),
+            tests.iter().filter(|t| ignored(&t.outcome)).count(),
+        ));
+        for test in tests {
+            xml.push_str(&format!(
+                "    <testcase name=\"{}\" classname=\"{suite}\" time=\"{:.3}\"",
+                escape(&test.name),
+                test.duration.as_secs_f64()
+            ));
+            if test.outcome == Outcome::Passed && test.output.is_empty() {
+                xml.push_str("/>\n");
+                continue;
+            }
+            xml.push_str(">\n");
+            match &test.outcome {
+                Outcome::Failed(message) => xml.push_str(&format!(
+                    "      <failure message=\"{}\">{}</failure>\n",
+                    escape(message.lines().next().unwrap_or_default()),
+                    escape(message)
+                )),
+                Outcome::Ignored => xml.push_str("      <skipped/>\n"),
+                Outcome::Passed => (),
+            }
+            if !test.output.is_empty() {
+                xml.push_str(&format!(
+                    "      <system-out>{}</system-out>\n",
+                    escape(&test.output)
+                ));
+            }
+            xml.push_str("    </testcase>\n");
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+//----------------------------------------------------------------
+//-JSON-----------------------------------------------------------
+//----------------------------------------------------------------
+
+fn json(results: &[TestResult]) -> Value {
+    let tests: Vec<_> = results
+        .iter()
+        .map(|result| {
+            let (outcome, message) = match &result.outcome {
+                Outcome::Passed => ("passed", None),
+                Outcome::Failed(message) => ("failed", Some(message)),
+                Outcome::Ignored => ("ignored", None),
+            };
+            json!({
+                "file": result.file.display().to_string(),
+                "name": result.name,
+                "outcome": outcome,
+                "message": message,
+                "duration": result.duration.as_secs_f64(),
+                "output": result.output,
+            })
+        })
+        .collect();
+    json!({
+        "passed": count(results, passed),
+        "failed": count(results, failed),
+        "ignored": count(results, ignored),
+        "tests": tests,
+    })
+}
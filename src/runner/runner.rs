@@ -0,0 +1,173 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ast::{
+        arena::StmtId,
+        stmt::{Program, Statement},
+    },
+    engine::{
+        engine::{Backend, Engine},
+        error::Error,
+    },
+};
+
+/// Which tests to run
+#[derive(Debug, Clone, Default)]
+pub struct TestOptions {
+    /// Only tests whose name contains this are run
+    pub filter: Option<String>,
+    /// Runs `@ignore` tests as well
+    pub include_ignored: bool,
+    pub backend: Backend,
+    /// Where modules are looked for besides next to the file
+    pub search_path: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Passed,
+    /// The error that stopped the test
+    Failed(String),
+    Ignored,
+}
+
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    pub file: PathBuf,
+    pub name: String,
+    pub outcome: Outcome,
+    pub duration: Duration,
+    /// Everything the test printed
+    pub output: String,
+}
+
+/// Every script under `paths`, directories are searched recursively for `.sn` files
+pub fn discover(paths: &[PathBuf]) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths {
+        match path.is_dir() {
+            true => discover_dir(path, &mut files)?,
+            false => files.push(path.clone()),
+        }
+    }
+    Ok(files)
+}
+
+fn discover_dir(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            discover_dir(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "sn") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// What a test runs
+enum Body {
+    /// The statements of a `test "name" { ... }` declaration
+    Statements(Vec<StmtId>),
+    /// Calls the `@test` function of this name
+    Function(String),
+}
+
+/// Runs the tests declared in `source`, each one in a fresh engine that first runs
+/// the rest of the file so tests can use its functions and globals. Top level `@test`
+/// functions are tests named after the function
+pub fn run_tests(
+    file: &Path,
+    source: &str,
+    options: &TestOptions,
+) -> Result<Vec<TestResult>, Error> {
+    let program = engine(options).compile_file(file, source)?;
+    let arena = &program.arena;
+    let setup = Program {
+        statements: program
+            .statements
+            .iter()
+            .copied()
+            .filter(|stmt| !matches!(arena[*stmt].0, Statement::TestDeclaration { .. }))
+            .collect(),
+        ..program.clone()
+    };
+    let results = program
+        .statements
+        .iter()
+        .filter_map(|test| {
+            let (name, body) = match &arena[*test].0 {
+                Statement::TestDeclaration {
+                    name, statments, ..
+                } => (name.0.clone(), Body::Statements(statments.clone())),
+                stmt @ Statement::FuncDeclaration { name, .. }
+                    if stmt.attribute("test").is_some() =>
+                {
+                    let name = source[name.1.into_range()].to_string();
+                    (name.clone(), Body::Function(name))
+                }
+                _ => return None,
+            };
+            if options
+                .filter
+                .as_ref()
+                .is_some_and(|filter| !name.contains(filter.as_str()))
+            {
+                return None;
+            }
+            let result = TestResult {
+                file: file.to_path_buf(),
+                name,
+                outcome: Outcome::Ignored,
+                duration: Duration::ZERO,
+                output: String::new(),
+            };
+            let ignored = arena[*test].0.attribute("ignore").is_some();
+            match ignored && !options.include_ignored {
+                true => Some(result),
+                false => Some(run_test(&setup, body, options, result)),
+            }
+        })
+        .collect();
+    Ok(results)
+}
+
+/// An engine that looks for modules where `options` says to
+fn engine(options: &TestOptions) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_backend(options.backend);
+    for dir in &options.search_path {
+        engine.add_search_path(dir);
+    }
+    engine
+}
+
+fn run_test(setup: &Program, body: Body, options: &TestOptions, result: TestResult) -> TestResult {
+    let mut engine = engine(options);
+    engine.capture_output();
+    let start = Instant::now();
+    let run = engine.run(setup).and_then(|_| match body {
+        // The engine already ran the modules for the setup
+        Body::Statements(statements) => engine.run(&Program {
+            statements,
+            ..setup.clone()
+        }),
+        Body::Function(name) => engine.run_source(&format!("{name}();")),
+    });
+    let outcome = match run {
+        Ok(()) => Outcome::Passed,
+        Err(error) => Outcome::Failed(error.to_string()),
+    };
+    TestResult {
+        outcome,
+        duration: start.elapsed(),
+        output: engine.take_output(),
+        ..result
+    }
+}
@@ -0,0 +1,164 @@
+use std::path::Path;
+
+use crate::engine::engine::Backend;
+
+use super::{
+    report::{report, ReportFormat},
+    runner::{run_tests, Outcome, TestOptions},
+};
+
+const SOURCE: &str = r#"
+fn add(val a: int, val b: int), int {
+    a + b
+}
+var counter = 0;
+
+test "adds numbers" {
+    assert_eq(add(1, 2), 3);
+    counter += 1;
+    assert_eq(counter, 1);
+}
+
+test "counter starts fresh" {
+    counter += 1;
+    assert_eq(counter, 1);
+}
+
+test "prints and fails" {
+    println("checking");
+    assert_eq("a\nb\nc", "a\nB\nc");
+}
+
+@ignore
+test "slow" {
+    panic("never runs");
+}
+"#;
+
+fn outcomes(options: &TestOptions) -> Vec<(String, Outcome)> {
+    run_tests(Path::new("math.sn"), SOURCE, options)
+        .unwrap()
+        .into_iter()
+        .map(|result| (result.name, result.outcome))
+        .collect()
+}
+
+#[test]
+fn tests_run_isolated() {
+    let results = outcomes(&TestOptions::default());
+    assert_eq!(4, results.len());
+    assert_eq!(("adds numbers".to_string(), Outcome::Passed), results[0]);
+    assert_eq!(Outcome::Passed, results[1].1);
+    assert_eq!(
+        Outcome::Failed(
+            "Assertion failed, left != right\n--- left\n+++ right\n a\n-b\n+B\n c".to_string()
+        ),
+        results[2].1
+    );
+    assert_eq!(Outcome::Ignored, results[3].1);
+}
+
+#[test]
+fn filters_and_ignored() {
+    let results = outcomes(&TestOptions {
+        filter: Some("s".to_string()),
+        include_ignored: true,
+        ..TestOptions::default()
+    });
+    let names: Vec<_> = results.iter().map(|r| r.0.as_str()).collect();
+    assert_eq!(
+        vec![
+            "adds numbers",
+            "counter starts fresh",
+            "prints and fails",
+            "slow"
+        ],
+        names
+    );
+    assert_eq!(Outcome::Failed("never runs".to_string()), results[3].1);
+
+    let results = outcomes(&TestOptions {
+        filter: Some("counter".to_string()),
+        ..TestOptions::default()
+    });
+    assert_eq!(1, results.len());
+}
+
+#[test]
+fn scripts_skip_test_declarations() {
+    let mut engine = crate::Engine::new();
+    engine.capture_output();
+    engine
+        .run_source("test \"never\" { println(1); } println(2);")
+        .unwrap();
+    assert_eq!("2\n", engine.take_output());
+}
+
+#[test]
+fn reports() {
+    let results = run_tests(Path::new("math.sn"), SOURCE, &TestOptions::default()).unwrap();
+
+    let human = report(&results, ReportFormat::Human);
+    assert!(human.contains("test math.sn::adds numbers ... ok\n"));
+    assert!(human.contains("---- math.sn::prints and fails ----\n"));
+    assert!(human.contains("output:\nchecking\n"));
+    assert!(human.ends_with("test result: FAILED. 2 passed; 1 failed; 1 ignored\n"));
+
+    let junit = report(&results, ReportFormat::Junit);
+    assert!(junit
+        .contains("<testsuites name=\"scorpionest\" tests=\"4\" failures=\"1\" skipped=\"1\">"));
+    assert!(junit.contains("<failure message=\"Assertion failed, left != right\">"));
+    assert!(junit.contains("<system-out>checking\n</system-out>"));
+    assert!(junit.contains("<skipped/>"));
+
+    let json: serde_json::Value =
+        serde_json::from_str(&report(&results, ReportFormat::Json)).unwrap();
+    assert_eq!(2, json["passed"]);
+    assert_eq!("failed", json["tests"][2]["outcome"]);
+    assert_eq!("math.sn", json["tests"][0]["file"]);
+}
+
+#[test]
+fn test_functions() {
+    let source = "@test\nfn passes() { assert_eq(1, 1); }\n@test\n@ignore\nfn slow() { panic(\"never runs\"); }\nfn helper() { panic(\"not a test\"); }";
+    for backend in [Backend::Tree, Backend::Vm] {
+        let results: Vec<_> = run_tests(
+            Path::new("fns.sn"),
+            source,
+            &TestOptions {
+                backend,
+                ..TestOptions::default()
+            },
+        )
+        .unwrap()
+        .into_iter()
+        .map(|result| (result.name, result.outcome))
+        .collect();
+        assert_eq!(
+            vec![
+                ("passes".to_string(), Outcome::Passed),
+                ("slow".to_string(), Outcome::Ignored)
+            ],
+            results
+        );
+    }
+}
+
+#[test]
+fn modules_on_the_search_path() {
+    let lib = std::env::temp_dir().join(format!("scorpionest_runner_lib_{}", std::process::id()));
+    std::fs::create_dir_all(&lib).unwrap();
+    std::fs::write(
+        lib.join("math.sn"),
+        "pub fn double(val n: int), int { n * 2 }",
+    )
+    .unwrap();
+    let source = "use math::{double};\ntest \"doubles\" { assert_eq(double(2), 4); }";
+    let options = TestOptions {
+        search_path: vec![lib.clone()],
+        ..TestOptions::default()
+    };
+    let results = run_tests(Path::new("uses.sn"), source, &options).unwrap();
+    std::fs::remove_dir_all(&lib).unwrap();
+    assert_eq!(Outcome::Passed, results[0].outcome);
+}
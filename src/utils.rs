@@ -1,4 +1,9 @@
+pub mod error;
+pub mod format;
 pub mod interner;
 pub mod object;
+pub mod source_map;
 pub mod spanned;
+#[cfg(test)]
+mod test;
 pub mod valtype;
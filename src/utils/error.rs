@@ -0,0 +1,18 @@
+use std::fmt::Display;
+
+/// An error raised while a program is running
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{message}")]
+pub struct RuntimeError {
+    pub message: String,
+}
+
+impl RuntimeError {
+    pub fn new(message: impl Display) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+pub type RuntimeResult<T> = Result<T, RuntimeError>;
@@ -0,0 +1,155 @@
+use rust_decimal::Decimal;
+
+use super::error::{RuntimeError, RuntimeResult};
+use super::object::Object;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+/// The part of an interpolation after the `:`, e.g. `{x:.2}`, `{n:>8}` or `{v:?}`
+///
+/// Grammar: `[[fill]align][width][.precision][?]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FormatSpec {
+    pub fill: char,
+    pub align: Option<Alignment>,
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+    pub debug: bool,
+}
+
+impl Default for FormatSpec {
+    fn default() -> Self {
+        Self {
+            fill: ' ',
+            align: None,
+            width: None,
+            precision: None,
+            debug: false,
+        }
+    }
+}
+
+fn to_alignment(c: char) -> Option<Alignment> {
+    match c {
+        '<' => Some(Alignment::Left),
+        '^' => Some(Alignment::Center),
+        '>' => Some(Alignment::Right),
+        _ => None,
+    }
+}
+
+impl FormatSpec {
+    pub fn parse(spec: &str) -> Option<FormatSpec> {
+        let mut result = FormatSpec::default();
+        let mut rest = spec;
+
+        let mut chars = rest.chars();
+        let first = chars.next();
+        let second = chars.next();
+        if let (Some(fill), Some(align)) = (first, second.and_then(to_alignment)) {
+            result.fill = fill;
+            result.align = Some(align);
+            rest = &rest[fill.len_utf8() + 1..];
+        } else if let Some(align) = first.and_then(to_alignment) {
+            result.align = Some(align);
+            rest = &rest[1..];
+        }
+
+        let width_len = rest.chars().take_while(char::is_ascii_digit).count();
+        if width_len > 0 {
+            result.width = Some(rest[..width_len].parse().ok()?);
+            rest = &rest[width_len..];
+        }
+
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            let precision_len = after_dot.chars().take_while(char::is_ascii_digit).count();
+            if precision_len == 0 {
+                return None;
+            }
+            result.precision = Some(after_dot[..precision_len].parse().ok()?);
+            rest = &after_dot[precision_len..];
+        }
+
+        if let Some(after_debug) = rest.strip_prefix('?') {
+            result.debug = true;
+            rest = after_debug;
+        }
+
+        rest.is_empty().then_some(result)
+    }
+
+    pub fn apply(&self, value: &Object) -> String {
+        let text = match (value, self.precision) {
+            (_, _) if self.debug => debug_repr(value),
+            (Object::Float(f), Some(p)) => format!("{:.*}", p, f.round_dp(p as u32)),
+            (Object::Integer(i), Some(p)) => format!("{:.*}", p, Decimal::from(*i)),
+            (Object::Long(l), Some(p)) => format!("{:.*}", p, Decimal::from(*l)),
+            (Object::String(_), Some(p)) => value.to_string().chars().take(p).collect(),
+            _ => value.to_string(),
+        };
+        let Some(width) = self.width else {
+            return text;
+        };
+        let padding = width.saturating_sub(text.chars().count());
+        let default_align = match value {
+            Object::Integer(_) | Object::Long(_) | Object::Float(_) => Alignment::Right,
+            _ => Alignment::Left,
+        };
+        let (before, after) = match self.align.unwrap_or(default_align) {
+            Alignment::Left => (0, padding),
+            Alignment::Center => (padding / 2, padding - padding / 2),
+            Alignment::Right => (padding, 0),
+        };
+        let fill = |count: usize| std::iter::repeat_n(self.fill, count).collect::<String>();
+        format!("{}{text}{}", fill(before), fill(after))
+    }
+}
+
+/// Like the `Display` output, except strings are quoted and escaped
+pub fn debug_repr(value: &Object) -> String {
+    match value {
+        Object::String(_) => format!("{:?}", value.to_string()),
+        _ => value.to_string(),
+    }
+}
+
+/// Fills every `{}`/`{:spec}` placeholder of `template` with the next argument,
+/// `{{` and `}}` produce literal braces
+pub fn format_template(template: &str, args: &[Object]) -> RuntimeResult<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut rest = template;
+    while let Some(index) = rest.find(['{', '}']) {
+        output.push_str(&rest[..index]);
+        let brace = &rest[index..];
+        if brace.starts_with("{{") || brace.starts_with("}}") {
+            output.push_str(&brace[..1]);
+            rest = &brace[2..];
+            continue;
+        }
+        let Some(end) = brace.find('}').filter(|_| brace.starts_with('{')) else {
+            return Err(RuntimeError::new("Unmatched '}}' in format string!"));
+        };
+        let spec = match brace[1..end].strip_prefix(':') {
+            Some(spec) => FormatSpec::parse(spec)
+                .ok_or_else(|| RuntimeError::new(format!("Invalid format spec '{spec}'!")))?,
+            None if end == 1 => FormatSpec::default(),
+            None => return Err(RuntimeError::new("Invalid format placeholder!")),
+        };
+        let arg = args
+            .next()
+            .ok_or_else(|| RuntimeError::new("Not enough arguments for format string!"))?;
+        output.push_str(&spec.apply(arg));
+        rest = &brace[end + 1..];
+    }
+    output.push_str(rest);
+    if args.next().is_some() {
+        return Err(RuntimeError::new("Too many arguments for format string!"));
+    }
+    Ok(output)
+}
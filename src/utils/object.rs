@@ -1,17 +1,22 @@
-use anyhow::anyhow;
 use rust_decimal::Decimal;
 use std::{fmt::Display, ops};
 
 use lasso::Spur;
 
-use super::interner::INTERNER;
+use super::{
+    error::{RuntimeError, RuntimeResult},
+    interner::INTERNER,
+};
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub enum Object {
     String(Spur),
     Integer(i32),
+    Long(i64),
     Float(Decimal),
     Boolean(bool),
     NullValue,
+    /// Index into the interpreter's table of function values
+    Function(u32),
 }
 
 impl<'a> Display for Object {
@@ -19,18 +24,37 @@ impl<'a> Display for Object {
         match &self {
             Object::String(s) => write!(f, "{}", INTERNER.resolve(s)),
             Object::Integer(i) => write!(f, "{i}"),
+            Object::Long(l) => write!(f, "{l}"),
             Object::Float(flt) => write!(f, "{flt}"),
             Object::Boolean(b) => write!(f, "{b}"),
             Object::NullValue => write!(f, "null"),
+            Object::Function(_) => write!(f, "<function>"),
+        }
+    }
+}
+
+impl Object {
+    /// Converts two numbers into their widest shared type, `Integer` < `Long` < `Float`,
+    /// anything else is returned unchanged
+    pub fn promote(self, other: Object) -> (Object, Object) {
+        use Object::*;
+        match (self, other) {
+            (Integer(i), Long(l)) => (Long(i.into()), Long(l)),
+            (Long(l), Integer(i)) => (Long(l), Long(i.into())),
+            (Integer(i), Float(f)) => (Float(i.into()), Float(f)),
+            (Float(f), Integer(i)) => (Float(f), Float(i.into())),
+            (Long(l), Float(f)) => (Float(l.into()), Float(f)),
+            (Float(f), Long(l)) => (Float(f), Float(l.into())),
+            pair => pair,
         }
     }
 }
 
 impl ops::Add for Object {
-    type Output = anyhow::Result<Object>;
+    type Output = RuntimeResult<Object>;
 
     fn add(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
+        match self.promote(rhs) {
             (Object::String(s1), Object::String(s2)) => {
                 let str1 = INTERNER.resolve(&s1);
                 let str2 = INTERNER.resolve(&s2);
@@ -38,58 +62,73 @@ impl ops::Add for Object {
                     INTERNER.get_or_intern(format!("{}{}", str1, str2)),
                 ))
             }
-            (Object::Integer(i1), Object::Integer(i2)) => Ok(Object::Integer(i1 + i2)),
-            (Object::Integer(i), Object::Float(f)) | (Object::Float(f), Object::Integer(i)) => {
-                Ok(Object::Float(f + Decimal::from(i)))
-            }
+            (Object::Integer(i1), Object::Integer(i2)) => i1
+                .checked_add(i2)
+                .map(Object::Integer)
+                .ok_or_else(|| RuntimeError::new("Integer overflow!")),
+            (Object::Long(l1), Object::Long(l2)) => l1
+                .checked_add(l2)
+                .map(Object::Long)
+                .ok_or_else(|| RuntimeError::new("Integer overflow!")),
             (Object::Float(f1), Object::Float(f2)) => Ok(Object::Float(f1 + f2)),
-            _ => Err(anyhow!("Invalid operation arguments!")),
+            _ => Err(RuntimeError::new("Invalid operation arguments!")),
         }
     }
 }
 
 impl ops::Sub for Object {
-    type Output = anyhow::Result<Object>;
+    type Output = RuntimeResult<Object>;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Object::Integer(i1), Object::Integer(i2)) => Ok(Object::Integer(i1 - i2)),
-            (Object::Integer(i), Object::Float(f)) | (Object::Float(f), Object::Integer(i)) => {
-                Ok(Object::Float(f - Decimal::from(i)))
-            }
+        match self.promote(rhs) {
+            (Object::Integer(i1), Object::Integer(i2)) => i1
+                .checked_sub(i2)
+                .map(Object::Integer)
+                .ok_or_else(|| RuntimeError::new("Integer overflow!")),
+            (Object::Long(l1), Object::Long(l2)) => l1
+                .checked_sub(l2)
+                .map(Object::Long)
+                .ok_or_else(|| RuntimeError::new("Integer overflow!")),
             (Object::Float(f1), Object::Float(f2)) => Ok(Object::Float(f1 - f2)),
-            _ => Err(anyhow!("Invalid operation arguments!")),
+            _ => Err(RuntimeError::new("Invalid operation arguments!")),
         }
     }
 }
 
 impl ops::Mul for Object {
-    type Output = anyhow::Result<Object>;
+    type Output = RuntimeResult<Object>;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Object::Integer(i1), Object::Integer(i2)) => Ok(Object::Integer(i1 * i2)),
-            (Object::Integer(i), Object::Float(f)) | (Object::Float(f), Object::Integer(i)) => {
-                Ok(Object::Float(f * Decimal::from(i)))
-            }
+        match self.promote(rhs) {
+            (Object::Integer(i1), Object::Integer(i2)) => i1
+                .checked_mul(i2)
+                .map(Object::Integer)
+                .ok_or_else(|| RuntimeError::new("Integer overflow!")),
+            (Object::Long(l1), Object::Long(l2)) => l1
+                .checked_mul(l2)
+                .map(Object::Long)
+                .ok_or_else(|| RuntimeError::new("Integer overflow!")),
             (Object::Float(f1), Object::Float(f2)) => Ok(Object::Float(f1 * f2)),
-            _ => Err(anyhow!("Invalid operation arguments!")),
+            _ => Err(RuntimeError::new("Invalid operation arguments!")),
         }
     }
 }
 
 impl ops::Div for Object {
-    type Output = anyhow::Result<Object>;
+    type Output = RuntimeResult<Object>;
     fn div(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
+        match self.promote(rhs) {
             (Object::Integer(i1), Object::Integer(i2)) => {
                 Ok(Object::Integer(i1.checked_div(i2).unwrap_or(0)))
             }
-            (Object::Integer(i), Object::Float(f)) | (Object::Float(f), Object::Integer(i)) => {
-                Ok(Object::Float(f / Decimal::from(i)))
+            (Object::Long(l1), Object::Long(l2)) => {
+                Ok(Object::Long(l1.checked_div(l2).unwrap_or(0)))
             }
-            (Object::Float(f1), Object::Float(f2)) => Ok(Object::Float(f1 / f2)),
-            _ => Err(anyhow!("Invalid operation arguments!")),
+            (Object::Float(f1), Object::Float(f2)) => f1
+                .checked_div(f2)
+                .map(Object::Float)
+                .ok_or_else(|| RuntimeError::new("Division by zero!")),
+            _ => Err(RuntimeError::new("Invalid operation arguments!")),
         }
     }
 }
@@ -0,0 +1,179 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use ariadne::{Cache, Config, IndexType, Report, ReportBuilder, ReportKind, Source};
+
+use super::spanned::{FileId, Span};
+
+/// Where an offset is in its file, lines and columns count from 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    /// In bytes from the start of the line
+    pub column: usize,
+    /// In UTF-16 code units from the start of the line, which is what LSP counts
+    pub utf16_column: usize,
+}
+
+/// The text of a file, with where each of its lines start
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    path: PathBuf,
+    text: String,
+    /// The offset of the first byte of every line
+    lines: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(path: impl Into<PathBuf>, text: impl Into<String>) -> Self {
+        let text = text.into();
+        let lines = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        SourceFile {
+            path: path.into(),
+            text,
+            lines,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// The line and columns of `offset`, offsets past the end are clamped to it
+    pub fn location(&self, offset: usize) -> Location {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.lines.partition_point(|start| *start <= offset) - 1;
+        let start = self.lines[line];
+        Location {
+            line,
+            column: offset - start,
+            utf16_column: self.text[start..offset].encode_utf16().count(),
+        }
+    }
+
+    /// The offset of a line and UTF-16 column, columns past the end of the line are clamped to it
+    pub fn offset(&self, line: usize, utf16_column: usize) -> Option<usize> {
+        let start = *self.lines.get(line)?;
+        let end = self
+            .lines
+            .get(line + 1)
+            .map_or(self.text.len(), |next| next - 1);
+        let mut units = 0;
+        for (i, c) in self.text[start..end].char_indices() {
+            if units >= utf16_column {
+                return Some(start + i);
+            }
+            units += c.len_utf16();
+        }
+        Some(end)
+    }
+}
+
+/// Every file a program is compiled from, spans find their file by its `FileId`
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: HashMap<FileId, SourceFile>,
+    next: u32,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A map of just one file, which gets `FileId(0)`
+    pub fn single(path: impl Into<PathBuf>, text: impl Into<String>) -> Self {
+        let mut sources = Self::new();
+        sources.add(path, text);
+        sources
+    }
+
+    /// Adds a file and returns the id spans into it use
+    pub fn add(&mut self, path: impl Into<PathBuf>, text: impl Into<String>) -> FileId {
+        let file = FileId(self.next);
+        self.insert(file, SourceFile::new(path, text));
+        file
+    }
+
+    /// Adds a file under the id another map gave it
+    pub fn insert(&mut self, file: FileId, source: SourceFile) {
+        self.next = self.next.max(file.0 + 1);
+        self.files.insert(file, source);
+    }
+
+    pub fn get(&self, file: FileId) -> Option<&SourceFile> {
+        self.files.get(&file)
+    }
+
+    /// The id of the file at `path`, if it's been added
+    pub fn find(&self, path: &Path) -> Option<FileId> {
+        self.files
+            .iter()
+            .find(|(_, source)| source.path == path)
+            .map(|(file, _)| *file)
+    }
+
+    /// The text `span` covers, `None` if its file isn't in the map
+    pub fn slice(&self, span: Span) -> Option<&str> {
+        self.get(span.file)?.text.get(span.into_range())
+    }
+
+    /// Where `span` starts, `None` if its file isn't in the map
+    pub fn location(&self, span: Span) -> Option<Location> {
+        Some(self.get(span.file)?.location(span.start))
+    }
+
+    /// The files for `ariadne` to print reports from, named by their paths
+    pub fn cache(&self) -> impl Cache<FileId> + '_ {
+        ReportCache {
+            sources: self,
+            loaded: HashMap::new(),
+        }
+    }
+}
+
+/// Starts an `ariadne` report at `span`, telling it spans count bytes rather than chars
+pub fn report(kind: ReportKind<'_>, span: Span) -> ReportBuilder<'_, Span> {
+    Report::build(kind, span.file, span.start)
+        .with_config(Config::default().with_index_type(IndexType::Byte))
+}
+
+struct ReportCache<'a> {
+    sources: &'a SourceMap,
+    loaded: HashMap<FileId, Source<&'a str>>,
+}
+
+impl<'a> Cache<FileId> for ReportCache<'a> {
+    type Storage = &'a str;
+
+    fn fetch(&mut self, id: &FileId) -> Result<&Source<&'a str>, Box<dyn fmt::Debug + '_>> {
+        let Some(file) = self.sources.get(*id) else {
+            return Err(Box::new(format!("Unknown file {id}")));
+        };
+        Ok(self
+            .loaded
+            .entry(*id)
+            .or_insert_with(|| Source::from(file.text.as_str())))
+    }
+
+    fn display<'b>(&self, id: &'b FileId) -> Option<Box<dyn fmt::Display + 'b>> {
+        let path = self.sources.get(*id)?.path.display().to_string();
+        Some(Box::new(path))
+    }
+}
@@ -1,9 +1,93 @@
-use std::hash::Hash;
+use std::{fmt, hash::Hash, ops::Range};
 
-use chumsky::span::{SimpleSpan, Span};
+/// Which file a span points into, the file a program is compiled from is `FileId(0)` and
+/// the modules it imports count up from there
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(pub u32);
+
+impl fmt::Display for FileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Byte offsets into the text of a file
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub file: FileId,
+}
+
+impl Span {
+    pub fn new(file: FileId, range: Range<usize>) -> Self {
+        Span {
+            start: range.start,
+            end: range.end,
+            file,
+        }
+    }
+
+    pub fn into_range(self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// A span over `range` in the same file
+    pub fn with_range(self, range: Range<usize>) -> Self {
+        Span::new(self.file, range)
+    }
+}
+
+/// Spans made from a bare range point into the first file
+impl From<Range<usize>> for Span {
+    fn from(range: Range<usize>) -> Self {
+        Span::new(FileId::default(), range)
+    }
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.file {
+            FileId(0) => write!(f, "{}..{}", self.start, self.end),
+            file => write!(f, "{file}:{}..{}", self.start, self.end),
+        }
+    }
+}
+
+impl chumsky::span::Span for Span {
+    type Context = FileId;
+    type Offset = usize;
+
+    fn new(file: FileId, range: Range<usize>) -> Self {
+        Span::new(file, range)
+    }
+    fn context(&self) -> FileId {
+        self.file
+    }
+    fn start(&self) -> usize {
+        self.start
+    }
+    fn end(&self) -> usize {
+        self.end
+    }
+}
+
+impl ariadne::Span for Span {
+    type SourceId = FileId;
+
+    fn source(&self) -> &FileId {
+        &self.file
+    }
+    fn start(&self) -> usize {
+        self.start
+    }
+    fn end(&self) -> usize {
+        self.end
+    }
+}
 
 #[derive(Debug, PartialEq, Eq)]
-pub struct Spanned<T>(pub T, pub SimpleSpan);
+pub struct Spanned<T>(pub T, pub Span);
 
 impl<T: Copy> Copy for Spanned<T> {}
 
@@ -46,7 +130,7 @@ impl<T> Spanned<T> {
     }
 }
 
-pub fn concat_span<S: Span<Offset = usize>>(s1: S, s2: S) -> S {
+pub fn concat_span<S: chumsky::span::Span<Offset = usize>>(s1: S, s2: S) -> S {
     S::new(
         s1.context(),
         (usize::min(s1.start(), s2.start()))..(usize::max(s1.end(), s2.end())),
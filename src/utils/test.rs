@@ -0,0 +1,62 @@
+use rust_decimal::Decimal;
+
+use std::path::Path;
+
+use super::{
+    format::format_template,
+    interner::INTERNER,
+    object::Object,
+    source_map::{Location, SourceMap},
+    spanned::{FileId, Span},
+};
+
+#[test]
+fn format_specs() {
+    let name = Object::String(INTERNER.get_or_intern("Bob"));
+    let args = [
+        Object::Float(Decimal::new(31415, 4)),
+        Object::Integer(42),
+        name,
+        name,
+    ];
+    assert_eq!(
+        "3.14|   42|\"Bob\"|*Bob*|{}",
+        format_template("{:.2}|{:>5}|{:?}|{:*^5}|{{}}", &args).unwrap()
+    );
+}
+
+#[test]
+fn format_argument_mismatch() {
+    assert!(format_template("{} {}", &[Object::Integer(1)]).is_err());
+    assert!(format_template("{}", &[Object::Integer(1), Object::Integer(2)]).is_err());
+}
+
+#[test]
+fn source_map_locations() {
+    let mut sources = SourceMap::new();
+    let main = sources.add("main.sn", "let a = 1;\nlet é = \"😀\"; b\n");
+    let lib = sources.add("lib.sn", "pub let x = 2;");
+    assert_eq!((FileId(0), FileId(1)), (main, lib));
+    assert_eq!(Some(lib), sources.find(Path::new("lib.sn")));
+
+    let file = sources.get(main).unwrap();
+    assert_eq!(3, file.line_count());
+    let b = file.text().find('b').unwrap();
+    assert_eq!(
+        Location {
+            line: 1,
+            column: 17,
+            utf16_column: 14,
+        },
+        file.location(b)
+    );
+    assert_eq!(Some(b), file.offset(1, 14));
+    // Past the end of a line is its end
+    assert_eq!(Some(10), file.offset(0, 40));
+    assert_eq!(None, file.offset(5, 0));
+
+    let x = Span::new(lib, 8..9);
+    assert_eq!(Some("x"), sources.slice(x));
+    assert_eq!(Some(8), sources.location(x).map(|l| l.column));
+    assert_eq!(None, sources.slice(Span::new(FileId(7), 0..1)));
+}
@@ -1,2 +1,16 @@
+/// A type annotation, `int?` is the nullable version of `int`
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct Type(pub u64);
+pub struct Type {
+    pub name: u64,
+    /// Written with a trailing `?`, only nullable types can hold `null`
+    pub nullable: bool,
+}
+
+impl Type {
+    pub fn new(name: u64) -> Self {
+        Type {
+            name,
+            nullable: false,
+        }
+    }
+}
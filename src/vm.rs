@@ -0,0 +1,5 @@
+pub mod chunk;
+pub mod compiler;
+#[cfg(test)]
+mod test;
+pub mod vm;
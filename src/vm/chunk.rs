@@ -0,0 +1,178 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{
+        misc::{AssignOp, BinaryOp, ParamType, UnaryOp},
+        stmt::ExternDeclaration,
+    },
+    interperter::interperter::ResourceHooks,
+    utils::object::Object,
+};
+
+/// Which statement a condition belongs to, they fail with different messages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    If,
+    While,
+}
+
+impl Condition {
+    pub fn message(self) -> &'static str {
+        match self {
+            Condition::If => "Not a Bool!",
+            Condition::While => "Not a bool!",
+        }
+    }
+}
+
+/// One vm instruction, jump targets are indices into the same chunk
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instr {
+    /// Pushes a value from the constant pool
+    Constant(u32),
+    Null,
+    Pop,
+    Unary(UnaryOp),
+    Binary(BinaryOp),
+    /// Globals are slots of the interpreter's globals, handed out when compiling
+    LoadGlobal(u32),
+    /// Fails if the global is already declared, checked before the value is evaluated
+    CheckGlobal(u32),
+    DefineGlobal {
+        slot: u32,
+        mutable: bool,
+    },
+    AssignGlobal {
+        slot: u32,
+        op: AssignOp,
+    },
+    /// Falls back to the global in slot `global` while the local hasn't been declared yet
+    LoadLocal {
+        slot: u32,
+        global: u32,
+    },
+    CheckLocal(u32),
+    DefineLocal {
+        slot: u32,
+        mutable: bool,
+    },
+    AssignLocal {
+        slot: u32,
+        global: u32,
+        op: AssignOp,
+    },
+    /// Reads the caller's variable a `ref` or `out` parameter stands for
+    LoadReference(u32),
+    AssignReference {
+        slot: u32,
+        op: AssignOp,
+    },
+    Jump(u32),
+    /// Pops the condition
+    JumpIfFalse(u32),
+    /// Pops the condition
+    JumpIfTrue(u32),
+    /// Leaves the value on the stack, for `?.`
+    JumpIfNull(u32),
+    /// Leaves the value on the stack, for `??`
+    JumpIfNotNull(u32),
+    /// Fails unless the value on top of the stack is a bool
+    ExpectBool(Condition),
+    /// Jumps unless the value on top of the stack equals the constant
+    Match {
+        constant: u32,
+        target: u32,
+    },
+    /// Calls a variable holding a function if there is one, `local` is its slot and
+    /// `global` the slot of the global, and the declared function or native of the global's
+    /// name otherwise. `site` indexes the chunk's call sites
+    Call {
+        global: u32,
+        local: Option<u32>,
+        site: u32,
+    },
+    /// Calls the function value below the arguments of the call site
+    CallValue(u32),
+    /// Replaces the arguments of an interpolated string with the string, `template` is a
+    /// constant
+    Interpolate {
+        template: u32,
+        arguments: u32,
+    },
+    /// Pushes a function value for one of the chunk's lambdas, copying the locals it captures
+    Closure(u32),
+    /// Declares one of the chunk's functions
+    Function(u32),
+    /// Looks up one of the chunk's `extern` functions
+    Extern(u32),
+    /// Pops the value of a `with` into its variable, `local` is its slot and it's the global
+    /// in slot `global` otherwise, then calls the enter hook of the function `producer`
+    Enter {
+        producer: Option<u64>,
+        local: Option<u32>,
+        global: u32,
+    },
+    /// Undeclares the variable of the innermost `with` and calls its exit hook
+    Exit,
+    /// Pops and prints a value for the `test` statement
+    Test,
+    Error,
+    /// Queues the statement after it to run when the function returns and jumps past it
+    Defer(u32),
+    /// Ends a deferred statement, going back to the `Return` that ran it
+    EndDefer,
+    /// Pops the return value and leaves the function
+    Return,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<Instr>,
+    pub constants: Vec<Object>,
+    pub functions: Vec<Rc<Function>>,
+    pub externs: Vec<ExternDeclaration>,
+    pub calls: Vec<CallSite>,
+}
+
+/// The arguments of a call, the ones that are variables can be passed to `ref` and `out`
+/// parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallSite {
+    pub arguments: u32,
+    pub places: Vec<Option<Place>>,
+}
+
+/// The variable an argument reads
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Place {
+    Global(u32),
+    /// Falls back to the global in slot `global` while the local hasn't been declared yet
+    Local {
+        slot: u32,
+        global: u32,
+    },
+    Reference(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Parameter {
+    pub mode: ParamType,
+    pub mutable: bool,
+}
+
+/// A compiled function, the script itself is one without parameters or slots
+#[derive(Debug, Clone, Default)]
+pub struct Function {
+    pub name: u64,
+    /// Parameters take the first slots
+    pub parameters: Vec<Parameter>,
+    /// How many local slots a call needs
+    pub slots: usize,
+    /// For lambdas, the slot of each captured local in the enclosing frame and the slot it's copied into
+    pub captures: Vec<(u32, u32)>,
+    pub chunk: Chunk,
+    /// Marked `@memoize`, calls by name remember what they returned
+    pub memoize: bool,
+    /// Set for functions whose results can be used with `with`
+    pub hooks: Option<ResourceHooks>,
+}
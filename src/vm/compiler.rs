@@ -0,0 +1,447 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::{
+        arena::{Arena, ExprId, StmtId},
+        expr::Expr,
+        misc::{BinaryOp, DeclarationType, FuncParameter},
+        pattern::Pattern,
+        stmt::{Program, Statement},
+    },
+    interperter::{
+        interperter::{producer, Globals, ResourceHooks},
+        resolver::{resolve, Code, Slot},
+    },
+    lexer::lexer::convert_to_hash,
+    utils::object::Object,
+};
+
+use super::chunk::{CallSite, Chunk, Condition, Function, Instr, Parameter, Place};
+
+/// Compiles a script, the value of a trailing expression statement is what running it returns.
+/// Globals get slots in `globals`, the ones of the interpreter the script is run against
+pub(crate) fn compile(program: &Program, globals: &mut Globals) -> Rc<Function> {
+    // Frames and globals are laid out like the tree-walker's
+    let code = resolve(program, globals);
+    let mut compiler = Compiler::new(&code);
+    compiler.body(&program.statements);
+    Rc::new(Function {
+        name: convert_to_hash(&"script"),
+        chunk: compiler.chunk,
+        ..Function::default()
+    })
+}
+
+struct Compiler<'a> {
+    arena: &'a Arena,
+    code: &'a Code,
+    chunk: Chunk,
+    constants: HashMap<Object, u32>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(code: &'a Code) -> Self {
+        Compiler {
+            arena: &code.arena,
+            code,
+            chunk: Chunk::default(),
+            constants: HashMap::new(),
+        }
+    }
+
+    fn emit(&mut self, instr: Instr) -> usize {
+        self.chunk.code.push(instr);
+        self.chunk.code.len() - 1
+    }
+
+    /// Where the next instruction goes
+    fn here(&self) -> u32 {
+        self.chunk.code.len() as u32
+    }
+
+    /// Points the jump at `jump` to the next instruction
+    fn patch(&mut self, jump: usize) {
+        let target = self.here();
+        match &mut self.chunk.code[jump] {
+            Instr::Jump(t)
+            | Instr::JumpIfFalse(t)
+            | Instr::JumpIfTrue(t)
+            | Instr::JumpIfNull(t)
+            | Instr::JumpIfNotNull(t)
+            | Instr::Defer(t) => *t = target,
+            Instr::Match { target: t, .. } => *t = target,
+            instr => unreachable!("{instr:?} isn't a jump"),
+        }
+    }
+
+    fn constant(&mut self, value: Object) -> u32 {
+        *self.constants.entry(value).or_insert_with(|| {
+            self.chunk.constants.push(value);
+            self.chunk.constants.len() as u32 - 1
+        })
+    }
+
+    /// The slot of a local, the slots of references hold a copy of the argument
+    fn local(slot: Slot) -> Option<u32> {
+        match slot {
+            Slot::Local { slot, .. } | Slot::Reference { slot } => Some(slot),
+            Slot::Global(_) => None,
+        }
+    }
+
+    /// Adds a call site for `arguments` and returns its index
+    fn call_site(&mut self, arguments: &[ExprId]) -> u32 {
+        let places = arguments
+            .iter()
+            .map(|arg| match &self.arena[*arg].0 {
+                Expr::Variable { .. } => Some(match self.code.expr_slot(*arg) {
+                    Slot::Global(global) => Place::Global(global),
+                    Slot::Local { slot, global } => Place::Local { slot, global },
+                    Slot::Reference { slot } => Place::Reference(slot),
+                }),
+                _ => None,
+            })
+            .collect();
+        self.chunk.calls.push(CallSite {
+            arguments: arguments.len() as u32,
+            places,
+        });
+        self.chunk.calls.len() as u32 - 1
+    }
+
+    /// Adds `function` to the chunk and returns its index
+    fn function(&mut self, function: Function) -> u32 {
+        self.chunk.functions.push(Rc::new(function));
+        self.chunk.functions.len() as u32 - 1
+    }
+
+    //----------------------------------------------------------------
+    //-Statements-----------------------------------------------------
+    //----------------------------------------------------------------
+
+    /// A trailing expression statement is returned, anything else returns `null`
+    fn body(&mut self, statements: &[StmtId]) {
+        let arena = self.arena;
+        match statements
+            .split_last()
+            .map(|(tail, rest)| (&arena[*tail].0, rest))
+        {
+            Some((Statement::Expression { expr }, rest)) => {
+                self.statements(rest);
+                self.expr(*expr);
+            }
+            _ => {
+                self.statements(statements);
+                self.emit(Instr::Null);
+            }
+        }
+        self.emit(Instr::Return);
+    }
+
+    fn statements(&mut self, statements: &[StmtId]) {
+        for stmt in statements {
+            self.statement(*stmt);
+        }
+    }
+
+    fn statement(&mut self, id: StmtId) {
+        let arena = self.arena;
+        match &arena[id].0 {
+            Statement::Error => {
+                self.emit(Instr::Error);
+            }
+            Statement::Empty | Statement::TestDeclaration { .. } | Statement::Use { .. } => (),
+            Statement::Test(expr) => {
+                self.expr(*expr);
+                self.emit(Instr::Test);
+            }
+            Statement::Assign(assign) => {
+                self.expr(assign.value);
+                let op = assign.operator.0;
+                match self.code.stmt_slot(id) {
+                    Slot::Local { slot, global } => {
+                        self.emit(Instr::AssignLocal { slot, global, op })
+                    }
+                    Slot::Reference { slot } => self.emit(Instr::AssignReference { slot, op }),
+                    Slot::Global(slot) => self.emit(Instr::AssignGlobal { slot, op }),
+                };
+            }
+            Statement::Block { statments } => self.statements(statments),
+            Statement::Expression { expr } => {
+                self.expr(*expr);
+                self.emit(Instr::Pop);
+            }
+            Statement::Declaration {
+                declaration_type,
+                value,
+                ..
+            } => {
+                let mutable = matches!(declaration_type, DeclarationType::Mutable);
+                match self.code.stmt_slot(id) {
+                    Slot::Global(slot) => {
+                        self.emit(Instr::CheckGlobal(slot));
+                        self.expr(*value);
+                        self.emit(Instr::DefineGlobal { slot, mutable });
+                    }
+                    Slot::Local { slot, .. } | Slot::Reference { slot } => {
+                        self.emit(Instr::CheckLocal(slot));
+                        self.expr(*value);
+                        self.emit(Instr::DefineLocal { slot, mutable });
+                    }
+                }
+            }
+            Statement::FuncDeclaration {
+                name,
+                parameters,
+                statments,
+                ..
+            } => {
+                let parameters = parameters.as_deref().unwrap_or_default();
+                let slots = self.code.frame_size(id);
+                let function = Function {
+                    memoize: self.arena[id].0.attribute("memoize").is_some(),
+                    hooks: ResourceHooks::of(self.arena[id].0.attributes()),
+                    ..function(self.code, name.0, parameters, statments, slots)
+                };
+                let function = self.function(function);
+                self.emit(Instr::Function(function));
+            }
+            Statement::Extern(declaration) => {
+                self.chunk.externs.push(declaration.clone());
+                let index = self.chunk.externs.len() as u32 - 1;
+                self.emit(Instr::Extern(index));
+            }
+            Statement::IfStmt {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(*condition);
+                self.emit(Instr::ExpectBool(Condition::If));
+                let to_else = self.emit(Instr::JumpIfFalse(0));
+                self.statement(*then_branch);
+                match else_branch {
+                    Some(else_branch) => {
+                        let to_end = self.emit(Instr::Jump(0));
+                        self.patch(to_else);
+                        self.statement(*else_branch);
+                        self.patch(to_end);
+                    }
+                    None => self.patch(to_else),
+                }
+            }
+            Statement::MatchStmt(match_stmt) => {
+                self.expr(match_stmt.predicate);
+                let mut to_end = vec![];
+                for (pattern, stmt) in &match_stmt.then_branches.0 {
+                    // Only literal patterns can match so far
+                    let Pattern::Literal(literal) = pattern else {
+                        continue;
+                    };
+                    let constant = self.constant(literal.value.0);
+                    let to_next = self.emit(Instr::Match {
+                        constant,
+                        target: 0,
+                    });
+                    self.emit(Instr::Pop);
+                    self.statement(*stmt);
+                    to_end.push(self.emit(Instr::Jump(0)));
+                    self.patch(to_next);
+                }
+                self.emit(Instr::Pop);
+                to_end.into_iter().for_each(|jump| self.patch(jump));
+            }
+            Statement::WhileStmt {
+                condition,
+                then_branch,
+            } => {
+                // The first check is strict, later ones only convert to a bool like the tree-walker
+                self.expr(*condition);
+                self.emit(Instr::ExpectBool(Condition::While));
+                let to_end = self.emit(Instr::JumpIfFalse(0));
+                let start = self.here();
+                self.statement(*then_branch);
+                self.expr(*condition);
+                self.emit(Instr::JumpIfTrue(start));
+                self.patch(to_end);
+            }
+            Statement::Defer { defered_statment } => {
+                let skip = self.emit(Instr::Defer(0));
+                self.statement(*defered_statment);
+                self.emit(Instr::EndDefer);
+                self.patch(skip);
+            }
+            Statement::With {
+                value, statments, ..
+            } => {
+                let (local, global) = match self.code.stmt_slot(id) {
+                    Slot::Global(global) => (None, global),
+                    Slot::Local { slot, global } => (Some(slot), global),
+                    Slot::Reference { .. } => unreachable!("`with` declares a variable of its own"),
+                };
+                self.emit(match local {
+                    Some(slot) => Instr::CheckLocal(slot),
+                    None => Instr::CheckGlobal(global),
+                });
+                self.expr(*value);
+                self.emit(Instr::Enter {
+                    producer: producer(self.arena, *value),
+                    local,
+                    global,
+                });
+                self.statements(statments);
+                self.emit(Instr::Exit);
+            }
+        }
+    }
+
+    //----------------------------------------------------------------
+    //-Expressions----------------------------------------------------
+    //----------------------------------------------------------------
+
+    fn expr(&mut self, id: ExprId) {
+        let arena = self.arena;
+        match &arena[id].0 {
+            Expr::Literal(literal) => {
+                let constant = self.constant(literal.value.0);
+                self.emit(Instr::Constant(constant));
+            }
+            Expr::Variable { .. } => {
+                match self.code.expr_slot(id) {
+                    Slot::Local { slot, global } => self.emit(Instr::LoadLocal { slot, global }),
+                    Slot::Reference { slot } => self.emit(Instr::LoadReference(slot)),
+                    Slot::Global(slot) => self.emit(Instr::LoadGlobal(slot)),
+                };
+            }
+            Expr::Unary(unary) => {
+                self.expr(unary.right);
+                self.emit(Instr::Unary(unary.operator.0));
+            }
+            Expr::Binary(binary) if binary.operator.0 == BinaryOp::Coalesce => {
+                self.expr(binary.left);
+                let to_end = self.emit(Instr::JumpIfNotNull(0));
+                self.emit(Instr::Pop);
+                self.expr(binary.right);
+                self.patch(to_end);
+            }
+            Expr::Binary(binary) => {
+                self.expr(binary.left);
+                self.expr(binary.right);
+                self.emit(Instr::Binary(binary.operator.0));
+            }
+            Expr::TenaryIfStmt {
+                condition,
+                value,
+                else_value,
+            } => {
+                self.expr(*condition);
+                let to_else = self.emit(Instr::JumpIfFalse(0));
+                self.expr(*value);
+                let to_end = self.emit(Instr::Jump(0));
+                self.patch(to_else);
+                self.expr(*else_value);
+                self.patch(to_end);
+            }
+            Expr::FunctionCall {
+                callee,
+                arguments,
+                safe,
+            } => {
+                let arguments = arguments.as_deref().unwrap_or_default();
+                // References are called through their value
+                let named = match &arena[*callee].0 {
+                    Expr::Variable { .. } => match self.code.expr_slot(*callee) {
+                        Slot::Global(global) | Slot::Local { global, .. } => Some(global),
+                        Slot::Reference { .. } => None,
+                    },
+                    _ => None,
+                };
+                if named.is_none() {
+                    self.expr(*callee);
+                }
+                let mut to_null = None;
+                for (i, argument) in arguments.iter().enumerate() {
+                    self.expr(*argument);
+                    if *safe && i == 0 {
+                        to_null = Some(self.emit(Instr::JumpIfNull(0)));
+                    }
+                }
+                let site = self.call_site(arguments);
+                match named {
+                    Some(global) => self.emit(Instr::Call {
+                        global,
+                        local: Self::local(self.code.expr_slot(*callee)),
+                        site,
+                    }),
+                    None => self.emit(Instr::CallValue(site)),
+                };
+                // A null receiver is left as the result, under it is the callee if it was pushed
+                if let Some(to_null) = to_null {
+                    if named.is_some() {
+                        self.patch(to_null);
+                    } else {
+                        let to_end = self.emit(Instr::Jump(0));
+                        self.patch(to_null);
+                        self.emit(Instr::Pop);
+                        self.emit(Instr::Pop);
+                        self.emit(Instr::Null);
+                        self.patch(to_end);
+                    }
+                }
+            }
+            Expr::Interpolation {
+                template,
+                arguments,
+            } => {
+                for argument in arguments {
+                    self.expr(*argument);
+                }
+                let template = self.constant(Object::String(*template));
+                self.emit(Instr::Interpolate {
+                    template,
+                    arguments: arguments.len() as u32,
+                });
+            }
+            Expr::Lambda(lambda) => {
+                let parameters = lambda.parameters.as_deref().unwrap_or_default();
+                let layout = self.code.lambda(id);
+                let mut lambda = function(
+                    self.code,
+                    convert_to_hash(&"lambda"),
+                    parameters,
+                    &lambda.statments,
+                    layout.frame_size,
+                );
+                lambda.captures = layout.captures.clone();
+                let function = self.function(lambda);
+                self.emit(Instr::Closure(function));
+            }
+        }
+    }
+}
+
+fn function(
+    code: &Code,
+    name: u64,
+    parameters: &[FuncParameter],
+    statments: &[StmtId],
+    slots: usize,
+) -> Function {
+    let mut compiler = Compiler::new(code);
+    compiler.body(statments);
+    Function {
+        name,
+        parameters: parameters
+            .iter()
+            .map(|param| Parameter {
+                mode: param.param_type.0,
+                mutable: param.is_mutable(),
+            })
+            .collect(),
+        slots,
+        captures: vec![],
+        chunk: compiler.chunk,
+        memoize: false,
+        hooks: None,
+    }
+}
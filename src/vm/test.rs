@@ -0,0 +1,150 @@
+use crate::{
+    engine::engine::{Backend, Engine},
+    interperter::interperter::Globals,
+    lexer::lexer::convert_to_hash,
+    utils::object::Object,
+};
+
+use super::{chunk::Instr, compiler::compile};
+
+/// What running `source` did, its value or error and everything it printed
+fn outcome(source: &str, backend: Backend) -> (Result<Object, String>, String) {
+    let mut engine = Engine::new();
+    engine.set_backend(backend).capture_output();
+    let value = engine
+        .eval::<Object>(source)
+        .map_err(|error| error.to_string());
+    (value, engine.take_output())
+}
+
+/// Both engines have to agree on every one of these
+const PROGRAMS: &[&str] = &[
+    "1 + 2 * 3 - 4 / 2",
+    "1 + 2i64 + 0.5",
+    "\"a\" + \"b\" == \"ab\" and not false",
+    "let x = 4; \"x is {x:>3}!\"",
+    "format(r\"{} and {:?}\", 1, \"a\")",
+    "fn format(val s: string), string { \"shadowed\" } let x = 4; format(\"{x}\") + \" {x}\"",
+    "var i = 0; var total = 0; while i < 100 { { total += i; i += 1; } } total",
+    "var i = 10; while i > 0 { i -= 3 } i",
+    "let x = 5; if x > 3 { println(\"big\") } else { println(\"small\") }",
+    "let x = 1; if x > 3 { println(\"big\") } x",
+    "if 1 { println(1) }",
+    "while 1 { println(1) }",
+    "var n = 3; while n > 0 { { n -= 1; println(n); } }",
+    "var n = 2; while n { n -= 1 }",
+    "let x = if true : 1 else 2; x",
+    "match 2 { 1 => println(\"one\"), 2 => println(\"two\"), _ => println(\"other\"), }",
+    "match 5 { 1 => println(\"one\"), }",
+    "fn fib(val n: int), int { var result = n; if n > 1 { { let a = fib(n - 1); let b = fib(n - 2); result = a + b; } } result } fib(15)",
+    "fn fact(val n: int), int { var result = 1; var i = n; while i > 1 { { result *= i; i -= 1; } } result } fact(10)",
+    "fn add(val a: int, val b: int), int { a + b } let x = add(1, 2); x",
+    "fn add(val a: int, val b: int), int { a + b } add(1)",
+    "fn bump(val a: int), int { a += 1; a } bump(1)",
+    "fn bump(val a: mut int), int { a += 1; a } bump(1)",
+    "var g = 1; fn touch() { g += 1; } touch(); touch(); g",
+    "let g = 1; fn shadow() { println(g); let g = 2; println(g); } shadow(); g",
+    "fn outer() { fn inner(), int { 7 } } outer(); inner()",
+    "fn twice() { let x = 1; } twice(); twice(); x",
+    "fn loop_decl() { var i = 0; while i < 2 { { let y = i; i += 1; } } } loop_decl()",
+    "fn len(val s: string), int { 42 } len(\"abc\")",
+    "println(\"a\", 1, true); print(\"b\"); test 1 + 1; to_string(3)",
+    "let x = 1; x = 2;",
+    "let x = 1; let x = println(\"evaluated\");",
+    "y += 1;",
+    "missing()",
+    "-\"a\"",
+    "2147483647 + 1",
+    "test \"skipped\" { println(1); } 3",
+    "fn make_adder(val n: int), function { fn(val x: int) -> x + n } let add = make_adder(1); add(2) + make_adder(10)(5)",
+    "fn apply(val f: function, val x: int), int { f(x) } apply(fn(val x: int) -> x * 2, 21)",
+    "let double = fn(val x: int), int { let y = x * 2; y } println(double(4), double); type_of(double)",
+    "let l = len; l(\"abc\") + len(\"de\")",
+    "fn counter(), function { var count = 0; fn() { count += 1; } } counter()()",
+    "fn outer(), function { let inner = fn(), function { fn() -> later }; let later = 1; inner() } outer()()",
+    "let x = 1; x(2)",
+    "(fn(val a: int, val b: int) -> a - b)(1)",
+    "fn swap(ref a: mut int, ref b: mut int) { let t = a; a = b; b = t; } var x = 1; var y = 2; swap(x, y); println(x, y);",
+    "fn divmod(val a: int, val b: int, out q: int, out r: int) { q = a / b; r = a - q * b; } var q = 0; var r = 0; divmod(17, 5, q, r); println(q, r);",
+    "var g = 1; fn bump(ref a: mut int) { a += 1; println(g); } bump(g); g",
+    "var x = 1; fn inner(ref a: mut int) { a *= 10; } fn outer(ref b: mut int) { inner(b); let f = fn() -> b; println(f()); } outer(x); x",
+    "fn f(ref a: mut int) { a = 1; } f(3)",
+    "fn f(ref a: mut int) { a = 1; } f(len)",
+    "let x = 1; fn f(ref a: mut int) { a = 2; } f(x)",
+    "var x = 1; fn f(ref a: int) { a = 2; } f(x)",
+    "var x = 1; fn f(ref a: int) { println(a); } fn g(ref b: int) { f(b); } g(x)",
+    "var x = 1; fn f(ref a: mut int) { a = 2; } fn g(ref b: int) { f(b); } g(x)",
+    "var x = 1; fn f(val a: const int) { a = 2; } f(x)",
+    "var x = 1; fn f(in a: int) { a += 1; } f(x)",
+    "var x = 1; fn f(out a: int) { } f(x)",
+    "var x = 1; fn f(out a: int) { let b = a; a = 2; } f(x)",
+    "var x = 1; fn f(out a: int) { a += 2; } f(x)",
+    "var x = 1; fn f(out a: int) { a = 2; let a = 3; } f(x)",
+    "var x = 1; fn f(ref a: mut int, val b: int) { a = b; } f(x)",
+    "var x = 1; let f = fn(out a: int) { a = 7; }; f(x); x",
+    "fn f(), int { defer println(\"last\"); defer { println(\"first\"); } println(\"body\"); 1 } f() + f()",
+    "var i = 0; while i < 2 { { defer println(\"deferred\", i); i += 1; } } println(\"end\"); i",
+    "fn f() { defer println(\"skipped\"); missing(); } f()",
+    "var x = 1; fn f(out a: int) { defer a = 5; } f(x); x",
+];
+
+#[test]
+fn engines_agree() {
+    for source in PROGRAMS {
+        assert_eq!(
+            outcome(source, Backend::Tree),
+            outcome(source, Backend::Vm),
+            "engines disagree on `{source}`"
+        );
+    }
+}
+
+#[test]
+fn vm_keeps_state_between_runs() {
+    let mut engine = Engine::new();
+    engine.set_backend(Backend::Vm);
+    engine
+        .run_source("var count = 0; fn next(), int { count += 1; count }")
+        .unwrap();
+    engine.eval::<i32>("next()").unwrap();
+    assert_eq!(2, engine.eval::<i32>("next()").unwrap());
+    assert_eq!(Some(2), engine.get_global::<i32>("count").unwrap());
+}
+
+#[test]
+fn compiles_locals_to_slots() {
+    let program = Engine::new()
+        .compile("fn f(val a: int), int { let b = a; b }")
+        .unwrap();
+    let script = compile(&program, &mut Globals::default());
+    let function = &script.chunk.functions[0];
+    assert_eq!(2, function.slots);
+    assert!(function
+        .chunk
+        .code
+        .iter()
+        .all(|instr| !matches!(instr, Instr::LoadGlobal(_))));
+    assert_eq!(Some(&Instr::Return), function.chunk.code.last());
+}
+
+#[test]
+fn compiles_globals_to_slots() {
+    let program = Engine::new()
+        .compile("var seen = 0;\nvar total = 0;\ntotal += seen;\ntotal")
+        .unwrap();
+    let mut globals = Globals::default();
+    globals.slot(convert_to_hash(&"earlier"));
+    let script = compile(&program, &mut globals);
+    // Slots follow the ones the globals already handed out
+    assert_eq!(
+        vec![Instr::LoadGlobal(1), Instr::LoadGlobal(2)],
+        script
+            .chunk
+            .code
+            .iter()
+            .filter(|instr| matches!(instr, Instr::LoadGlobal(_)))
+            .copied()
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(2, globals.slot(convert_to_hash(&"total")));
+}
@@ -0,0 +1,639 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    ast::misc::{AssignOp, ParamType},
+    interperter::interperter::{
+        assign_op, binary_op, interpolate, unary_op, Callable, Globals, Interperter,
+    },
+    utils::{
+        error::{RuntimeError, RuntimeResult},
+        object::Object,
+    },
+};
+
+use super::chunk::{CallSite, Chunk, Function, Instr, Parameter, Place};
+
+type Local = Option<(Object, bool)>;
+
+/// Where a caller continues once the function it called returns
+struct Frame {
+    function: Rc<Function>,
+    ip: usize,
+    base: usize,
+    /// Indexed by slot, set for `ref` and `out` parameters
+    references: Vec<Option<Reference>>,
+    /// The arguments of a `@memoize` call, to remember the result under
+    memo: Option<Vec<Object>>,
+    /// Where the statements `defer` queued start, the last one runs first
+    defers: Vec<usize>,
+    /// The `Return` a deferred statement goes back to
+    resume: usize,
+}
+
+/// The variable a reference stands for, locals are indices into the whole `locals` stack
+#[derive(Clone, Copy)]
+enum Target {
+    Global(u32),
+    Local(usize),
+}
+
+/// A `ref` or `out` parameter of the function being run
+#[derive(Clone, Copy)]
+struct Reference {
+    target: Target,
+    mutable: bool,
+    /// `out` parameters can't be read until they're assigned and have to be before returning
+    output: bool,
+    assigned: bool,
+}
+
+impl Reference {
+    fn check_read(&self) -> RuntimeResult<()> {
+        match self.output && !self.assigned {
+            true => Err(RuntimeError::new(
+                "Out parameter read before it was assigned!",
+            )),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Everything a run moves between as functions are called and return
+struct Machine {
+    stack: Vec<Object>,
+    locals: Vec<Local>,
+    /// The callers of `current`, innermost last
+    frames: Vec<Frame>,
+    current: Frame,
+    /// The `with` bodies being run, innermost last
+    guards: Vec<Guard>,
+}
+
+/// A `with` whose body is running
+struct Guard {
+    resource: Object,
+    exit: u64,
+    /// The variable the resource is bound to
+    target: Target,
+}
+
+impl Machine {
+    fn local(&mut self, slot: u32) -> &mut Local {
+        &mut self.locals[self.current.base + slot as usize]
+    }
+
+    fn reference_mut(&mut self, slot: u32) -> &mut Reference {
+        self.current
+            .references
+            .get_mut(slot as usize)
+            .and_then(Option::as_mut)
+            .expect("references are parameters")
+    }
+
+    fn target_mut<'a>(
+        &'a mut self,
+        globals: &'a mut Globals,
+        target: Target,
+    ) -> Option<&'a mut (Object, bool)> {
+        match target {
+            Target::Global(slot) => globals.at(slot).as_mut(),
+            Target::Local(index) => self.locals[index].as_mut(),
+        }
+    }
+
+    /// The value of a local, reading through references
+    fn local_value(&mut self, globals: &mut Globals, slot: u32) -> Option<Object> {
+        match self
+            .current
+            .references
+            .get(slot as usize)
+            .copied()
+            .flatten()
+        {
+            Some(reference) => self.target_mut(globals, reference.target).map(|var| var.0),
+            None => self.local(slot).map(|var| var.0),
+        }
+    }
+
+    /// Binds `param` to the variable the argument at `place` reads
+    fn reference(
+        &mut self,
+        globals: &mut Globals,
+        place: Option<Place>,
+        param: &Parameter,
+    ) -> RuntimeResult<Reference> {
+        let Some(place) = place else {
+            return Err(RuntimeError::new(
+                "Only variables can be passed to ref and out parameters!",
+            ));
+        };
+        let target = match place {
+            Place::Global(global) => Target::Global(global),
+            Place::Local { slot, global } => match self.local(slot) {
+                Some(_) => Target::Local(self.current.base + slot as usize),
+                None => Target::Global(global),
+            },
+            // Passing a reference on binds to the caller's variable
+            Place::Reference(slot) => {
+                let outer = *self.reference_mut(slot);
+                if param.mutable && !outer.mutable {
+                    return Err(RuntimeError::new("Variable is immutable!"));
+                }
+                outer.target
+            }
+        };
+        match self.target_mut(globals, target) {
+            Some((_, false)) if param.mutable => Err(RuntimeError::new("Variable is immutable!")),
+            Some(_) => Ok(Reference {
+                target,
+                mutable: param.mutable,
+                output: param.mode == ParamType::Output,
+                assigned: false,
+            }),
+            // Functions and natives can only be passed by value
+            None => Err(RuntimeError::new("Variable doesn't exist!")),
+        }
+    }
+
+    /// Moves the arguments from `args` up into a new frame for `function`
+    fn enter(
+        &mut self,
+        globals: &mut Globals,
+        function: Rc<Function>,
+        args: usize,
+        captures: &[(u32, Option<Object>)],
+        site: &CallSite,
+    ) -> RuntimeResult<()> {
+        let arguments = self.stack.len() - args;
+        if function.parameters.len() != arguments {
+            return Err(RuntimeError::new(format!(
+                "Expected {} argument(s), found {arguments}!",
+                function.parameters.len()
+            )));
+        }
+        // `ref` and `out` parameters get the argument's variable on top of its value
+        let mut references = vec![];
+        if function
+            .parameters
+            .iter()
+            .any(|param| matches!(param.mode, ParamType::Reference | ParamType::Output))
+        {
+            for (param, place) in function.parameters.iter().zip(&site.places) {
+                let reference = match param.mode {
+                    ParamType::Reference | ParamType::Output => {
+                        Some(self.reference(globals, *place, param)?)
+                    }
+                    _ => None,
+                };
+                references.push(reference);
+            }
+        }
+        let base = self.locals.len();
+        let caller = std::mem::replace(
+            &mut self.current,
+            Frame {
+                function,
+                ip: 0,
+                base,
+                references,
+                memo: None,
+                defers: vec![],
+                resume: 0,
+            },
+        );
+        self.frames.push(caller);
+        let parameters = &self.current.function.parameters;
+        self.locals.extend(
+            self.stack
+                .drain(args..)
+                .zip(parameters)
+                .map(|(value, param)| Some((value, param.mutable))),
+        );
+        self.locals.resize(base + self.current.function.slots, None);
+        // Lambdas get their own copy of what they captured, which they can't assign to
+        for (slot, value) in captures {
+            *self.local(*slot) = value.map(|value| (value, false));
+        }
+        Ok(())
+    }
+}
+
+/// Runs compiled scripts against an interpreter's globals, natives and I/O,
+/// functions scripts declare stay around for later runs
+#[derive(Debug, Clone, Default)]
+pub struct Vm {
+    functions: HashMap<u64, Rc<Function>>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The functions runs have declared so far
+    pub fn declared(&self) -> impl Iterator<Item = u64> + '_ {
+        self.functions.keys().copied()
+    }
+
+    /// Runs `script` and returns what it returned
+    pub fn run(
+        &mut self,
+        interperter: &mut Interperter,
+        script: Rc<Function>,
+    ) -> RuntimeResult<Object> {
+        let mut m = Machine {
+            stack: vec![],
+            locals: vec![],
+            frames: vec![],
+            current: Frame {
+                function: script,
+                ip: 0,
+                base: 0,
+                references: vec![],
+                memo: None,
+                defers: vec![],
+                resume: 0,
+            },
+            guards: vec![],
+        };
+        let result = self.execute(interperter, &mut m);
+        if result.is_err() {
+            // The `with` bodies the error leaves still get their exit hooks, innermost first
+            while let Some(guard) = m.guards.pop() {
+                let _ = self.exit(interperter, &mut m, guard);
+            }
+        }
+        result
+    }
+
+    fn execute(&mut self, interperter: &mut Interperter, m: &mut Machine) -> RuntimeResult<Object> {
+        loop {
+            let instr = m.current.function.chunk.code[m.current.ip];
+            m.current.ip += 1;
+            match instr {
+                Instr::Constant(index) => {
+                    let value = m.current.function.chunk.constants[index as usize];
+                    m.stack.push(value)
+                }
+                Instr::Null => m.stack.push(Object::NullValue),
+                Instr::Pop => {
+                    m.stack.pop();
+                }
+                Instr::Unary(op) => {
+                    let value = pop(&mut m.stack);
+                    m.stack.push(unary_op(op, value)?);
+                }
+                Instr::Binary(op) => {
+                    let rhs = pop(&mut m.stack);
+                    let lhs = pop(&mut m.stack);
+                    m.stack.push(binary_op(op, lhs, rhs)?);
+                }
+                Instr::LoadGlobal(slot) => {
+                    let value = self.global(interperter, slot)?;
+                    m.stack.push(value)
+                }
+                Instr::CheckGlobal(slot) => {
+                    if interperter.globals_mut().at(slot).is_some() {
+                        return Err(RuntimeError::new("Variable already declared!"));
+                    }
+                }
+                Instr::DefineGlobal { slot, mutable } => {
+                    let value = pop(&mut m.stack);
+                    *interperter.globals_mut().at(slot) = Some((value, mutable));
+                }
+                Instr::AssignGlobal { slot, op } => {
+                    let value = pop(&mut m.stack);
+                    assign(interperter.globals_mut().at(slot).as_mut(), op, value)?;
+                }
+                Instr::LoadLocal { slot, global } => match *m.local(slot) {
+                    Some((value, _)) => m.stack.push(value),
+                    None => {
+                        let value = self.global(interperter, global)?;
+                        m.stack.push(value)
+                    }
+                },
+                Instr::CheckLocal(slot) => {
+                    if m.local(slot).is_some() {
+                        return Err(RuntimeError::new("Variable already declared!"));
+                    }
+                }
+                Instr::DefineLocal { slot, mutable } => {
+                    let value = pop(&mut m.stack);
+                    *m.local(slot) = Some((value, mutable));
+                }
+                Instr::AssignLocal { slot, global, op } => {
+                    let value = pop(&mut m.stack);
+                    let var = match m.local(slot) {
+                        Some(var) => Some(var),
+                        None => interperter.globals_mut().at(global).as_mut(),
+                    };
+                    assign(var, op, value)?;
+                }
+                Instr::LoadReference(slot) => {
+                    let reference = *m.reference_mut(slot);
+                    reference.check_read()?;
+                    let var = m.target_mut(interperter.globals_mut(), reference.target);
+                    let value = var.expect("references point at variables").0;
+                    m.stack.push(value);
+                }
+                Instr::AssignReference { slot, op } => {
+                    let value = pop(&mut m.stack);
+                    let reference = m.reference_mut(slot);
+                    if !reference.mutable {
+                        return Err(RuntimeError::new("Variable is immutable!"));
+                    }
+                    if op != AssignOp::Set {
+                        reference.check_read()?;
+                    }
+                    reference.assigned = true;
+                    let target = reference.target;
+                    assign(m.target_mut(interperter.globals_mut(), target), op, value)?;
+                }
+                Instr::Jump(target) => m.current.ip = target as usize,
+                Instr::JumpIfFalse(target) => {
+                    if !Into::<bool>::into(pop(&mut m.stack)) {
+                        m.current.ip = target as usize;
+                    }
+                }
+                Instr::JumpIfTrue(target) => {
+                    if pop(&mut m.stack).into() {
+                        m.current.ip = target as usize;
+                    }
+                }
+                Instr::JumpIfNull(target) => {
+                    if m.stack.last() == Some(&Object::NullValue) {
+                        m.current.ip = target as usize;
+                    }
+                }
+                Instr::JumpIfNotNull(target) => {
+                    if m.stack.last() != Some(&Object::NullValue) {
+                        m.current.ip = target as usize;
+                    }
+                }
+                Instr::ExpectBool(condition) => {
+                    if !matches!(m.stack.last(), Some(Object::Boolean(_))) {
+                        return Err(RuntimeError::new(condition.message()));
+                    }
+                }
+                Instr::Match { constant, target } => {
+                    if m.stack.last()
+                        != Some(&m.current.function.chunk.constants[constant as usize])
+                    {
+                        m.current.ip = target as usize;
+                    }
+                }
+                Instr::Call {
+                    global,
+                    local,
+                    site,
+                } => {
+                    let function = m.current.function.clone();
+                    let site = &function.chunk.calls[site as usize];
+                    let args = m.stack.len() - site.arguments as usize;
+                    // Variables holding a function come first, then declared functions and natives
+                    let variable = match local.and_then(|slot| *m.local(slot)) {
+                        Some(var) => Some(var),
+                        None => *interperter.globals_mut().at(global),
+                    };
+                    let callable = match variable {
+                        Some((value, _)) => interperter.callable(value)?,
+                        None => Callable::Named(interperter.globals_mut().name(global)),
+                    };
+                    self.call(interperter, m, callable, args, site)?;
+                }
+                Instr::CallValue(site) => {
+                    let function = m.current.function.clone();
+                    let site = &function.chunk.calls[site as usize];
+                    let args = m.stack.len() - site.arguments as usize;
+                    let callee = m.stack.remove(args - 1);
+                    let callable = interperter.callable(callee)?;
+                    self.call(interperter, m, callable, args - 1, site)?;
+                }
+                Instr::Function(index) => {
+                    let declared = m.current.function.chunk.functions[index as usize].clone();
+                    interperter.forget(declared.name);
+                    interperter.set_resource(declared.name, declared.hooks);
+                    self.functions.insert(declared.name, declared);
+                }
+                Instr::Extern(index) => {
+                    let declaration = &m.current.function.chunk.externs[index as usize];
+                    interperter.define_extern(declaration)?;
+                }
+                Instr::Interpolate {
+                    template,
+                    arguments,
+                } => {
+                    let Object::String(template) =
+                        m.current.function.chunk.constants[template as usize]
+                    else {
+                        unreachable!("templates are string constants")
+                    };
+                    let start = m.stack.len() - arguments as usize;
+                    let value = interpolate(template, &m.stack[start..])?;
+                    m.stack.truncate(start);
+                    m.stack.push(value);
+                }
+                Instr::Closure(index) => {
+                    let function = m.current.function.chunk.functions[index as usize].clone();
+                    let captures = function
+                        .captures
+                        .iter()
+                        .map(|(outer, slot)| {
+                            (*slot, m.local_value(interperter.globals_mut(), *outer))
+                        })
+                        .collect();
+                    let value =
+                        interperter.function_value(Callable::Compiled { function, captures });
+                    m.stack.push(value);
+                }
+                Instr::Enter {
+                    producer,
+                    local,
+                    global,
+                } => {
+                    let resource = pop(&mut m.stack);
+                    let hooks = interperter.resource(producer)?;
+                    if let Some(enter) = hooks.enter {
+                        self.call_hook(interperter, enter, resource)?;
+                    }
+                    let target = match local {
+                        Some(slot) => {
+                            *m.local(slot) = Some((resource, false));
+                            Target::Local(m.current.base + slot as usize)
+                        }
+                        None => {
+                            *interperter.globals_mut().at(global) = Some((resource, false));
+                            Target::Global(global)
+                        }
+                    };
+                    m.guards.push(Guard {
+                        resource,
+                        exit: hooks.exit,
+                        target,
+                    });
+                }
+                Instr::Exit => {
+                    let guard = m.guards.pop().expect("`with` bodies are balanced");
+                    self.exit(interperter, m, guard)?;
+                }
+                Instr::Test => {
+                    let value = pop(&mut m.stack);
+                    interperter.write_output(&format!("Test Output:{value}\n"))?;
+                }
+                Instr::Error => return Err(RuntimeError::new("Error statment!")),
+                Instr::Defer(skip) => {
+                    m.current.defers.push(m.current.ip);
+                    m.current.ip = skip as usize;
+                }
+                Instr::EndDefer => m.current.ip = m.current.resume,
+                Instr::Return => {
+                    // The return value stays on the stack while the deferred statements run
+                    if let Some(start) = m.current.defers.pop() {
+                        m.current.resume = m.current.ip - 1;
+                        m.current.ip = start;
+                        continue;
+                    }
+                    let value = pop(&mut m.stack);
+                    if m.current
+                        .references
+                        .iter()
+                        .flatten()
+                        .any(|reference| reference.output && !reference.assigned)
+                    {
+                        return Err(RuntimeError::new("Out parameter wasn't assigned!"));
+                    }
+                    if let Some(args) = m.current.memo.take() {
+                        interperter.memoize(m.current.function.name, args, value);
+                    }
+                    let Some(caller) = m.frames.pop() else {
+                        return Ok(value);
+                    };
+                    m.locals.truncate(m.current.base);
+                    m.current = caller;
+                    m.stack.push(value);
+                }
+            }
+        }
+    }
+
+    /// Calls `callable` with the arguments from `args` up, script functions get a frame
+    /// while natives run right away
+    fn call(
+        &self,
+        interperter: &mut Interperter,
+        m: &mut Machine,
+        callable: Callable,
+        args: usize,
+        site: &CallSite,
+    ) -> RuntimeResult<()> {
+        match callable {
+            Callable::Named(name) => {
+                if let Some(function) = self.functions.get(&name).cloned() {
+                    if !function.memoize {
+                        return m.enter(interperter.globals_mut(), function, args, &[], site);
+                    }
+                    if let Some(value) = interperter.memoized(name, &m.stack[args..]) {
+                        m.stack.truncate(args);
+                        m.stack.push(value);
+                        return Ok(());
+                    }
+                    let memo = m.stack[args..].to_vec();
+                    m.enter(interperter.globals_mut(), function, args, &[], site)?;
+                    m.current.memo = Some(memo);
+                    return Ok(());
+                }
+                let Some(native) = interperter.native(name) else {
+                    return Err(RuntimeError::new("Function doesn't exist!"));
+                };
+                let args = m.stack.split_off(args);
+                m.stack.push(native.call(interperter, &args)?);
+            }
+            Callable::Compiled { function, captures } => {
+                m.enter(interperter.globals_mut(), function, args, &captures, site)?
+            }
+            Callable::Lambda { .. } => {
+                return Err(RuntimeError::new(
+                    "Functions declared by the tree-walker can't be called by the vm!",
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Undeclares the variable of a `with`, so it can be bound again, and calls its exit hook
+    fn exit(
+        &mut self,
+        interperter: &mut Interperter,
+        m: &mut Machine,
+        guard: Guard,
+    ) -> RuntimeResult<()> {
+        match guard.target {
+            Target::Global(slot) => *interperter.globals_mut().at(slot) = None,
+            Target::Local(index) => m.locals[index] = None,
+        }
+        self.call_hook(interperter, guard.exit, guard.resource)?;
+        Ok(())
+    }
+
+    /// Calls the function or native `name` with `resource` in a run of its own
+    fn call_hook(
+        &mut self,
+        interperter: &mut Interperter,
+        name: u64,
+        resource: Object,
+    ) -> RuntimeResult<Object> {
+        let global = interperter.globals_mut().slot(name);
+        let hook = Function {
+            name,
+            chunk: Chunk {
+                code: vec![
+                    Instr::Constant(0),
+                    Instr::Call {
+                        global,
+                        local: None,
+                        site: 0,
+                    },
+                    Instr::Return,
+                ],
+                constants: vec![resource],
+                calls: vec![CallSite {
+                    arguments: 1,
+                    places: vec![None],
+                }],
+                ..Chunk::default()
+            },
+            ..Function::default()
+        };
+        self.run(interperter, Rc::new(hook))
+    }
+
+    /// The global in `slot`, declared functions and natives can be used as values by the
+    /// name of the slot
+    fn global(&self, interperter: &mut Interperter, slot: u32) -> RuntimeResult<Object> {
+        let globals = interperter.globals_mut();
+        if let Some(var) = *globals.at(slot) {
+            return Ok(var.0);
+        }
+        let name = globals.name(slot);
+        match self.functions.contains_key(&name) || interperter.native(name).is_some() {
+            true => Ok(interperter.named_value(name)),
+            false => Err(RuntimeError::new("Variable doesn't exist!")),
+        }
+    }
+}
+
+/// The compiler keeps the stack balanced, so popping an empty one is a bug in it
+fn pop(stack: &mut Vec<Object>) -> Object {
+    stack.pop().expect("vm stack underflow")
+}
+
+fn assign(var: Option<&mut (Object, bool)>, op: AssignOp, value: Object) -> RuntimeResult<()> {
+    let Some(var) = var else {
+        return Err(RuntimeError::new("Variable doesn't exist!"));
+    };
+    if !var.1 {
+        return Err(RuntimeError::new("Variable is immutable!"));
+    }
+    var.0 = assign_op(op, var.0, value)?;
+    Ok(())
+}